@@ -1,20 +1,30 @@
-use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
+use cosmwasm_schema::{export_schema, schema_for, write_api};
 use std::env::current_dir;
-use std::fs::create_dir_all;
 
-use cw_escrow::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, CreateMsg, ReceiveMsg};
+use cw_escrow::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, SudoMsg, CreateMsg, CreateResponse, ArbitrationAck, ArbitrationPacket, EscrowHookMsg, PayoutCallbackMsg, PayoutResponse, ReceiptMintMsg, ReceiveMsg};
 use cw_escrow::state::Escrow;
 
 fn main() {
+    // the entry points with the responses of every query, as ts-codegen reads them
+    write_api! {
+        instantiate: InstantiateMsg,
+        execute: ExecuteMsg,
+        query: QueryMsg,
+        migrate: MigrateMsg,
+        sudo: SudoMsg,
+    }
+
+    // messages exchanged with other contracts rather than sent to an entry point
     let mut out_dir = current_dir().unwrap();
     out_dir.push("schema");
-    create_dir_all(&out_dir).unwrap();
-    remove_schemas(&out_dir).unwrap();
-
-    export_schema(&schema_for!(InstantiateMsg), &out_dir);
-    export_schema(&schema_for!(ExecuteMsg), &out_dir);
-    export_schema(&schema_for!(QueryMsg), &out_dir);
     export_schema(&schema_for!(CreateMsg), &out_dir);
     export_schema(&schema_for!(ReceiveMsg), &out_dir);
+    export_schema(&schema_for!(CreateResponse), &out_dir);
+    export_schema(&schema_for!(PayoutResponse), &out_dir);
+    export_schema(&schema_for!(PayoutCallbackMsg), &out_dir);
+    export_schema(&schema_for!(EscrowHookMsg), &out_dir);
+    export_schema(&schema_for!(ReceiptMintMsg), &out_dir);
+    export_schema(&schema_for!(ArbitrationPacket), &out_dir);
+    export_schema(&schema_for!(ArbitrationAck), &out_dir);
     export_schema(&schema_for!(Escrow), &out_dir);
 }
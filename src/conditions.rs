@@ -0,0 +1,145 @@
+use cosmwasm_std::{
+    to_vec, Api, Binary, ContractResult, Deps, Empty, Env, QueryRequest, StdError, SystemResult,
+    WasmQuery,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+use crate::error::ContractError;
+
+/// Has to hold before the recipient can release an escrow with `Fulfill`.
+/// New kinds of conditions only need a variant here and a case in `Condition::is_met`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// Met from this block height on
+    Height(u64),
+    /// Met from this block time on, in seconds since epoch
+    Time(u64),
+    /// Met by revealing the preimage of this hex encoded sha256 hash
+    Preimage(String),
+    /// Met once the smart query `msg` to `contract` answers exactly (byte for byte) `expected`
+    OracleQuery {
+        contract: String,
+        msg: Binary,
+        expected: Binary,
+    },
+    /// Met once `contract` answers `ApprovalQuery::Approved` for this escrow with true
+    ExternalContractApproval { contract: String },
+}
+
+/// The query contracts used in `Condition::ExternalContractApproval` have to answer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalQuery {
+    Approved { escrow_id: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ApprovalResponse {
+    pub approved: bool,
+}
+
+/// What the caller submitted to meet the conditions
+#[derive(Clone, Debug, Default)]
+pub struct Witness {
+    /// hex encoded
+    pub preimage: Option<String>,
+}
+
+impl Condition {
+    /// Checks the addresses and normalizes hashes, called once at creation
+    pub fn validate(self, api: &dyn Api) -> Result<Self, ContractError> {
+        Ok(match self {
+            Condition::Preimage(hash) => {
+                if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(ContractError::InvalidHashLock {});
+                }
+                Condition::Preimage(hash.to_ascii_lowercase())
+            }
+            Condition::OracleQuery { contract, msg, expected } => Condition::OracleQuery {
+                contract: api.addr_validate(&contract)?.into_string(),
+                msg,
+                expected,
+            },
+            Condition::ExternalContractApproval { contract } => {
+                Condition::ExternalContractApproval {
+                    contract: api.addr_validate(&contract)?.into_string(),
+                }
+            }
+            condition => condition,
+        })
+    }
+
+    fn is_met(&self, deps: Deps, env: &Env, id: &str, witness: &Witness) -> Result<bool, ContractError> {
+        match self {
+            Condition::Height(height) => Ok(env.block.height >= *height),
+            Condition::Time(time) => Ok(env.block.time.seconds() >= *time),
+            Condition::Preimage(hash) => match &witness.preimage {
+                None => Ok(false),
+                Some(preimage) => {
+                    let bin = hex::decode(preimage).map_err(|_| ContractError::InvalidPreimage {})?;
+                    if hex::encode(Sha256::digest(&bin)) != *hash {
+                        return Err(ContractError::InvalidPreimage {});
+                    }
+                    Ok(true)
+                }
+            },
+            Condition::OracleQuery { contract, msg, expected } => {
+                let request: QueryRequest<Empty> = WasmQuery::Smart {
+                    contract_addr: contract.clone(),
+                    msg: msg.clone(),
+                }
+                .into();
+                match deps.querier.raw_query(&to_vec(&request)?) {
+                    SystemResult::Ok(ContractResult::Ok(answer)) => Ok(answer == *expected),
+                    SystemResult::Ok(ContractResult::Err(err)) => {
+                        Err(StdError::generic_err(format!("Oracle query failed: {}", err)).into())
+                    }
+                    SystemResult::Err(err) => {
+                        Err(StdError::generic_err(format!("Oracle query failed: {}", err)).into())
+                    }
+                }
+            }
+            Condition::ExternalContractApproval { contract } => {
+                let query = ApprovalQuery::Approved {
+                    escrow_id: id.to_string(),
+                };
+                let res: ApprovalResponse = deps.querier.query_wasm_smart(contract, &query)?;
+                Ok(res.approved)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Condition::Height(height) => write!(f, "height {}", height),
+            Condition::Time(time) => write!(f, "time {}", time),
+            Condition::Preimage(hash) => write!(f, "preimage of {}", hash),
+            Condition::OracleQuery { contract, .. } => write!(f, "oracle query to {}", contract),
+            Condition::ExternalContractApproval { contract } => write!(f, "approval by {}", contract),
+        }
+    }
+}
+
+/// Fails on the first condition of escrow `id` that is not met
+pub fn check_conditions(
+    deps: Deps,
+    env: &Env,
+    id: &str,
+    conditions: &[Condition],
+    witness: &Witness,
+) -> Result<(), ContractError> {
+    for condition in conditions {
+        if !condition.is_met(deps, env, id, witness)? {
+            return Err(ContractError::ConditionNotMet {
+                condition: condition.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
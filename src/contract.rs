@@ -1,421 +1,9377 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    entry_point, BankMsg,  DepsMut, Env, MessageInfo, Response, StdResult, Binary, to_binary, Deps, WasmMsg, CosmosMsg, from_binary
+    Addr, BankMsg, BlockInfo, Coin, Empty, DepsMut, Event, Storage, Env, MessageInfo, Reply, Response, QuerierWrapper, IbcMsg, IbcTimeout, StdError, StdResult, SubMsg, Timestamp, Binary, to_binary, Deps, Uint128, WasmMsg, CosmosMsg, from_binary
 };
 
+use crate::conditions::{check_conditions, Condition, Witness};
 use crate::error::ContractError;
-use crate::msg::{CreateMsg, ExecuteMsg, InstantiateMsg, DetailsResponse, QueryMsg, ReceiveMsg};
-use crate::state::{ Escrow, escrows_read, escrows_update, escrows_remove, escrows_save, GenericBalance };
-use cw20::{ Balance, Cw20ReceiveMsg, Cw20Coin, Cw20CoinVerified, Cw20ExecuteMsg };
-use cw2::set_contract_version;
+use crate::ibc::{transfer_denom, transfer_with_memo, MSG_TRANSFER_TYPE_URL};
+use crate::msg::{OwnershipAction, PayoutCallbackMsg, ReceiptMintMsg, ClaimableInfo, ClaimableResponse, CreateFromTemplateMsg, FeeExemptResponse, OpenEscrowsResponse, TemplateMsg, TemplateResponse, TemplatesResponse, ResolveIdResponse, ArbitrationAck, ArbitrationPacket, EscrowChange, EscrowHookMsg, SubscribersResponse, HistoryEntry, HistoryResponse, SudoMsg, CountResponse, StatsResponse, ArbiterInfo, Arbiters, ArbitersResponse, ArbiterStatsResponse, ConfigResponse, CreateMsg, CreateResponse, PayoutResponse, ExecuteMsg, ContributionInfo, ContributionsResponse, FundingResponse, IsExpiredResponse, InstantiateMsg, DetailsResponse, ListResponse, MigrateMsg, QueryMsg, ReceiveMsg, TokenAmounts, UpdateConfigMsg, VoteInfo, VotesResponse};
+use crate::state::{ add_claimable, remove_claimable, escrow_claimable, CLAIMABLE, CLAIMABLE_SINCE, count_escrows, escrows_by_deadline, Deadline, escrow_ids_by_status_and_arbiter, escrow_ids_by_token, expired_escrow_ids, namespaced_id, open_escrows_of, transfer_source, FEE_EXEMPT, OPEN_LIMIT_EXEMPT, ARBITER_CHANNELS, RemoteAddress, escrow_subscribers, SUBSCRIBERS, record_action, escrow_history, add_topped_up, TOPPED_UP, total_locked, tracked_funds, ESCROW_COUNTS, Ownership, OWNERSHIP, migrate_ownership, ArbiterProfile, ARBITER_REGISTRY, ARBITER_STATS, record_decision, record_overturned, registered_arbiters, registered_templates, Template, TEMPLATES, Config, Schedule, Swap, Decision, Dispute, Escrow, PendingDeposit, PAYOUT_TOKENS, PENDING_DEPOSIT, OnExpiry, Status, Verdict, VOTES, escrow_votes, escrow_contributions, CONTRIBUTIONS, add_contribution, escrow_ids_by_status, BPS_DENOMINATOR, CONFIG, TOKEN_ALLOWLIST, all_escrow_ids, allowed_tokens, is_token_allowed, escrow_ids_by_arbiter, escrows_read, escrows_read_without_balance, escrow_balance_add, escrow_balance_read, escrows_update, escrows_save, count_archived_escrows, migrate_escrows, ambiguous_legacy_deadlines, archive_unindexed, MigrationProgress, MIGRATION, EXPIRY_CURSOR, archive_escrow, archived_escrow_ids, archived_escrows_may_load, GenericBalance };
+use cw20::{ Balance, BalanceResponse, Expiration, Cw20ReceiveMsg, Cw20Coin, Cw20CoinVerified, Cw20ExecuteMsg, Cw20QueryMsg };
+use cw721::{Cw721ExecuteMsg, Cw721QueryMsg, Cw721ReceiveMsg, OwnerOfResponse};
+use cw2::{get_contract_version, set_contract_version};
+use cw_utils::Duration;
+use semver::Version;
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:cw20-escrow";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+// the first version keeping the escrow indexes and stats
+const INDEXED_SINCE: &str = "0.11.0";
 
-#[entry_point]
+// settings for pagination
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+const DEFAULT_MAX_TOKENS: u32 = 10;
+// escrow ids, they are part of storage keys, events and URLs
+const MIN_ID_LENGTH: usize = 1;
+const MAX_ID_LENGTH: usize = 64;
+// a week
+const DEFAULT_APPEAL_WINDOW: u64 = 7 * 24 * 60 * 60;
+
+// reply ids
+const DEPOSIT_REPLY_ID: u64 = 1;
+// failing subscriber notifications are ignored
+const HOOK_REPLY_ID: u64 = 2;
+// packets of remote arbiters that could not be carried out
+pub(crate) const ARBITRATION_REPLY_ID: u64 = 3;
+// payouts count up from here
+const PAYOUT_REPLY_ID: u64 = 100;
+// per escrow
+const MAX_SUBSCRIBERS: u32 = 5;
+const MAX_SPLITS: usize = 10;
+// escrows settled and expired escrows looked at by one ProcessExpirations or Crank
+const MAX_SETTLE_LIMIT: u32 = 30;
+const MAX_EXPIRY_SCAN: usize = 100;
+// ICS-20 transfers to remote recipients, a day
+const IBC_TRANSFER_TIMEOUT: u64 = 24 * 60 * 60;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
-    _msg: InstantiateMsg,
+    info: MessageInfo,
+    msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
+    let admin = match msg.admin {
+        Some(admin) => deps.api.addr_validate(&admin)?,
+        None => info.sender,
+    };
+    let fee_collector = match msg.fee_collector {
+        Some(collector) => deps.api.addr_validate(&collector)?,
+        None => admin.clone(),
+    };
+    let ownership = Ownership {
+        owner: Some(admin.clone()),
+        ..Ownership::default()
+    };
+    OWNERSHIP.save(deps.storage, &ownership)?;
+    let config = Config {
+        max_tokens: msg.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        fee_bps: validate_bps(msg.fee_bps.unwrap_or_default())?,
+        fee_collector,
+        appellate_arbiter: msg.appellate_arbiter.map(|a| deps.api.addr_validate(&a)).transpose()?,
+        appeal_window: msg.appeal_window.unwrap_or(DEFAULT_APPEAL_WINDOW),
+        paused: false,
+        releases_paused: false,
+        keeper_reward: vec![],
+        unclaimed_timeout: None,
+        arbiter_relayers: vec![],
+        max_duration: None,
+        max_open_per_source: None,
+        creation_fee: msg.creation_fee.filter(|fee| !fee.amount.is_zero()),
+        strict_parties: msg.strict_parties.unwrap_or_default(),
+        receipt_nft: msg.receipt_nft.map(|nft| deps.api.addr_validate(&nft)).transpose()?,
+    };
+    CONFIG.save(deps.storage, &config)?;
+
+    for token in msg.token_allowlist.unwrap_or_default() {
+        let token = deps.api.addr_validate(&token)?;
+        TOKEN_ALLOWLIST.save(deps.storage, &token, &Empty {})?;
+    }
+
     Ok(Response::default())
 }
 
-#[entry_point]
+#[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
-    
+    assert_not_migrating(deps.storage)?;
     // let state = config_read(deps.storage).load()?;
     match msg {
-        ExecuteMsg::Create(msg) => try_create(deps, msg, Balance::from(info.funds), info.sender.to_string()),  // create an escrow with coins
+        ExecuteMsg::Create(msg) => try_create_with_coins(deps, env, info, ReceiveMsg::Create(msg)),
+        ExecuteMsg::CreateFromTemplate(msg) => {
+            try_create_with_coins(deps, env, info, ReceiveMsg::CreateFromTemplate(msg))
+        }
         ExecuteMsg::Approve { id} => try_approve(deps, env, info, id),
-        ExecuteMsg::Refund { id } => try_refund(deps, info, id),
-        ExecuteMsg::TopUp { id } => try_top_up(deps, Balance::from(info.funds), id),
-        ExecuteMsg::Receive(msg) => try_receive(deps, info, msg),
+        ExecuteMsg::ApproveMany { ids } => try_approve_many(deps, env, info, ids),
+        ExecuteMsg::ApprovePartial { id, amounts } => {
+            let amounts = validate_amounts(deps.as_ref(), amounts)?;
+            try_approve_partial(deps, env, info, id, amounts)
+        }
+        ExecuteMsg::ApproveTokens { id, tokens } => {
+            let amounts = tokens_held(deps.storage, &id, &tokens)?;
+            try_approve_partial(deps, env, info, id, amounts)
+        }
+        ExecuteMsg::Refund { id } => try_refund(deps, env, info, id),
+        ExecuteMsg::RefundMany { ids } => try_refund_many(deps, env, info, ids),
+        ExecuteMsg::RefundPartial { id, amounts } => {
+            let amounts = validate_amounts(deps.as_ref(), amounts)?;
+            try_refund_partial(deps, env, info, id, amounts)
+        }
+        ExecuteMsg::RefundTokens { id, tokens } => {
+            let amounts = tokens_held(deps.storage, &id, &tokens)?;
+            try_refund_partial(deps, env, info, id, amounts)
+        }
+        ExecuteMsg::Resolve { id, recipient_bps } => try_resolve(deps, env, info, id, recipient_bps),
+        ExecuteMsg::TopUp { id } => try_top_up(deps, env, Balance::from(info.funds).into(), &info.sender, id),
+        ExecuteMsg::CompleteSwap { id } => try_complete_swap(deps, env, info, id),
+        ExecuteMsg::WithdrawSwapDeposit { id } => try_withdraw_swap_deposit(deps, env, info, id),
+        ExecuteMsg::SettleExpired { id } => try_settle_expired(deps, env, id),
+        ExecuteMsg::Accept { id } => try_accept(deps, env, info, id),
+        ExecuteMsg::Cancel { id } => try_cancel(deps, env, info, id),
+        ExecuteMsg::SetRecipient { id, new_recipient } => {
+            try_set_recipient(deps, env, info, id, new_recipient)
+        }
+        ExecuteMsg::TransferSourceRights { id, new_source } => {
+            try_transfer_source_rights(deps, env, info, id, new_source)
+        }
+        ExecuteMsg::ExtendExpiry { id, expires } => {
+            try_extend_expiry(deps, env, info, id, expires)
+        }
+        ExecuteMsg::Ping { id } => try_ping(deps, env, info, id),
+        ExecuteMsg::Subscribe { id, subscriber } => try_subscribe(deps, info, id, subscriber),
+        ExecuteMsg::Unsubscribe { id, subscriber } => try_unsubscribe(deps, info, id, subscriber),
+        ExecuteMsg::ClaimRefund { id } => try_claim_refund(deps, env, info, id),
+        ExecuteMsg::ClaimWithPreimage { id, preimage } => {
+            try_claim_with_preimage(deps, env, info, id, preimage)
+        }
+        ExecuteMsg::Fulfill { id, preimage } => {
+            try_fulfill(deps, env, info, id, Witness { preimage })
+        }
+        ExecuteMsg::Claim { id } => try_claim(deps, env, info, id),
+        ExecuteMsg::StopSchedule { id } => try_stop_schedule(deps, env, info, id),
+        ExecuteMsg::RaiseDispute { id, reason } => try_raise_dispute(deps, env, info, id, reason),
+        ExecuteMsg::Appeal { id } => try_appeal(deps, env, info, id),
+        ExecuteMsg::DecideAppeal { id, recipient_bps } => try_decide_appeal(deps, env, info, id, recipient_bps),
+        ExecuteMsg::FinalizeDecision { id } => try_finalize_decision(deps, env, id),
+        ExecuteMsg::MutualApprove { id } => try_mutual_approve(deps, env, info, id),
+        ExecuteMsg::CastVote { id, verdict } => try_cast_vote(deps, env, info, id, verdict),
+        ExecuteMsg::Receive(msg) => try_receive(deps, env, info, msg),
+        ExecuteMsg::ReceiveNft(msg) => try_receive_nft(deps, env, info, msg),
+        ExecuteMsg::DepositCw20 { token, amount, msg } => {
+            try_deposit_cw20(deps, env, info, token, amount, msg)
+        }
+        ExecuteMsg::CreateFromAllowance { msg, token, amount } => {
+            try_deposit_cw20(deps, env, info, token, amount, ReceiveMsg::Create(msg))
+        }
+        ExecuteMsg::RegisterArbiter { fee_bps, categories } => {
+            try_register_arbiter(deps, info, fee_bps, categories)
+        }
+        ExecuteMsg::UnregisterArbiter {} => try_unregister_arbiter(deps, info),
+        ExecuteMsg::RegisterTemplate { id, template } => try_register_template(deps, info, id, template),
+        ExecuteMsg::RemoveTemplate { id } => try_remove_template(deps, info, id),
+        ExecuteMsg::Crank { limit } => try_crank(deps, env, info, limit),
+        ExecuteMsg::SweepUnclaimed { id, to } => try_sweep_unclaimed(deps, env, info, id, to),
+        ExecuteMsg::RescueFunds { denom_or_token, amount, to } => {
+            try_rescue_funds(deps, env, info, denom_or_token, amount, to)
+        }
+        ExecuteMsg::Pause { block_releases } => try_pause(deps, info, block_releases.unwrap_or_default()),
+        ExecuteMsg::Unpause {} => try_unpause(deps, info),
+        ExecuteMsg::UpdateTokenAllowlist { add, remove } => {
+            try_update_token_allowlist(deps, info, add, remove)
+        }
+        ExecuteMsg::UpdateFeeExempt { add, remove } => try_update_fee_exempt(deps, info, add, remove),
+        ExecuteMsg::UpdateOpenLimitExempt { add, remove } => {
+            try_update_open_limit_exempt(deps, info, add, remove)
+        }
+        ExecuteMsg::UpdateConfig(msg) => try_update_config(deps, info, msg),
+        ExecuteMsg::BindArbiterChannel { channel_id, arbiter } => {
+            try_bind_arbiter_channel(deps, info, channel_id, arbiter)
+        }
+        ExecuteMsg::IbcArbitrate { channel_id, packet } => try_ibc_arbitrate(deps, env, info, channel_id, packet),
+        ExecuteMsg::UpdateOwnership(action) => try_update_ownership(deps, env, info, action),
+    }
+}
+
+/// Privileged operations for chains governing the contract through proposals
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    assert_not_migrating(deps.storage)?;
+    match msg {
+        SudoMsg::ForceRefund { id } => {
+            let escrow = load_unsettled(deps.storage, &id)?;
+            refund_escrow(deps, &env, escrow, &id, None)
+        }
+        SudoMsg::UpdateConfig(msg) => update_config(deps, msg),
+        SudoMsg::Pause { block_releases } => pause(deps.storage, block_releases.unwrap_or_default()),
+        SudoMsg::Unpause {} => unpause(deps.storage),
+        SudoMsg::ProcessExpirations { limit } => process_expirations(deps, env, limit),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        DEPOSIT_REPLY_ID => credit_deposit(deps, env),
+        HOOK_REPLY_ID => Ok(Response::new().add_attribute("subscriber_failed", msg.result.unwrap_err())),
+        ARBITRATION_REPLY_ID => Ok(Response::new().set_data(ack_fail(msg.result.unwrap_err())?)),
+        id if id >= PAYOUT_REPLY_ID => payout_failed(deps.as_ref(), id - PAYOUT_REPLY_ID),
+        id => Err(StdError::generic_err(format!("Unknown reply id {}", id)).into()),
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(
     deps: Deps,
-    _env: Env,
+    env: Env,
     msg: QueryMsg,
 ) -> StdResult<Binary> {
     match msg {
-        QueryMsg::Details { id } => to_binary(&query_details(deps, id)?),
-        // QueryMsg::List {} => to_binary(&query_list(deps)?),
+        QueryMsg::List { start_after, limit, status, arbiter } => {
+            to_binary(&query_list(deps, start_after, limit, status, arbiter)?)
+        }
+        QueryMsg::ListArchived { start_after, limit } => {
+            to_binary(&query_list_archived(deps, start_after, limit)?)
+        }
+        QueryMsg::ListByArbiter { arbiter, start_after, limit } => {
+            to_binary(&query_list_by_arbiter(deps, arbiter, start_after, limit)?)
+        }
+        QueryMsg::ListExpired { as_of_height, as_of_time, start_after, limit } => {
+            to_binary(&query_list_expired(deps, env, as_of_height, as_of_time, start_after, limit)?)
+        }
+        QueryMsg::ListByToken { denom_or_cw20, start_after, limit } => {
+            to_binary(&query_list_by_token(deps, denom_or_cw20, start_after, limit)?)
+        }
+        QueryMsg::ListExpiringBetween { from, to, start_after, limit } => {
+            to_binary(&query_list_expiring_between(deps, from, to, start_after, limit)?)
+        }
+        QueryMsg::Count { status, arbiter } => to_binary(&query_count(deps, status, arbiter)?),
+        QueryMsg::Details { id } => to_binary(&query_details(deps, env, id)?),
+        QueryMsg::ResolveId { creator, id } => to_binary(&query_resolve_id(deps, creator, id)?),
+        QueryMsg::Votes { id } => to_binary(&query_votes(deps, id)?),
+        QueryMsg::Contributions { id } => to_binary(&query_contributions(deps, id)?),
+        QueryMsg::History { id, start_after, limit } => {
+            to_binary(&query_history(deps, id, start_after, limit)?)
+        }
+        QueryMsg::Claimable { id } => to_binary(&query_claimable(deps, id)?),
+        QueryMsg::Subscribers { id } => to_binary(&SubscribersResponse {
+            subscribers: escrow_subscribers(deps.storage, &id)?.into_iter().map(Addr::into_string).collect(),
+        }),
+        QueryMsg::IsExpired { id } => to_binary(&query_is_expired(deps, env, id)?),
+        QueryMsg::Funding { id } => to_binary(&query_funding(deps, id)?),
+        QueryMsg::Config {} => to_binary(&query_config(deps)?),
+        QueryMsg::OpenEscrows { source } => to_binary(&query_open_escrows(deps, source)?),
+        QueryMsg::FeeExempt { address } => to_binary(&FeeExemptResponse {
+            exempt: FEE_EXEMPT.has(deps.storage, &deps.api.addr_validate(&address)?),
+        }),
+        QueryMsg::Ownership {} => to_binary(&OWNERSHIP.load(deps.storage)?),
+        QueryMsg::Stats {} => to_binary(&query_stats(deps)?),
+        QueryMsg::Arbiter { address } => to_binary(&query_arbiter(deps, address)?),
+        QueryMsg::ArbiterStats { arbiter } => to_binary(&query_arbiter_stats(deps, arbiter)?),
+        QueryMsg::ListArbiters { category, start_after, limit } => {
+            to_binary(&query_list_arbiters(deps, category, start_after, limit)?)
+        }
+        QueryMsg::Template { id } => to_binary(&query_template(deps, id)?),
+        QueryMsg::ListTemplates { owner, start_after, limit } => {
+            to_binary(&query_list_templates(deps, owner, start_after, limit)?)
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(
+    deps: DepsMut,
+    env: Env,
+    msg: MigrateMsg,
+) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::CannotMigrate {
+            previous_contract: stored.contract,
+        });
+    }
+    // refuse to downgrade
+    let stored_version: Version = stored.version.parse()?;
+    let new_version: Version = CONTRACT_VERSION.parse()?;
+    if stored_version > new_version {
+        return Err(ContractError::CannotMigrateVersion {
+            previous_version: stored.version,
+        });
+    }
+    if msg.limit == Some(0) {
+        return Err(ContractError::ZeroLimit {});
+    }
+
+    let ownership_migrated = migrate_ownership(deps.storage)?;
+    // contracts deployed before the config existed get the defaults of `instantiate`
+    let config_created = CONFIG.may_load(deps.storage)?.is_none();
+    if config_created {
+        let admin = deps.api.addr_validate(&msg.admin.ok_or(ContractError::ConfigRequired {})?)?;
+        let fee_collector = match msg.fee_collector {
+            Some(collector) => deps.api.addr_validate(&collector)?,
+            None => admin.clone(),
+        };
+        if OWNERSHIP.may_load(deps.storage)?.is_none() {
+            let ownership = Ownership {
+                owner: Some(admin),
+                ..Ownership::default()
+            };
+            OWNERSHIP.save(deps.storage, &ownership)?;
+        }
+        let config = Config {
+            max_tokens: DEFAULT_MAX_TOKENS,
+            fee_bps: 0,
+            fee_collector,
+            appellate_arbiter: None,
+            appeal_window: DEFAULT_APPEAL_WINDOW,
+            paused: false,
+            releases_paused: false,
+            keeper_reward: vec![],
+            unclaimed_timeout: None,
+            arbiter_relayers: vec![],
+            max_duration: None,
+            max_open_per_source: None,
+            creation_fee: None,
+            strict_parties: false,
+            receipt_nft: None,
+        };
+        CONFIG.save(deps.storage, &config)?;
+    }
+
+    // escrows created before the move to cw-storage-plus are missing from the indexes,
+    // later versions only need this if the stats or the archive index are missing. With a
+    // limit, this continues where the previous step stopped
+    let progress = match MIGRATION.may_load(deps.storage)? {
+        Some(progress) => Some(progress),
+        None if stored_version < INDEXED_SINCE.parse()?
+            || ESCROW_COUNTS.may_load(deps.storage)?.is_none()
+            || archive_unindexed(deps.storage) =>
+        {
+            // checked before anything moves, the old code stays in place if this fails
+            let ambiguous = ambiguous_legacy_deadlines(deps.storage, &env.block)?;
+            if !ambiguous.is_empty() {
+                return Err(ContractError::AmbiguousDeadlines {
+                    ids: ambiguous.join(", "),
+                });
+            }
+            Some(MigrationProgress::start(deps.storage)?)
+        }
+        None => None,
+    };
+    let (mut reindexed, mut archived, mut done) = (0, 0, progress.is_none());
+    if let Some(mut progress) = progress {
+        let limit = msg.limit.map_or(usize::MAX, |limit| limit as usize);
+        let mut left = limit;
+        if !progress.archive_done {
+            left -= count_archived_escrows(deps.storage, &mut progress, limit)?;
+        }
+        if progress.archive_done {
+            (reindexed, archived) = migrate_escrows(deps.storage, &env.block, &mut progress, left)?;
+            done = reindexed < left;
+        }
+        if done {
+            MIGRATION.remove(deps.storage);
+        } else {
+            MIGRATION.save(deps.storage, &progress)?;
+        }
+    }
+    if done {
+        set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     }
+
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", stored.version)
+        .add_attribute("to_version", CONTRACT_VERSION)
+        .add_attribute("reindexed", reindexed.to_string())
+        .add_attribute("archived", archived.to_string())
+        .add_attribute("ownership_migrated", ownership_migrated.to_string())
+        .add_attribute("done", done.to_string())
+        .add_attribute("config_created", config_created.to_string())
+    )
 }
 
 pub fn try_receive(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     wrapper: Cw20ReceiveMsg,
 ) -> Result<Response, ContractError> {
     let msg = from_binary(&wrapper.msg)?;
 
     let balance = Balance::Cw20(Cw20CoinVerified {
-        address: info.sender.into(),
+        address: info.sender,
         amount: wrapper.amount,
     });
 
+    let sender = deps.api.addr_validate(&wrapper.sender)?;
+    receive_tokens(deps, env, msg, balance.into(), &sender)
+}
+
+// creates or tops up an escrow with tokens the contract received from `sender`
+fn receive_tokens(
+    deps: DepsMut,
+    env: Env,
+    msg: ReceiveMsg,
+    balance: GenericBalance,
+    sender: &Addr,
+) -> Result<Response, ContractError> {
     match msg {
-        ReceiveMsg::Create(msg) => try_create(deps, msg, balance, wrapper.sender),
-        ReceiveMsg::TopUp { id } => try_top_up(deps, balance, id),
+        ReceiveMsg::Create(msg) => try_create(deps, env, msg, balance, sender),
+        ReceiveMsg::CreateFromTemplate(msg) => {
+            let msg = template_create_msg(deps.storage, &env, msg, &balance)?;
+            try_create(deps, env, msg, balance, sender)
+        }
+        ReceiveMsg::TopUp { id } => try_top_up(deps, env, balance, sender, id),
+    }
+}
+
+pub fn try_deposit_cw20(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token: String,
+    amount: Uint128,
+    msg: ReceiveMsg,
+) -> Result<Response, ContractError> {
+    if amount.is_zero() {
+        return Err(ContractError::ZeroBalance {});
+    }
+    let token = deps.api.addr_validate(&token)?;
+
+    let balance_before = query_cw20_balance(deps.as_ref(), &token, &env.contract.address)?;
+    PENDING_DEPOSIT.save(
+        deps.storage,
+        &PendingDeposit {
+            token: token.clone(),
+            sender: info.sender.clone(),
+            balance_before,
+            msg,
+        },
+    )?;
+
+    let transfer = WasmMsg::Execute {
+        contract_addr: token.into_string(),
+        msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+            owner: info.sender.into_string(),
+            recipient: env.contract.address.into_string(),
+            amount,
+        })?,
+        funds: vec![],
+    };
+    Ok(Response::new().add_submessage(SubMsg::reply_on_success(transfer, DEPOSIT_REPLY_ID)))
+}
+
+// credits a `DepositCw20` with the amount that actually arrived
+fn credit_deposit(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let pending = PENDING_DEPOSIT.load(deps.storage)?;
+    PENDING_DEPOSIT.remove(deps.storage);
+
+    let balance_after = query_cw20_balance(deps.as_ref(), &pending.token, &env.contract.address)?;
+    let received = balance_after.saturating_sub(pending.balance_before);
+    if received.is_zero() {
+        return Err(ContractError::ZeroBalance {});
+    }
+
+    let balance = GenericBalance {
+        cw20: vec![Cw20CoinVerified {
+            address: pending.token,
+            amount: received,
+        }],
+        ..GenericBalance::default()
+    };
+    receive_tokens(deps, env, pending.msg, balance, &pending.sender)
+}
+
+fn query_cw20_balance(deps: Deps, token: &Addr, address: &Addr) -> StdResult<Uint128> {
+    let res: BalanceResponse = deps.querier.query_wasm_smart(
+        token,
+        &Cw20QueryMsg::Balance {
+            address: address.to_string(),
+        },
+    )?;
+    Ok(res.balance)
+}
+
+pub fn try_receive_nft(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    wrapper: Cw721ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let msg = from_binary(&wrapper.msg)?;
+
+    let balance = GenericBalance {
+        nfts: vec![(info.sender, wrapper.token_id)],
+        ..GenericBalance::default()
+    };
+
+    let sender = deps.api.addr_validate(&wrapper.sender)?;
+    receive_tokens(deps, env, msg, balance, &sender)
+}
+
+// creates an escrow with coins, less the creation fee
+fn try_create_with_coins(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if FEE_EXEMPT.has(deps.storage, &info.sender) {
+        config.creation_fee = None;
+    }
+    let mut funds = info.funds;
+    if let Some(fee) = &config.creation_fee {
+        match funds.iter_mut().find(|coin| coin.denom == fee.denom) {
+            Some(coin) if coin.amount >= fee.amount => coin.amount -= fee.amount,
+            _ => return Err(ContractError::CreationFeeNotPaid { fee: fee.clone() }),
+        }
+        funds.retain(|coin| !coin.amount.is_zero());
     }
+
+    let res = receive_tokens(deps, env, msg, Balance::from(funds).into(), &info.sender)?;
+    Ok(match config.creation_fee {
+        Some(fee) => res.add_attribute("creation_fee", fee.to_string()).add_message(BankMsg::Send {
+            to_address: config.fee_collector.into_string(),
+            amount: vec![fee],
+        }),
+        None => res,
+    })
 }
 
+/// Creates an escrow holding `escrow_balance`, which the caller must have received from
+/// `sender` already
 pub fn try_create(
     deps: DepsMut,
-    msg: CreateMsg,
-    balance: Balance,
-    sender: String,
+    env: Env,
+    mut msg: CreateMsg,
+    escrow_balance: GenericBalance,
+    sender: &Addr,
 ) -> Result<Response, ContractError>{
+    assert_not_migrating(deps.storage)?;
+    validate_id(&msg.id)?;
+    if msg.namespaced.unwrap_or_default() {
+        msg.id = namespaced_id(sender, &msg.id);
+    }
 
-    // this fails if no fund is sent from the receiver
-    if balance.is_empty() {
+    let crowdfund = msg.crowdfund.unwrap_or_default();
+    // a dead man's switch expires once the source stops pinging, releasing to the recipient
+    if let Some(interval) = msg.ping_interval {
+        if crowdfund
+            || msg.expires != (Expiration::Never {})
+            || msg.on_expiry == Some(OnExpiry::RefundToSource)
+            || interval == Duration::Height(0)
+            || interval == Duration::Time(0)
+        {
+            return Err(ContractError::InvalidSwitch {});
+        }
+        msg.expires = interval.after(&env.block);
+        msg.on_expiry = Some(OnExpiry::ReleaseToRecipient);
+    }
+    // this fails if no fund is sent from the receiver, campaigns may start empty
+    if escrow_balance.is_empty() && !crowdfund {
         return Err(ContractError::ZeroBalance{})
     }
+    // campaigns release at the deadline, if the goal was reached
+    if crowdfund
+        && (msg.funding_target.is_none()
+            || msg.expires == Expiration::Never {}
+            || msg.on_expiry == Some(OnExpiry::RefundToSource))
+    {
+        return Err(ContractError::InvalidCrowdfund {});
+    }
+    let swap_for = msg
+        .swap_for
+        .map(|wanted| validate_amounts(deps.as_ref(), wanted))
+        .transpose()?;
+    // swaps are delivered both ways at once, or each side goes back to its owner
+    if swap_for.is_some() {
+        let reason = if crowdfund || msg.funding_target.is_some() || msg.ping_interval.is_some() {
+            Some("swaps are funded by the two parties only")
+        } else if msg.on_expiry == Some(OnExpiry::ReleaseToRecipient) {
+            Some("expired swaps are refunded")
+        } else if msg.schedule.is_some()
+            || msg.mint_receipt.unwrap_or_default()
+            || msg.splits.is_some()
+            || msg.pull_payouts.unwrap_or_default()
+            || msg.pull_refunds.unwrap_or_default()
+            || msg.payout_callback.unwrap_or_default()
+            || msg.remote_recipient.is_some()
+            || msg.remote_refund_address.is_some()
+        {
+            Some("swaps are delivered with plain transfers")
+        } else if matches!(&swap_for, Some(wanted) if wanted.is_empty()) {
+            Some("the recipient has to deposit something")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            return Err(ContractError::InvalidSwap {
+                reason: String::from(reason),
+            });
+        }
+    }
+    let on_expiry = if crowdfund {
+        OnExpiry::ReleaseToRecipient
+    } else {
+        msg.on_expiry.unwrap_or_default()
+    };
+    assert_tokens_allowed(deps.storage, &escrow_balance)?;
 
-    let mut cw20_whitelist = msg.cw20_whitelist.unwrap_or_else(|| vec![]);
+    let mut cw20_whitelist = msg.cw20_whitelist.unwrap_or_default();
+    // make sure the token sent is on the whitelist by default
+    for token in escrow_balance.cw20.iter() {
+        if !cw20_whitelist.iter().any(|t| t == token.address.as_str()) {
+            cw20_whitelist.push(token.address.to_string());
+        }
+    }
 
-    let escrow_balance = match balance {
-        Balance::Native(balance) => GenericBalance {
-            native: balance.0,
-            cw20: vec![],
-        },
-        Balance::Cw20(token) => {
-            // make sure the token sent is on the whitelist by default
-            if !cw20_whitelist.iter().any(|t| t == &token.address.to_string()) {
-                cw20_whitelist.push(token.address.to_string());
-            }
+    let config = CONFIG.load(deps.storage)?;
+    // dead man's switches never expire by design, they settle once the pings stop
+    if msg.ping_interval.is_none() {
+        assert_max_duration(&config, &env.block, &msg.expires)?;
+    }
+    // an escrow past its deadline from the start could only be settled again
+    if msg.expires.is_expired(&env.block) {
+        return Err(ContractError::Expired {
+            expires: msg.expires,
+        });
+    }
+    if let Some(max) = config.max_open_per_source {
+        if open_escrows_of(deps.storage, sender)? >= max && !OPEN_LIMIT_EXEMPT.has(deps.storage, sender) {
+            return Err(ContractError::TooManyOpenEscrows {
+                creator: sender.to_string(),
+                max,
+            });
+        }
+    }
+    if config.paused {
+        return Err(ContractError::Paused {});
+    }
+    if escrow_balance.num_tokens() > config.max_tokens as usize {
+        return Err(ContractError::TooManyTokens {
+            max: config.max_tokens,
+        });
+    }
+
+    let arbiters = validate_arbiters(deps.as_ref(), msg.arbiter)?;
+    let threshold = msg.threshold.unwrap_or(1);
+    if threshold == 0 || threshold as usize > arbiters.len() {
+        return Err(ContractError::InvalidThreshold {});
+    }
+
+    let funding_target = msg
+        .funding_target
+        .map(|target| validate_amounts(deps.as_ref(), target))
+        .transpose()?;
+    // the funding and swapped tokens have to be accepted on top-ups
+    for token in funding_target.iter().chain(swap_for.iter()).flat_map(|target| target.cw20.iter()) {
+        if !cw20_whitelist.iter().any(|t| t == token.address.as_str()) {
+            cw20_whitelist.push(token.address.to_string());
+        }
+    }
 
-            GenericBalance {
-                native: vec![],
-                cw20: vec![token.clone()],
+    if let Some(schedule) = &msg.schedule {
+        schedule.validate()?;
+    }
+    // vesting escrows are claimed already, `Claim` would not know which to pay out
+    let pull_payouts = msg.pull_payouts.unwrap_or_default();
+    if pull_payouts && msg.schedule.is_some() {
+        return Err(ContractError::InvalidSchedule {
+            reason: String::from("vesting escrows cannot pull payouts"),
+        });
+    }
+    let schedule = msg.schedule;
+    // a start that is never reached would lock the funds until expiry
+    if matches!(&msg.start, Some(start) if *start >= msg.expires) {
+        return Err(ContractError::InvalidStart {});
+    }
+    if let Some(grace_period) = msg.grace_period {
+        (msg.expires + grace_period).map_err(|_| ContractError::InvalidGracePeriod {})?;
+    }
+    for remote in msg.remote_recipient.iter().chain(msg.remote_refund_address.iter()) {
+        remote.validate()?;
+    }
+    // pulled payouts and callbacks need a local address
+    if (msg.remote_recipient.is_some() && (pull_payouts || msg.payout_callback.unwrap_or_default()))
+        || (msg.remote_refund_address.is_some() && msg.pull_refunds.unwrap_or_default())
+    {
+        return Err(ContractError::InvalidRemotePayout {});
+    }
+    let arbiter_proxy = msg
+        .arbiter_proxy
+        .map(|proxy| validate_arbiter_proxy(deps.as_ref(), &config, &proxy))
+        .transpose()?;
+    let receipt = if msg.mint_receipt.unwrap_or_default() {
+        // the payouts have to be plain transfers the holder of the receipt can take over
+        if schedule.is_some()
+            || msg.payout_callback.unwrap_or_default()
+            || msg.release_hook.is_some()
+            || msg.remote_recipient.is_some()
+        {
+            return Err(ContractError::InvalidReceipt {});
+        }
+        Some(config.receipt_nft.clone().ok_or(ContractError::ReceiptsDisabled {})?)
+    } else {
+        None
+    };
+    let splits = match msg.splits {
+        Some(splits) => {
+            if receipt.is_some()
+                || msg.payout_callback.unwrap_or_default()
+                || msg.release_hook.is_some()
+                || msg.remote_recipient.is_some()
+            {
+                return Err(ContractError::InvalidSplits {
+                    reason: String::from("splits are paid with plain transfers"),
+                });
             }
+            validate_splits(deps.as_ref(), splits)?
         }
+        None => vec![],
     };
 
-    let escrow = Escrow {
-        arbiter: msg.arbiter,
-        recipient: msg.recipient,
-        source: sender,
-        end_height: msg.end_height,
-        end_time: msg.end_time,
+    let mut escrow = Escrow {
+        arbiters,
+        threshold,
+        recipient: deps.api.addr_validate(&msg.recipient)?,
+        source: sender.clone(),
+        expires: msg.expires,
+        start: msg.start,
+        grace_period: msg.grace_period,
+        ping_interval: msg.ping_interval,
+        release_hook: msg.release_hook,
+        payout_callback: msg.payout_callback.unwrap_or_default(),
+        pull_payouts,
+        pull_refunds: msg.pull_refunds.unwrap_or_default(),
+        created_at_height: Some(env.block.height),
+        created_at_time: Some(env.block.time),
+        remote_recipient: msg.remote_recipient,
+        remote_refund_address: msg.remote_refund_address,
+        arbiter_proxy,
+        strict_parties: config.strict_parties || msg.strict_parties.unwrap_or_default(),
+        receipt,
+        splits,
+        swap: swap_for.map(|wanted| Swap {
+            offered: escrow_balance.clone(),
+            wanted,
+        }),
         balance: escrow_balance,
         cw20_whitelist,
+        // partners creating escrows never pay the protocol fee on them
+        fee_bps: if FEE_EXEMPT.has(deps.storage, sender) { 0 } else { config.fee_bps },
+        arbiter_fee_bps: validate_bps(msg.arbiter_fee_bps.unwrap_or_default())?,
+        refund_address: msg
+            .refund_address
+            .map(|addr| deps.api.addr_validate(&addr))
+            .transpose()?,
+        on_expiry,
+        status: Status::Pending,
+        title: msg.title,
+        description: msg.description,
+        metadata: msg.metadata,
+        terms_hash: msg.terms_hash.map(validate_terms_hash).transpose()?,
+        source_approved: false,
+        recipient_approved: false,
+        accepted: !msg.requires_acceptance.unwrap_or_default(),
+        funding_target,
+        arbiter_can_set_recipient: msg.arbiter_can_set_recipient.unwrap_or_default(),
+        schedule,
+        claimed: GenericBalance::default(),
+        allowed_funders: msg
+            .allowed_funders
+            .map(|funders| funders.iter().map(|f| deps.api.addr_validate(f)).collect())
+            .transpose()?,
+        crowdfund,
+        conditions: msg
+            .hash_lock
+            .map(Condition::Preimage)
+            .into_iter()
+            .chain(msg.conditions.into_iter().flatten())
+            .map(|condition| condition.validate(deps.api))
+            .collect::<Result<_, _>>()?,
+        dispute: None,
+        decision: None,
     };
+    escrow.check_parties()?;
+    // the other side of a swap can only come from its recipient
+    if escrow.swap.is_some() {
+        escrow.allowed_funders = Some(vec![escrow.recipient.clone()]);
+    }
+    escrow.activate_if_ready();
+
+    let mut event = escrow_event("escrow_created", &msg.id)
+        .add_attribute("arbiters", escrow.arbiters.iter().map(Addr::as_str).collect::<Vec<_>>().join(","))
+        .add_attribute("recipient", &escrow.recipient)
+        .add_attribute("source", &escrow.source)
+        .add_attribute("amount", escrow.balance.to_string())
+        .add_attribute("on_expiry", escrow.on_expiry.to_string());
+    if escrow.expires != (Expiration::Never {}) {
+        event = event.add_attribute("expires", escrow.expires.to_string());
+    }
+    if let Some(start) = &escrow.start {
+        event = event.add_attribute("start", start.to_string());
+    }
+    if let Some(grace_period) = &escrow.grace_period {
+        event = event.add_attribute("grace_period", grace_period.to_string());
+    }
+    if let Some(ping_interval) = &escrow.ping_interval {
+        event = event.add_attribute("ping_interval", ping_interval.to_string());
+    }
+    let mut msgs = vec![];
+    if let Some(receipt) = &escrow.receipt {
+        event = event.add_attribute("receipt", receipt);
+        msgs.push(WasmMsg::Execute {
+            contract_addr: receipt.to_string(),
+            msg: to_binary(&ReceiptMintMsg::Mint {
+                token_id: msg.id.clone(),
+                owner: escrow.recipient.to_string(),
+                token_uri: None,
+                extension: None,
+            })?,
+            funds: vec![],
+        });
+    }
+
+    let subscribers = msg.subscribers.unwrap_or_default();
+    if subscribers.len() > MAX_SUBSCRIBERS as usize {
+        return Err(ContractError::TooManySubscribers { max: MAX_SUBSCRIBERS });
+    }
+    for subscriber in subscribers {
+        SUBSCRIBERS.save(deps.storage, (&msg.id, &deps.api.addr_validate(&subscriber)?), &Empty {})?;
+    }
 
     // try to store it, fail if the id was already in use
     let res = escrows_update(deps.storage, escrow, &msg.id);
     match res {
-        Ok(_) => Ok(Response::default()),
-        _ =>  Err(ContractError::IdAlreadyExists{}), 
+        Ok(escrow) => {
+            if !escrow.balance.is_empty() {
+                add_contribution(deps.storage, &msg.id, sender, &escrow.balance)?;
+            }
+            record_action(deps.storage, &env, &msg.id, "create", Some(sender), &escrow.balance)?;
+            Ok(Response::new()
+            .add_messages(msgs)
+            .add_submessages(notify_subscribers(deps.storage, &msg.id, EscrowChange::Created)?)
+            .add_event(event)
+            .set_data(to_binary(&CreateResponse { id: msg.id })?))
+        }
+        Err(err) => Err(err),
     }
 }
 
-fn try_approve(
+fn escrow_event(ty: &str, id: &str) -> Event {
+    Event::new(ty).add_attribute("id", id)
+}
+
+fn payout_data(id: &str, paid: &GenericBalance) -> StdResult<Binary> {
+    to_binary(&PayoutResponse {
+        id: id.to_string(),
+        native: paid.native.clone(),
+        cw20: paid
+            .cw20
+            .iter()
+            .map(|c| Cw20Coin {
+                address: c.address.to_string(),
+                amount: c.amount,
+            })
+            .collect(),
+    })
+}
+
+/// Releases the escrow to the recipient, `info.sender` has to be an arbiter
+pub fn try_approve(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     id: String
 ) -> Result<Response, ContractError> {
-    let escrow = escrows_read( deps.storage, &id)?;
+    assert_not_migrating(deps.storage)?;
+    let escrow = load_approvable(deps.storage, &env, &info.sender, &id)?;
+    decide(deps, &env, escrow, &id, Status::Approved, BPS_DENOMINATOR as u16, &info.sender)
+}
 
-    if  escrow.arbiter != info.sender.as_str() {
-        return Err(ContractError::Unauthorized {});
-    }
-    
-    else if escrow.is_expired(&env) {   // throws error if state is expired
+// the escrow, if `sender` can approve it
+fn load_approvable(storage: &dyn Storage, env: &Env, sender: &Addr, id: &str) -> Result<Escrow, ContractError> {
+    let escrow = load_unsettled(storage, id)?;
+
+    assert_arbiter(&escrow, sender)?;
+    assert_accepted(&escrow)?;
+    assert_started(&escrow, env)?;
+    assert_not_decided(&escrow)?;
+    if escrow.is_release_blocked(env) {   // throws error if state is expired
         return Err(ContractError::Expired {
-            end_height: escrow.end_height,
-            end_time: escrow.end_time,
+            expires: escrow.expires,
         });
-    } else {
-        escrows_remove(deps.storage, &id)?;  // remove the escrow contract because it is no longer needed
-        // send tokens to the seller
-        let msgs = send_tokens(escrow.recipient, &escrow.balance)?;
-        Ok(Response::new()
-            .add_messages(msgs)
-            .add_attribute("action", "approve escrow")
-        )
     }
+    Ok(escrow)
 }
 
-fn try_refund(
-    deps: DepsMut,
+// approves every id the sender can approve, the others are skipped with the reason in an
+// event rather than failing the whole batch
+fn try_approve_many(
+    mut deps: DepsMut,
+    env: Env,
     info: MessageInfo,
-    id: String
+    ids: Vec<String>,
 ) -> Result<Response, ContractError> {
-    let escrow = escrows_read(deps.storage, &id)?;
-    
-    if info.sender != escrow.arbiter
-    {
-        return Err(ContractError::Unauthorized {});
-    } else {
-        escrows_remove(deps.storage, &id)?;  // remove the escrow contract because it is no longer needed
+    // checked up front, a decision is recorded before the payout would fail on it
+    if CONFIG.load(deps.storage)?.releases_paused {
+        return Err(ContractError::Paused {});
+    }
 
-        let msgs = send_tokens(escrow.recipient, &escrow.balance)?;
-        Ok(Response::new()
-            .add_messages(msgs)
-            .add_attribute("action", "refund")
-        )       
+    let mut res = Response::new().add_attribute("action", "approve_many");
+    let mut msgs = vec![];
+    let mut approved = 0;
+    let mut skipped = 0;
+    for id in ids {
+        match load_approvable(deps.storage, &env, &info.sender, &id) {
+            Ok(escrow) => {
+                let approval = decide(
+                    deps.branch(),
+                    &env,
+                    escrow,
+                    &id,
+                    Status::Approved,
+                    BPS_DENOMINATOR as u16,
+                    &info.sender,
+                )?;
+                msgs.extend(approval.messages);
+                res = res.add_events(approval.events);
+                approved += 1;
+            }
+            Err(err) => {
+                res = res.add_event(escrow_event("escrow_approve_skipped", &id).add_attribute("reason", err.to_string()));
+                skipped += 1;
+            }
+        }
     }
+    Ok(res
+        .add_submessages(batch_payout_submsgs(deps.storage, msgs)?)
+        .add_attribute("approved", approved.to_string())
+        .add_attribute("skipped", skipped.to_string()))
 }
 
-// this is a helper to move the tokens, so the business logic is easy to read
-fn send_tokens(
-    to_address: String, 
-    amount: &GenericBalance, 
-) -> StdResult<Vec<CosmosMsg>> {
-    let native_balance = &amount.native;
-    let mut msgs = if native_balance.is_empty() {
-        vec![]
-    } else {
-        vec![BankMsg::Send {
-            to_address: to_address.clone(),
-            amount: native_balance.to_vec(),
-        }
-        .into()]
-    };
+// releases the whole balance, `arbiter` is set if an arbiter made the decision
+fn approve_escrow(
+    deps: DepsMut,
+    env: &Env,
+    mut escrow: Escrow,
+    id: &str,
+    arbiter: Option<&Addr>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let msgs = release_msgs(deps.storage, deps.querier, env, &config, id, &escrow, escrow.balance.clone(), arbiter)?;
+    let data = payout_data(id, &escrow.balance)?;
+    let event = escrow_event("escrow_approved", id)
+        .add_attribute("recipient", &escrow.recipient)
+        .add_attribute("amount", escrow.balance.to_string());
 
-    let cw20_balance = &amount.cw20;
-    let cw20_msgs: StdResult<Vec<_>> = cw20_balance
-        .iter()
-        .map(|c| {
-            let msg = Cw20ExecuteMsg::Transfer {
-                recipient: to_address.clone(),
-                amount: c.amount,
-            };
-            let exec = WasmMsg::Execute {
-                contract_addr: c.address.to_string(),
-                msg: to_binary(&msg)?,
-                funds: vec![],
-            };
-            Ok(exec.into())
-        })
-        .collect();
+    record_action(deps.storage, env, id, "approve", arbiter, &escrow.balance)?;
+    escrow.settle(Status::Approved);
+    archive_escrow(deps.storage, &escrow, id)?;
 
-    msgs.append(&mut cw20_msgs?);
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_submessages(notify_subscribers(deps.storage, id, EscrowChange::Approved)?)
+        .add_event(event)
+        .set_data(data)
+    )
+}
 
-    Ok(msgs)
+// escrows cannot be released before the recipient accepted and the funding target is met
+fn assert_accepted(escrow: &Escrow) -> Result<(), ContractError> {
+    if escrow.swap.is_some() {
+        return Err(ContractError::SwapOnly {});
+    }
+    match escrow.status {
+        Status::Pending => Err(ContractError::NotAccepted {}),
+        Status::Funding => Err(ContractError::NotFunded {}),
+        _ => Ok(()),
+    }
 }
 
+// nothing is released before the start of the escrow
+fn assert_started(escrow: &Escrow, env: &Env) -> Result<(), ContractError> {
+    match escrow.start {
+        Some(start) if !escrow.has_started(env) => Err(ContractError::NotStarted { start }),
+        _ => Ok(()),
+    }
+}
+
+// after expiry, only arbiters may act until the grace period ends
+fn assert_not_in_grace_period(escrow: &Escrow, env: &Env) -> Result<(), ContractError> {
+    if escrow.in_grace_period(env) {
+        return Err(ContractError::GracePeriod {
+            ends: escrow.grace_ends(),
+        });
+    }
+    Ok(())
+}
+
+// only arbiters of escrows that do not require a vote can decide alone
+fn assert_arbiter(escrow: &Escrow, sender: &Addr) -> Result<(), ContractError> {
+    if !escrow.is_arbiter(sender) {
+        return Err(ContractError::NotArbiter {
+            sender: sender.to_string(),
+        });
+    }
+    if escrow.threshold > 1 {
+        return Err(ContractError::VoteRequired {});
+    }
+    Ok(())
+}
 
-fn try_top_up(
+fn try_approve_partial(
     deps: DepsMut,
-    balance: Balance,
+    env: Env,
+    info: MessageInfo,
     id: String,
+    amounts: GenericBalance,
 ) -> Result<Response, ContractError> {
-    if balance.is_empty() {
-        return Err(ContractError::ZeroBalance{});
-    }
+    let mut escrow = load_unsettled(deps.storage, &id)?;
 
-    let mut escrow = escrows_read( deps.storage, &id)?;
+    assert_arbiter(&escrow, &info.sender)?;
+    assert_accepted(&escrow)?;
+    assert_not_decided(&escrow)?;
+    assert_started(&escrow, &env)?;
+    if escrow.is_release_blocked(&env) {
+        return Err(ContractError::Expired {
+            expires: escrow.expires,
+        });
+    }
+
+    escrow.balance.sub_tokens(&amounts)?;
+    // once everything is released, the escrow is settled and archived
+    if escrow.balance.is_empty() {
+        escrow.settle(Status::Approved);
+        archive_escrow(deps.storage, &escrow, &id)?;
+    } else {
+        escrows_save(deps.storage, &escrow, &id)?;
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let event = escrow_event("escrow_partially_approved", &id)
+        .add_attribute("recipient", &escrow.recipient)
+        .add_attribute("amount", amounts.to_string())
+        .add_attribute("remaining", escrow.balance.to_string());
+    let data = payout_data(&id, &amounts)?;
+    record_action(deps.storage, &env, &id, "approve", Some(&info.sender), &amounts)?;
+    let msgs = release_msgs(deps.storage, deps.querier, &env, &config, &id, &escrow, amounts, Some(&info.sender))?;
+
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_submessages(notify_subscribers(deps.storage, &id, EscrowChange::Approved)?)
+        .add_event(event)
+        .set_data(data)
+    )
+}
+
+// everything an escrow holds of the named tokens
+fn tokens_held(storage: &dyn Storage, id: &str, tokens: &[String]) -> Result<GenericBalance, ContractError> {
+    let amounts = load_unsettled(storage, id)?.balance.of_tokens(tokens)?;
+    if amounts.is_empty() {
+        return Err(ContractError::ZeroBalance {});
+    }
+    Ok(amounts)
+}
+
+fn try_refund_partial(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    amounts: GenericBalance,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    assert_arbiter(&escrow, &info.sender)?;
+    assert_not_decided(&escrow)?;
+
+    escrow.balance.sub_tokens(&amounts)?;
+    if escrow.balance.is_empty() {
+        escrow.settle(Status::Refunded);
+        archive_escrow(deps.storage, &escrow, &id)?;
+    } else {
+        escrows_save(deps.storage, &escrow, &id)?;
+    }
+
+    let event = escrow_event("escrow_partially_refunded", &id)
+        .add_attribute("to", escrow.refund_to())
+        .add_attribute("amount", amounts.to_string())
+        .add_attribute("remaining", escrow.balance.to_string());
+    let data = payout_data(&id, &amounts)?;
+    record_action(deps.storage, &env, &id, "refund", Some(&info.sender), &amounts)?;
+    let msgs = refund_msgs(deps.storage, &env, &id, &escrow, amounts, Some(&info.sender))?;
+
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_submessages(notify_subscribers(deps.storage, &id, EscrowChange::Refunded)?)
+        .add_event(event)
+        .set_data(data)
+    )
+}
+
+fn try_resolve(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    recipient_bps: u16,
+) -> Result<Response, ContractError> {
+    let escrow = load_unsettled(deps.storage, &id)?;
+
+    assert_arbiter(&escrow, &info.sender)?;
+    assert_accepted(&escrow)?;
+    assert_not_decided(&escrow)?;
+    validate_bps(recipient_bps)?;
+    if recipient_bps > 0 {
+        assert_started(&escrow, &env)?;
+    }
+    // an expired escrow may only be able to go back to the source
+    if recipient_bps > 0 && escrow.is_release_blocked(&env) {
+        return Err(ContractError::Expired {
+            expires: escrow.expires,
+        });
+    }
+
+    decide(deps, &env, escrow, &id, Status::Resolved, recipient_bps, &info.sender)
+}
+
+// splits the balance between recipient and source
+fn resolve_escrow(
+    deps: DepsMut,
+    env: &Env,
+    mut escrow: Escrow,
+    id: &str,
+    recipient_bps: u16,
+    arbiter: Option<&Addr>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut refunded = escrow.balance.clone();
+    let released = refunded.split_bps(recipient_bps);
+    let event = escrow_event("escrow_resolved", id)
+        .add_attribute("recipient_bps", recipient_bps.to_string())
+        .add_attribute("released", released.to_string())
+        .add_attribute("refunded", refunded.to_string());
+    let mut msgs = release_msgs(deps.storage, deps.querier, env, &config, id, &escrow, released, arbiter)?;
+    msgs.append(&mut refund_msgs(deps.storage, env, id, &escrow, refunded, arbiter)?);
+
+    record_action(deps.storage, env, id, "resolve", arbiter, &escrow.balance)?;
+    escrow.settle(Status::Resolved);
+    archive_escrow(deps.storage, &escrow, id)?;
+
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_submessages(notify_subscribers(deps.storage, id, EscrowChange::Resolved)?)
+        .add_event(event)
+    )
+}
+
+// An arbiter decision. On a disputed escrow it can be appealed if there is an appellate
+// arbiter, so it is only recorded and carried out by `FinalizeDecision` once the appeal
+// window closed. Everything else is paid out right away
+fn decide(
+    deps: DepsMut,
+    env: &Env,
+    mut escrow: Escrow,
+    id: &str,
+    outcome: Status,
+    recipient_bps: u16,
+    arbiter: &Addr,
+) -> Result<Response, ContractError> {
+    escrow.close_dispute(env);
+    let resolution_time = escrow
+        .dispute
+        .as_ref()
+        .map(|dispute| env.block.time.seconds().saturating_sub(dispute.raised_at.seconds()));
+    record_decision(deps.storage, arbiter, resolution_time)?;
+    let config = CONFIG.load(deps.storage)?;
+    if escrow.status != Status::Disputed || config.appellate_arbiter.is_none() || config.appeal_window == 0 {
+        return carry_out(deps, env, escrow, id, outcome, recipient_bps, Some(arbiter));
+    }
+
+    let decision = Decision {
+        arbiter: arbiter.clone(),
+        outcome,
+        recipient_bps,
+        decided_at: env.block.time,
+        appeal_until: env.block.time.plus_seconds(config.appeal_window),
+        appealed_by: None,
+    };
+    let event = escrow_event("escrow_decided", id)
+        .add_attribute("arbiter", arbiter)
+        .add_attribute("outcome", outcome.to_string())
+        .add_attribute("recipient_bps", recipient_bps.to_string())
+        .add_attribute("appeal_until", decision.appeal_until.seconds().to_string());
+    escrow.status = Status::Decided;
+    escrow.decision = Some(decision);
+    escrows_save(deps.storage, &escrow, id)?;
+
+    Ok(Response::new().add_event(event))
+}
+
+fn carry_out(
+    deps: DepsMut,
+    env: &Env,
+    escrow: Escrow,
+    id: &str,
+    outcome: Status,
+    recipient_bps: u16,
+    arbiter: Option<&Addr>,
+) -> Result<Response, ContractError> {
+    match outcome {
+        Status::Approved => approve_escrow(deps, env, escrow, id, arbiter),
+        Status::Refunded => refund_escrow(deps, env, escrow, id, arbiter),
+        _ => resolve_escrow(deps, env, escrow, id, recipient_bps, arbiter),
+    }
+}
+
+fn try_appeal(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+    let mut decision = match (escrow.status, escrow.decision.clone()) {
+        (Status::Decided, Some(decision)) => decision,
+        _ => return Err(ContractError::NoDecision {}),
+    };
+
+    if !decision.may_appeal(&escrow, &info.sender) {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: String::from("the party the decision went against"),
+        });
+    }
+    if env.block.time > decision.appeal_until {
+        return Err(ContractError::AppealWindowClosed {});
+    }
+
+    let event = escrow_event("escrow_appealed", &id).add_attribute("appealed_by", &info.sender);
+    decision.appealed_by = Some(info.sender);
+    escrow.status = Status::Appealed;
+    escrow.decision = Some(decision);
+    escrows_save(deps.storage, &escrow, &id)?;
+
+    Ok(Response::new().add_event(event))
+}
+
+fn try_decide_appeal(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    recipient_bps: u16,
+) -> Result<Response, ContractError> {
+    let escrow = load_unsettled(deps.storage, &id)?;
+    let config = CONFIG.load(deps.storage)?;
+
+    if config.appellate_arbiter.as_ref() != Some(&info.sender) {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: String::from("the appellate arbiter"),
+        });
+    }
+    if escrow.status != Status::Appealed {
+        return Err(ContractError::NoDecision {});
+    }
+    validate_bps(recipient_bps)?;
+    if let Some(decision) = escrow.decision.as_ref() {
+        if decision.recipient_bps != recipient_bps {
+            record_overturned(deps.storage, &decision.arbiter)?;
+        }
+    }
+
+    let outcome = match u128::from(recipient_bps) {
+        BPS_DENOMINATOR => Status::Approved,
+        0 => Status::Refunded,
+        _ => Status::Resolved,
+    };
+    carry_out(deps, &env, escrow, &id, outcome, recipient_bps, Some(&info.sender))
+}
+
+fn try_finalize_decision(
+    deps: DepsMut,
+    env: Env,
+    id: String,
+) -> Result<Response, ContractError> {
+    let escrow = load_unsettled(deps.storage, &id)?;
+    let decision = match (escrow.status, escrow.decision.clone()) {
+        (Status::Decided, Some(decision)) => decision,
+        (Status::Appealed, _) => return Err(ContractError::AppealPending {}),
+        _ => return Err(ContractError::NoDecision {}),
+    };
+    if env.block.time <= decision.appeal_until {
+        return Err(ContractError::AppealPending {});
+    }
+
+    carry_out(deps, &env, escrow, &id, decision.outcome, decision.recipient_bps, Some(&decision.arbiter))
+}
+
+// pays out a released balance: the deciding arbiter (if any) is compensated first,
+// then the protocol fee goes to the collector and the rest to the seller
+#[allow(clippy::too_many_arguments)]
+fn release_msgs(
+    storage: &mut dyn Storage,
+    querier: QuerierWrapper,
+    env: &Env,
+    config: &Config,
+    id: &str,
+    escrow: &Escrow,
+    mut balance: GenericBalance,
+    arbiter: Option<&Addr>,
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    if config.releases_paused && !balance.is_empty() {
+        return Err(ContractError::Paused {});
+    }
+    let mut msgs = vec![];
+    if let Some(arbiter) = arbiter {
+        let arbiter_fee = balance.split_bps(escrow.arbiter_fee_bps);
+        msgs.append(&mut send_tokens(arbiter, &arbiter_fee)?);
+    }
+    let recipient = match &escrow.receipt {
+        Some(receipt) => receipt_holder(querier, receipt, id)?,
+        None => escrow.recipient.clone(),
+    };
+    // neither do partners receiving them, as long as they are exempt. Split payouts are
+    // charged per payee, out of their share
+    if !escrow.splits.is_empty() {
+        let mut shares = split_balance(&escrow.splits, balance)?;
+        let mut fee = GenericBalance::default();
+        for (address, share) in shares.iter_mut() {
+            if !FEE_EXEMPT.has(storage, address) {
+                fee.add_all(&share.split_bps(escrow.fee_bps))?;
+            }
+        }
+        msgs.append(&mut send_tokens(&config.fee_collector, &fee)?);
+        for (address, share) in shares {
+            if share.is_empty() {
+                continue;
+            }
+            if escrow.pull_payouts {
+                add_claimable(storage, id, &address, &share, env.block.time)?;
+            } else {
+                msgs.append(&mut send_tokens(&address, &share)?);
+            }
+        }
+        return Ok(msgs);
+    }
+    if !FEE_EXEMPT.has(storage, &recipient) {
+        let fee = balance.split_bps(escrow.fee_bps);
+        msgs.append(&mut send_tokens(&config.fee_collector, &fee)?);
+    }
+    if escrow.pull_payouts {
+        add_claimable(storage, id, &recipient, &balance, env.block.time)?;
+        return Ok(msgs);
+    }
+    if let Some(remote) = &escrow.remote_recipient {
+        msgs.append(&mut ibc_transfers(env, remote, std::mem::take(&mut balance.native)));
+    }
+    // recipient contracts with a payout callback get native tokens along with it
+    if escrow.payout_callback && !balance.native.is_empty() {
+        let callback = WasmMsg::Execute {
+            contract_addr: escrow.recipient.to_string(),
+            msg: to_binary(&PayoutCallbackMsg::EscrowPayout { id: id.to_string() })?,
+            funds: std::mem::take(&mut balance.native),
+        };
+        msgs.push(callback.into());
+    }
+    msgs.append(&mut send_tokens_with_hook(&recipient, &balance, escrow.release_hook.as_ref())?);
+    Ok(msgs)
+}
+
+// the owner of the claim receipt of the escrow
+fn receipt_holder(querier: QuerierWrapper, receipt: &Addr, id: &str) -> StdResult<Addr> {
+    let res: OwnerOfResponse = querier.query_wasm_smart(
+        receipt,
+        &Cw721QueryMsg::OwnerOf {
+            token_id: id.to_string(),
+            include_expired: None,
+        },
+    )?;
+    Ok(Addr::unchecked(res.owner))
+}
+
+// pays back a refunded balance to the funders, the share of the source goes to the refund
+// address if set. An arbiter is only compensated if they made the decision. With pull
+// refunds, the funders withdraw their shares with `ClaimRefund`
+fn refund_msgs(
+    storage: &mut dyn Storage,
+    env: &Env,
+    id: &str,
+    escrow: &Escrow,
+    mut balance: GenericBalance,
+    arbiter: Option<&Addr>,
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    let mut msgs = vec![];
+    if let Some(arbiter) = arbiter {
+        let arbiter_fee = balance.split_bps(escrow.arbiter_fee_bps);
+        msgs.append(&mut send_tokens(arbiter, &arbiter_fee)?);
+    }
+    let contributions = escrow_contributions(storage, id)?;
+    for (funder, mut share) in refund_shares(escrow, balance, &contributions)? {
+        if escrow.pull_refunds {
+            add_claimable(storage, id, &funder, &share, env.block.time)?;
+            continue;
+        }
+        if let Some(remote) = escrow.remote_refund_address.as_ref().filter(|_| funder == *escrow.refund_to()) {
+            msgs.append(&mut ibc_transfers(env, remote, std::mem::take(&mut share.native)));
+        }
+        msgs.append(&mut send_tokens(&funder, &share)?);
+    }
+    Ok(msgs)
+}
+
+// one ICS-20 transfer per denom, if it times out the tokens come back to the contract.
+// `IbcMsg::Transfer` cannot carry a memo, those are sent as `MsgTransfer` instead
+fn ibc_transfers(env: &Env, to: &RemoteAddress, coins: Vec<Coin>) -> Vec<CosmosMsg> {
+    let timeout = env.block.time.plus_seconds(IBC_TRANSFER_TIMEOUT);
+    coins
+        .into_iter()
+        .map(|amount| match &to.memo {
+            Some(memo) => transfer_with_memo(env, to, amount, timeout, memo),
+            None => IbcMsg::Transfer {
+                channel_id: to.chain_channel.clone(),
+                to_address: to.address.clone(),
+                amount,
+                timeout: IbcTimeout::with_timestamp(timeout),
+            }
+            .into(),
+        })
+        .collect()
+}
+
+// splits a refund between the funders in proportion to what each of them contributed of
+// every token. NFTs go back to whoever deposited them. The rest, i.e. the share of the
+// source, rounding leftovers and anything not on record, goes to the source
+fn refund_shares(
+    escrow: &Escrow,
+    balance: GenericBalance,
+    contributions: &[(Addr, GenericBalance)],
+) -> Result<Vec<(Addr, GenericBalance)>, ContractError> {
+    let mut total = GenericBalance::default();
+    for (_, contribution) in contributions.iter() {
+        total.add_all(contribution)?;
+    }
+    let mut rest = balance.clone();
+    let mut shares = vec![];
+    for (funder, contribution) in contributions.iter().filter(|(f, _)| *f != escrow.source) {
+        let mut share = GenericBalance::default();
+        for coin in balance.native.iter() {
+            let part = |b: &GenericBalance| {
+                b.native.iter().find(|c| c.denom == coin.denom).map(|c| c.amount).unwrap_or_default()
+            };
+            let total = part(&total);
+            if !total.is_zero() {
+                let amount = coin.amount.multiply_ratio(part(contribution), total);
+                share.native.push(Coin {
+                    denom: coin.denom.clone(),
+                    amount,
+                });
+            }
+        }
+        for token in balance.cw20.iter() {
+            let part = |b: &GenericBalance| {
+                b.cw20.iter().find(|c| c.address == token.address).map(|c| c.amount).unwrap_or_default()
+            };
+            let total = part(&total);
+            if !total.is_zero() {
+                let amount = token.amount.multiply_ratio(part(contribution), total);
+                share.cw20.push(Cw20CoinVerified {
+                    address: token.address.clone(),
+                    amount,
+                });
+            }
+        }
+        share.native.retain(|c| !c.amount.is_zero());
+        share.cw20.retain(|c| !c.amount.is_zero());
+        share.nfts = balance.nfts.iter().filter(|nft| contribution.nfts.contains(nft)).cloned().collect();
+
+        rest.sub_tokens(&share)?;
+        shares.push((funder.clone(), share));
+    }
+    shares.push((escrow.refund_to().clone(), rest));
+    Ok(shares)
+}
+
+// validates the arbiter addresses, dropping duplicates
+// Polytone voices (and similar ICA controllers) instantiate one proxy per remote sender,
+// so a proxy they created only ever executes what that sender relayed
+fn validate_arbiter_proxy(deps: Deps, config: &Config, proxy: &str) -> Result<Addr, ContractError> {
+    let proxy = deps.api.addr_validate(proxy)?;
+    let trusted = deps
+        .querier
+        .query_wasm_contract_info(&proxy)
+        .map(|info| config.arbiter_relayers.iter().any(|relayer| *relayer == info.creator))
+        .unwrap_or(false);
+    if !trusted {
+        return Err(ContractError::UntrustedArbiterProxy {
+            proxy: proxy.into_string(),
+        });
+    }
+    Ok(proxy)
+}
+
+fn validate_arbiters(deps: Deps, arbiters: Arbiters) -> Result<Vec<Addr>, ContractError> {
+    let mut validated: Vec<Addr> = vec![];
+    for arbiter in arbiters.into_vec() {
+        let arbiter = deps.api.addr_validate(&arbiter)?;
+        if !validated.contains(&arbiter) {
+            validated.push(arbiter);
+        }
+    }
+    if validated.is_empty() {
+        return Err(ContractError::NoArbiters {});
+    }
+    Ok(validated)
+}
+
+fn validate_splits(deps: Deps, splits: Vec<(String, u16)>) -> Result<Vec<(Addr, u16)>, ContractError> {
+    let invalid = |reason: &str| ContractError::InvalidSplits { reason: reason.to_string() };
+    if !(2..=MAX_SPLITS).contains(&splits.len()) {
+        return Err(invalid(&format!("between 2 and {} recipients", MAX_SPLITS)));
+    }
+    let mut validated: Vec<(Addr, u16)> = vec![];
+    for (address, bps) in splits {
+        let address = deps.api.addr_validate(&address)?;
+        if bps == 0 || validated.iter().any(|(a, _)| *a == address) {
+            return Err(invalid("every recipient needs a share and can only appear once"));
+        }
+        validated.push((address, bps));
+    }
+    if validated.iter().map(|(_, bps)| u128::from(*bps)).sum::<u128>() != BPS_DENOMINATOR {
+        return Err(invalid("shares must add up to 10000"));
+    }
+    Ok(validated)
+}
+
+// the share of every split, what rounding leaves and the NFTs go to the first
+fn split_balance(splits: &[(Addr, u16)], mut balance: GenericBalance) -> Result<Vec<(Addr, GenericBalance)>, ContractError> {
+    let total = balance.clone();
+    let mut shares = vec![];
+    for (address, bps) in splits.iter().skip(1) {
+        let share = total.clone().split_bps(*bps);
+        balance.sub_tokens(&share)?;
+        shares.push((address.clone(), share));
+    }
+    if let Some((first, _)) = splits.first() {
+        shares.insert(0, (first.clone(), balance));
+    }
+    Ok(shares)
+}
+
+// turns user provided amounts into a balance, zero amounts are ignored
+fn validate_amounts(deps: Deps, amounts: TokenAmounts) -> Result<GenericBalance, ContractError> {
+    let native: Vec<Coin> = amounts.native.into_iter().filter(|c| !c.amount.is_zero()).collect();
+    let cw20 = amounts
+        .cw20
+        .into_iter()
+        .filter(|c| !c.amount.is_zero())
+        .map(|c| {
+            Ok(Cw20CoinVerified {
+                address: deps.api.addr_validate(&c.address)?,
+                amount: c.amount,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let nfts = amounts
+        .nfts
+        .into_iter()
+        .map(|(contract, token_id)| Ok((deps.api.addr_validate(&contract)?, token_id)))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let balance = GenericBalance { native, cw20, nfts };
+    if balance.is_empty() {
+        return Err(ContractError::ZeroBalance {});
+    }
+    Ok(balance)
+}
+
+/// Refunds the escrow, by an arbiter or anyone once it expired
+pub fn try_refund(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String
+) -> Result<Response, ContractError> {
+    assert_not_migrating(deps.storage)?;
+    let (escrow, by_arbiter) = load_refundable(deps.storage, &env, &info.sender, &id)?;
+    refund(deps, &env, escrow, &id, by_arbiter.then_some(&info.sender))
+}
+
+// the escrow, if `sender` can refund it, and whether they do so as its arbiter
+fn load_refundable(storage: &dyn Storage, env: &Env, sender: &Addr, id: &str) -> Result<(Escrow, bool), ContractError> {
+    let escrow = load_unsettled(storage, id)?;
+    assert_not_decided(&escrow)?;
+
+    // the arbiter can refund any time, anyone else only after expiration
+    match assert_arbiter(&escrow, sender) {
+        Ok(()) => Ok((escrow, true)),
+        Err(_) if escrow.in_grace_period(env) => Err(ContractError::GracePeriod {
+            ends: escrow.grace_ends(),
+        }),
+        Err(_) if escrow.is_release_blocked(env) => {
+            assert_not_disputed(&escrow)?;
+            Ok((escrow, false))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn refund(
+    deps: DepsMut,
+    env: &Env,
+    escrow: Escrow,
+    id: &str,
+    arbiter: Option<&Addr>,
+) -> Result<Response, ContractError> {
+    match arbiter {
+        Some(arbiter) => decide(deps, env, escrow, id, Status::Refunded, 0, arbiter),
+        None => refund_escrow(deps, env, escrow, id, None),
+    }
+}
+
+// like `ApproveMany`, ids that cannot be refunded are skipped with the reason in an event
+fn try_refund_many(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    ids: Vec<String>,
+) -> Result<Response, ContractError> {
+    let mut res = Response::new().add_attribute("action", "refund_many");
+    let mut msgs = vec![];
+    let mut refunded = 0;
+    let mut skipped = 0;
+    for id in ids {
+        match load_refundable(deps.storage, &env, &info.sender, &id) {
+            Ok((escrow, by_arbiter)) => {
+                let refund = refund(deps.branch(), &env, escrow, &id, by_arbiter.then_some(&info.sender))?;
+                msgs.extend(refund.messages);
+                res = res.add_events(refund.events);
+                refunded += 1;
+            }
+            Err(err) => {
+                res = res.add_event(escrow_event("escrow_refund_skipped", &id).add_attribute("reason", err.to_string()));
+                skipped += 1;
+            }
+        }
+    }
+    Ok(res
+        .add_submessages(batch_payout_submsgs(deps.storage, msgs)?)
+        .add_attribute("refunded", refunded.to_string())
+        .add_attribute("skipped", skipped.to_string()))
+}
+
+// returns the whole balance, `arbiter` is set if an arbiter made the decision
+fn refund_escrow(
+    deps: DepsMut,
+    env: &Env,
+    mut escrow: Escrow,
+    id: &str,
+    arbiter: Option<&Addr>,
+) -> Result<Response, ContractError> {
+    let msgs = refund_msgs(deps.storage, env, id, &escrow, escrow.balance.clone(), arbiter)?;
+    let data = payout_data(id, &escrow.balance)?;
+    let event = escrow_event("escrow_refunded", id)
+        .add_attribute("to", escrow.refund_to())
+        .add_attribute("amount", escrow.balance.to_string());
+
+    record_action(deps.storage, env, id, "refund", arbiter, &escrow.balance)?;
+    escrow.settle(Status::Refunded);
+    archive_escrow(deps.storage, &escrow, id)?;
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_submessages(notify_subscribers(deps.storage, id, EscrowChange::Refunded)?)
+        .add_event(event)
+        .set_data(data)
+    )
+}
+
+fn try_accept(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    if info.sender != escrow.recipient {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: escrow.recipient.to_string(),
+        });
+    }
+    if escrow.status != Status::Pending || escrow.accepted {
+        return Err(ContractError::NotPending {});
+    }
+
+    escrow.accepted = true;
+    escrow.activate_if_ready();
+    escrows_save(deps.storage, &escrow, &id)?;
+    record_action(deps.storage, &env, &id, "accept", Some(&info.sender), &GenericBalance::default())?;
+    Ok(Response::new().add_event(
+        escrow_event("escrow_accepted", &id).add_attribute("status", escrow.status.as_str()),
+    ))
+}
+
+fn try_cancel(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    if info.sender != escrow.source {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: escrow.source.to_string(),
+        });
+    }
+    if !escrow.is_inactive() {
+        return Err(ContractError::NotPending {});
+    }
+
+    let msgs = refund_msgs(deps.storage, &env, &id, &escrow, escrow.balance.clone(), None)?;
+    let data = payout_data(&id, &escrow.balance)?;
+    let event = escrow_event("escrow_cancelled", &id)
+        .add_attribute("to", escrow.refund_to())
+        .add_attribute("amount", escrow.balance.to_string());
+
+    record_action(deps.storage, &env, &id, "cancel", Some(&info.sender), &escrow.balance)?;
+    escrow.settle(Status::Cancelled);
+    archive_escrow(deps.storage, &escrow, &id)?;
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_submessages(notify_subscribers(deps.storage, &id, EscrowChange::Refunded)?)
+        .add_event(event)
+        .set_data(data)
+    )
+}
+
+fn try_set_recipient(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    new_recipient: String,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    let by_arbiter = escrow.arbiter_can_set_recipient && escrow.is_arbiter(&info.sender);
+    if info.sender != escrow.recipient && !by_arbiter {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: format!("the recipient {}", escrow.recipient),
+        });
+    }
+
+    // the recipient's side of a swap is on record as theirs
+    if escrow.swap.is_some() {
+        return Err(ContractError::InvalidSwap {
+            reason: String::from("the recipient of a swap cannot change"),
+        });
+    }
+
+    let new_recipient = deps.api.addr_validate(&new_recipient)?;
+    let mut event = escrow_event("escrow_recipient_changed", &id)
+        .add_attribute("old_recipient", &escrow.recipient)
+        .add_attribute("new_recipient", &new_recipient)
+        .add_attribute("by", &info.sender);
+    // the address on the other chain belongs to the old recipient
+    if let Some(remote) = escrow.remote_recipient.take() {
+        event = event.add_attribute("cleared_remote_recipient", remote.address);
+    }
+    let old_recipient = std::mem::replace(&mut escrow.recipient, new_recipient);
+    // the share of the old recipient moves along
+    for (address, _) in escrow.splits.iter_mut().filter(|(a, _)| *a == old_recipient) {
+        *address = escrow.recipient.clone();
+    }
+    escrow.check_parties()?;
+    // the new recipient has not consented to anything yet
+    escrow.recipient_approved = false;
+    escrows_save(deps.storage, &escrow, &id)?;
+    record_action(deps.storage, &env, &id, "set_recipient", Some(&info.sender), &GenericBalance::default())?;
+
+    Ok(Response::new().add_event(event))
+}
+
+fn try_transfer_source_rights(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    new_source: String,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    if info.sender != escrow.source {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: format!("the source {}", escrow.source),
+        });
+    }
+
+    let new_source = deps.api.addr_validate(&new_source)?;
+    let mut event = escrow_event("escrow_source_changed", &id)
+        .add_attribute("old_source", &escrow.source)
+        .add_attribute("new_source", &new_source)
+        .add_attribute("recipient", &escrow.recipient);
+    // refunds follow the rights, not the wallet the old source picked
+    if let Some(refund_address) = escrow.refund_address.take() {
+        event = event.add_attribute("cleared_refund_address", refund_address);
+    }
+    if let Some(remote) = escrow.remote_refund_address.take() {
+        event = event.add_attribute("cleared_remote_refund_address", remote.address);
+    }
+    transfer_source(deps.storage, &id, &mut escrow, new_source)?;
+    escrow.check_parties()?;
+    // the new source has not consented to anything yet
+    escrow.source_approved = false;
+    escrows_save(deps.storage, &escrow, &id)?;
+    record_action(deps.storage, &env, &id, "transfer_source", Some(&info.sender), &GenericBalance::default())?;
+
+    Ok(Response::new().add_event(event))
+}
+
+fn try_extend_expiry(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    expires: Expiration,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    // only the party that would receive the tokens on expiry can give up on that
+    let beneficiary = match escrow.on_expiry {
+        OnExpiry::RefundToSource => &escrow.source,
+        OnExpiry::ReleaseToRecipient => &escrow.recipient,
+    };
+    if info.sender != *beneficiary {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: beneficiary.to_string(),
+        });
+    }
+
+    escrow.expires = extend(escrow.expires, expires)?;
+    if escrow.ping_interval.is_none() {
+        assert_max_duration(&CONFIG.load(deps.storage)?, &env.block, &escrow.expires)?;
+    }
+    escrows_save(deps.storage, &escrow, &id)?;
+    record_action(deps.storage, &env, &id, "extend_expiry", Some(&info.sender), &GenericBalance::default())?;
+
+    let event = escrow_event("escrow_expiry_extended", &id)
+        .add_attribute("expires", escrow.expires.to_string());
+    Ok(Response::new().add_event(event))
+}
+
+fn try_ping(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    if info.sender != escrow.source {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: escrow.source.to_string(),
+        });
+    }
+    let interval = escrow.ping_interval.ok_or(ContractError::NoSwitch {})?;
+    // a missed ping cannot be made up for, the escrow is the recipient's now
+    if escrow.is_expired(&env) {
+        return Err(ContractError::Expired {
+            expires: escrow.expires,
+        });
+    }
+
+    escrow.expires = interval.after(&env.block);
+    escrows_save(deps.storage, &escrow, &id)?;
+
+    let event = escrow_event("escrow_pinged", &id)
+        .add_attribute("expires", escrow.expires.to_string());
+    Ok(Response::new().add_event(event))
+}
+
+// subscribers are managed by the parties, so no one else can hold up their escrow
+fn try_subscribe(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: String,
+    subscriber: String,
+) -> Result<Response, ContractError> {
+    let escrow = load_unsettled_without_balance(deps.storage, &id)?;
+    if !is_party(&escrow, &info.sender) {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: String::from("the source, recipient or an arbiter"),
+        });
+    }
+    let subscriber = deps.api.addr_validate(&subscriber)?;
+    if !SUBSCRIBERS.has(deps.storage, (&id, &subscriber))
+        && escrow_subscribers(deps.storage, &id)?.len() >= MAX_SUBSCRIBERS as usize
+    {
+        return Err(ContractError::TooManySubscribers { max: MAX_SUBSCRIBERS });
+    }
+    SUBSCRIBERS.save(deps.storage, (&id, &subscriber), &Empty {})?;
+
+    Ok(Response::new().add_event(escrow_event("escrow_subscribed", &id).add_attribute("subscriber", subscriber)))
+}
+
+fn try_unsubscribe(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: String,
+    subscriber: String,
+) -> Result<Response, ContractError> {
+    let escrow = match archived_escrows_may_load(deps.storage, &id)? {
+        Some(archived) => archived,
+        None => escrows_read_without_balance(deps.storage, &id).map_err(|err| not_found(err, &id))?,
+    };
+    let subscriber = deps.api.addr_validate(&subscriber)?;
+    if info.sender != subscriber && !is_party(&escrow, &info.sender) {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: String::from("the subscriber, source, recipient or an arbiter"),
+        });
+    }
+    SUBSCRIBERS.remove(deps.storage, (&id, &subscriber));
+
+    Ok(Response::new().add_event(escrow_event("escrow_unsubscribed", &id).add_attribute("subscriber", subscriber)))
+}
+
+fn is_party(escrow: &Escrow, addr: &Addr) -> bool {
+    *addr == escrow.source || *addr == escrow.recipient || escrow.is_arbiter(addr)
+}
+
+// tells the subscribers of the escrow about a change, failures are ignored in `reply`
+fn notify_subscribers(storage: &dyn Storage, id: &str, change: EscrowChange) -> StdResult<Vec<SubMsg>> {
+    let msg = to_binary(&EscrowHookMsg::EscrowChanged {
+        id: id.to_string(),
+        change,
+    })?;
+    Ok(escrow_subscribers(storage, id)?
+        .into_iter()
+        .map(|subscriber| {
+            let hook = WasmMsg::Execute {
+                contract_addr: subscriber.into_string(),
+                msg: msg.clone(),
+                funds: vec![],
+            };
+            SubMsg::reply_on_error(hook, HOOK_REPLY_ID)
+        })
+        .collect())
+}
+
+// heights and times do not compare, and what never expires cannot be extended
+// with a maximum duration, the expiry has to be in its unit and no further out than it
+fn assert_max_duration(config: &Config, block: &BlockInfo, expires: &Expiration) -> Result<(), ContractError> {
+    let max = match config.max_duration {
+        Some(max) => max,
+        None => return Ok(()),
+    };
+    let within = match (max.after(block), expires) {
+        (Expiration::AtHeight(limit), Expiration::AtHeight(height)) => *height <= limit,
+        (Expiration::AtTime(limit), Expiration::AtTime(time)) => *time <= limit,
+        _ => false,
+    };
+    if !within {
+        return Err(ContractError::DurationTooLong { max });
+    }
+    Ok(())
+}
+
+fn extend(current: Expiration, new: Expiration) -> Result<Expiration, ContractError> {
+    match (current, new) {
+        (Expiration::Never {}, _) => Err(ContractError::InvalidExpiry {}),
+        (_, Expiration::Never {}) => Ok(new),
+        (current, new) if new > current => Ok(new),
+        _ => Err(ContractError::InvalidExpiry {}),
+    }
+}
+
+fn try_claim_refund(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    // refunds of escrows with pull refunds, which may be settled already
+    if let Some(claimable) = CLAIMABLE.may_load(deps.storage, (&id, &info.sender))? {
+        return withdraw_claimable(deps, &env, &id, &info.sender, claimable);
+    }
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    if !escrow.crowdfund || !escrow.is_inactive() {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: String::from("contributors of a failed crowdfund"),
+        });
+    }
+    if !escrow.is_failed_crowdfund(&env) {
+        return Err(ContractError::NotExpired {});
+    }
+
+    let contribution = CONTRIBUTIONS
+        .may_load(deps.storage, (&id, &info.sender))?
+        .ok_or(ContractError::NothingToClaim {})?;
+    CONTRIBUTIONS.remove(deps.storage, (&id, &info.sender));
+    // arbiters may have refunded part of the balance already
+    let mut claim = contribution.clone();
+    claim.sub_tokens(&escrow.balance.shortfall(&contribution))?;
+    if claim.is_empty() {
+        return Err(ContractError::NothingToClaim {});
+    }
+
+    escrow.balance.sub_tokens(&claim)?;
+    if escrow.balance.is_empty() {
+        escrow.settle(Status::Refunded);
+        archive_escrow(deps.storage, &escrow, &id)?;
+    } else {
+        escrows_save(deps.storage, &escrow, &id)?;
+    }
+
+    let to = if info.sender == escrow.source {
+        escrow.refund_to().clone()
+    } else {
+        info.sender.clone()
+    };
+    let event = escrow_event("escrow_refund_claimed", &id)
+        .add_attribute("funder", &info.sender)
+        .add_attribute("to", &to)
+        .add_attribute("amount", claim.to_string());
+    let data = payout_data(&id, &claim)?;
+    record_action(deps.storage, &env, &id, "claim_refund", Some(&info.sender), &claim)?;
+    let msgs = send_tokens(&to, &claim)?;
+
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_event(event)
+        .set_data(data)
+    )
+}
+
+fn try_claim(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    // tokens released by escrows with pull payouts, which may be settled already
+    if let Some(claimable) = CLAIMABLE.may_load(deps.storage, (&id, &info.sender))? {
+        return withdraw_claimable(deps, &env, &id, &info.sender, claimable);
+    }
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    if info.sender != escrow.recipient {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: escrow.recipient.to_string(),
+        });
+    }
+    assert_accepted(&escrow)?;
+    assert_started(&escrow, &env)?;
+    assert_not_in_grace_period(&escrow, &env)?;
+    assert_not_disputed(&escrow)?;
+
+    let claim = escrow.claimable(env.block.time.seconds());
+    if claim.is_empty() {
+        return Err(ContractError::NothingToClaim {});
+    }
+    escrow.balance.sub_tokens(&claim)?;
+    escrow.claimed.add_all(&claim)?;
+    // once everything is claimed, the escrow is settled
+    if escrow.balance.is_empty() {
+        escrow.settle(Status::Approved);
+        archive_escrow(deps.storage, &escrow, &id)?;
+    } else {
+        escrows_save(deps.storage, &escrow, &id)?;
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let event = escrow_event("escrow_claimed", &id)
+        .add_attribute("recipient", &escrow.recipient)
+        .add_attribute("amount", claim.to_string())
+        .add_attribute("remaining", escrow.balance.to_string());
+    let data = payout_data(&id, &claim)?;
+    record_action(deps.storage, &env, &id, "claim", Some(&info.sender), &claim)?;
+    let msgs = release_msgs(deps.storage, deps.querier, &env, &config, &id, &escrow, claim, None)?;
+
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_submessages(notify_subscribers(deps.storage, &id, EscrowChange::Approved)?)
+        .add_event(event)
+        .set_data(data)
+    )
+}
+
+fn try_stop_schedule(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    assert_arbiter(&escrow, &info.sender)?;
+    let now = env.block.time.seconds();
+    let started = match &escrow.schedule {
+        Some(schedule @ Schedule::Periodic { periods, .. }) => match schedule.periods_started(now) {
+            Some(started) if started < *periods => started,
+            _ => {
+                return Err(ContractError::InvalidSchedule {
+                    reason: String::from("all periods started already"),
+                })
+            }
+        },
+        _ => {
+            return Err(ContractError::InvalidSchedule {
+                reason: String::from("not a periodic schedule"),
+            })
+        }
+    };
+
+    // the started periods stay claimable, those left are refunded
+    let mut refund = escrow.balance.clone();
+    refund.sub_tokens(&escrow.claimable(now))?;
+    if let Some(Schedule::Periodic { periods, .. }) = escrow.schedule.as_mut() {
+        *periods = started;
+    }
+    escrow.balance.sub_tokens(&refund)?;
+    if escrow.balance.is_empty() {
+        escrow.settle(Status::Refunded);
+        archive_escrow(deps.storage, &escrow, &id)?;
+    } else {
+        escrows_save(deps.storage, &escrow, &id)?;
+    }
+
+    let event = escrow_event("escrow_schedule_stopped", &id)
+        .add_attribute("periods", started.to_string())
+        .add_attribute("to", escrow.refund_to())
+        .add_attribute("amount", refund.to_string())
+        .add_attribute("remaining", escrow.balance.to_string());
+    let data = payout_data(&id, &refund)?;
+    record_action(deps.storage, &env, &id, "stop_schedule", Some(&info.sender), &refund)?;
+    let msgs = refund_msgs(deps.storage, &env, &id, &escrow, refund, Some(&info.sender))?;
+
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_submessages(notify_subscribers(deps.storage, &id, EscrowChange::Refunded)?)
+        .add_event(event)
+        .set_data(data)
+    )
+}
+
+fn withdraw_claimable(
+    deps: DepsMut,
+    env: &Env,
+    id: &str,
+    owner: &Addr,
+    claimable: GenericBalance,
+) -> Result<Response, ContractError> {
+    remove_claimable(deps.storage, id, owner);
+    record_action(deps.storage, env, id, "withdraw_claimable", Some(owner), &claimable)?;
+
+    let event = escrow_event("escrow_payout_claimed", id)
+        .add_attribute("owner", owner)
+        .add_attribute("amount", claimable.to_string());
+    let data = payout_data(id, &claimable)?;
+    let msgs = send_tokens(owner, &claimable)?;
+
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_event(event)
+        .set_data(data)
+    )
+}
+
+fn try_claim_with_preimage(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    preimage: String,
+) -> Result<Response, ContractError> {
+    if load_unsettled(deps.storage, &id)?.hash_lock().is_none() {
+        return Err(ContractError::NoHashLock {});
+    }
+    try_fulfill(deps, env, info, id, Witness { preimage: Some(preimage) })
+}
+
+fn try_fulfill(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    witness: Witness,
+) -> Result<Response, ContractError> {
+    let escrow = load_unsettled(deps.storage, &id)?;
+
+    if info.sender != escrow.recipient {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: escrow.recipient.to_string(),
+        });
+    }
+    if escrow.conditions.is_empty() {
+        return Err(ContractError::NoConditions {});
+    }
+    assert_accepted(&escrow)?;
+    assert_started(&escrow, &env)?;
+    assert_not_in_grace_period(&escrow, &env)?;
+    assert_not_disputed(&escrow)?;
+    if escrow.is_release_blocked(&env) {
+        return Err(ContractError::Expired {
+            expires: escrow.expires,
+        });
+    }
+    check_conditions(deps.as_ref(), &env, &id, &escrow.conditions, &witness)?;
+
+    let mut event = escrow_event("escrow_fulfilled", &id);
+    // the revealed preimage lets the counterparty claim on the other side of a swap
+    if let (Some(preimage), Some(_)) = (witness.preimage, escrow.hash_lock()) {
+        event = event.add_attribute("preimage", preimage);
+    }
+    Ok(approve_escrow(deps, &env, escrow, &id, None)?.add_event(event))
+}
+
+fn try_raise_dispute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    reason: String,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    if info.sender != escrow.source && info.sender != escrow.recipient {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: String::from("the source or the recipient"),
+        });
+    }
+    assert_accepted(&escrow)?;
+    assert_not_disputed(&escrow)?;
+    // past the deadline the escrow settles according to on_expiry
+    if escrow.is_expired(&env) {
+        return Err(ContractError::Expired {
+            expires: escrow.expires,
+        });
+    }
+
+    let event = escrow_event("escrow_disputed", &id)
+        .add_attribute("raised_by", &info.sender)
+        .add_attribute("reason", &reason);
+    record_action(deps.storage, &env, &id, "dispute", Some(&info.sender), &GenericBalance::default())?;
+    escrow.status = Status::Disputed;
+    escrow.dispute = Some(Dispute {
+        raised_by: info.sender,
+        reason,
+        raised_at: env.block.time,
+        raised_at_height: env.block.height,
+        resolved_at: None,
+    });
+    escrows_save(deps.storage, &escrow, &id)?;
+
+    Ok(Response::new().add_event(event))
+}
+
+// disputed escrows are only settled by their arbiter(s)
+fn assert_not_disputed(escrow: &Escrow) -> Result<(), ContractError> {
+    if escrow.status == Status::Disputed {
+        return Err(ContractError::Disputed {});
+    }
+    assert_not_decided(escrow)
+}
+
+// decided disputes wait for the appeal window or the appellate arbiter
+fn assert_not_decided(escrow: &Escrow) -> Result<(), ContractError> {
+    if matches!(escrow.status, Status::Decided | Status::Appealed) {
+        return Err(ContractError::AppealPending {});
+    }
+    Ok(())
+}
+
+fn try_mutual_approve(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+    assert_accepted(&escrow)?;
+    assert_started(&escrow, &env)?;
+    assert_not_in_grace_period(&escrow, &env)?;
+    assert_not_disputed(&escrow)?;
+
+    if info.sender == escrow.source {
+        escrow.source_approved = true;
+    } else if info.sender == escrow.recipient {
+        escrow.recipient_approved = true;
+    } else {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: String::from("the source or the recipient"),
+        });
+    }
+    let event = escrow_event("escrow_mutual_consent", &id).add_attribute("party", &info.sender);
+
+    if !(escrow.source_approved && escrow.recipient_approved) {
+        escrows_save(deps.storage, &escrow, &id)?;
+        return Ok(Response::new().add_event(event));
+    }
+    let mut res = approve_escrow(deps, &env, escrow, &id, None)?;
+    res.events.insert(0, event);
+    Ok(res)
+}
+
+fn try_cast_vote(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    verdict: Verdict,
+) -> Result<Response, ContractError> {
+    let escrow = load_unsettled(deps.storage, &id)?;
+
+    if !escrow.is_arbiter(&info.sender) {
+        return Err(ContractError::NotArbiter {
+            sender: info.sender.into_string(),
+        });
+    }
+    assert_not_decided(&escrow)?;
+    if verdict == Verdict::Release {
+        assert_accepted(&escrow)?;
+        assert_started(&escrow, &env)?;
+    }
+    if verdict == Verdict::Release && escrow.is_release_blocked(&env) {
+        return Err(ContractError::Expired {
+            expires: escrow.expires,
+        });
+    }
+
+    VOTES.save(deps.storage, (&id, &info.sender), &verdict)?;
+    let votes = escrow_votes(deps.storage, &id)?
+        .into_iter()
+        .filter(|(_, v)| *v == verdict)
+        .count();
+    let event = escrow_event("escrow_vote_cast", &id)
+        .add_attribute("arbiter", &info.sender)
+        .add_attribute("verdict", verdict.to_string())
+        .add_attribute("votes", votes.to_string());
+
+    if votes < escrow.threshold as usize {
+        return Ok(Response::new().add_event(event));
+    }
+    // the deciding vote executes the verdict, that arbiter receives the arbiter fee
+    let mut res = match verdict {
+        Verdict::Release => {
+            decide(deps, &env, escrow, &id, Status::Approved, BPS_DENOMINATOR as u16, &info.sender)?
+        }
+        Verdict::Refund => decide(deps, &env, escrow, &id, Status::Refunded, 0, &info.sender)?,
+    };
+    res.events.insert(0, event);
+    Ok(res)
+}
+
+fn try_settle_expired(
+    deps: DepsMut,
+    env: Env,
+    id: String,
+) -> Result<Response, ContractError> {
+    let escrow = load_unsettled(deps.storage, &id)?;
+    assert_not_disputed(&escrow)?;
+    if !escrow.is_expired(&env) {
+        return Err(ContractError::NotExpired {});
+    }
+    assert_not_in_grace_period(&escrow, &env)?;
+    if escrow.is_failed_crowdfund(&env) {
+        return Err(ContractError::CrowdfundFailed {});
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let (msgs, event, _) = settle_expired(deps.storage, deps.querier, &env, &config, escrow, &id, &[])?;
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_submessages(notify_subscribers(deps.storage, &id, EscrowChange::Expired)?)
+        .add_event(event)
+    )
+}
+
+// pays out an expired escrow according to on_expiry and archives it. `keeper_reward` is
+// taken out of the escrow's own balance first, as far as it holds those denoms
+fn settle_expired(
+    storage: &mut dyn Storage,
+    querier: QuerierWrapper,
+    env: &Env,
+    config: &Config,
+    mut escrow: Escrow,
+    id: &str,
+    keeper_reward: &[Coin],
+) -> Result<(Vec<CosmosMsg>, Event, GenericBalance), ContractError> {
+    let reward = GenericBalance {
+        native: keeper_reward
+            .iter()
+            .filter_map(|reward| {
+                let held = escrow.balance.native.iter().find(|c| c.denom == reward.denom)?.amount;
+                let amount = reward.amount.min(held);
+                (!amount.is_zero()).then(|| Coin::new(amount.u128(), reward.denom.clone()))
+            })
+            .collect(),
+        ..GenericBalance::default()
+    };
+    escrow.balance.sub_tokens(&reward)?;
+
+    let msgs = expiry_msgs(storage, querier, env, id, config, &escrow)?;
+    let mut event = escrow_event("escrow_expired", id)
+        .add_attribute("on_expiry", escrow.on_expiry.to_string())
+        .add_attribute("amount", escrow.balance.to_string());
+    if !reward.is_empty() {
+        event = event.add_attribute("keeper_reward", reward.to_string());
+    }
+
+    record_action(storage, env, id, "expire", None, &escrow.balance)?;
+    escrow.settle(Status::Expired);
+    archive_escrow(storage, &escrow, id)?;
+    Ok((msgs, event, reward))
+}
+
+fn process_expirations(deps: DepsMut, env: Env, limit: u32) -> Result<Response, ContractError> {
+    let (msgs, events, _) = settle_expired_batch(deps.storage, deps.querier, &env, limit, false)?;
+    Ok(Response::new()
+        .add_attribute("action", "process_expirations")
+        .add_attribute("settled", events.len().to_string())
+        .add_submessages(msgs)
+        .add_events(events)
+    )
+}
+
+// like ProcessExpirations, the caller earns the keeper reward for every settled escrow,
+// paid by that escrow, so settling one's own escrows never pays more than they held
+fn try_crank(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    limit: u32,
+) -> Result<Response, ContractError> {
+    let (msgs, events, reward) = settle_expired_batch(deps.storage, deps.querier, &env, limit, true)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "crank")
+        .add_attribute("settled", events.len().to_string())
+        .add_attribute("reward", reward.to_string())
+        .add_submessages(msgs)
+        .add_messages(send_tokens(&info.sender, &reward)?)
+        .add_events(events)
+    )
+}
+
+// payouts nobody withdrew within the unclaimed timeout go to `to`, so settled escrows
+// do not hold on to them forever
+fn try_sweep_unclaimed(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    to: String,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    let to = deps.api.addr_validate(&to)?;
+    let config = CONFIG.load(deps.storage)?;
+    let timeout = config.unclaimed_timeout.ok_or(ContractError::NothingToSweep {})?;
+
+    let mut swept = GenericBalance::default();
+    let mut events = vec![];
+    for (owner, claimable) in escrow_claimable(deps.storage, &id)? {
+        let since = match CLAIMABLE_SINCE.may_load(deps.storage, (&id, &owner))? {
+            Some(since) if since.plus_seconds(timeout) <= env.block.time => since,
+            _ => continue,
+        };
+        remove_claimable(deps.storage, &id, &owner);
+        events.push(
+            escrow_event("escrow_unclaimed_swept", &id)
+                .add_attribute("owner", &owner)
+                .add_attribute("amount", claimable.to_string())
+                .add_attribute("since", since.seconds().to_string())
+                .add_attribute("to", &to),
+        );
+        swept.add_all(&claimable)?;
+    }
+    if events.is_empty() {
+        return Err(ContractError::NothingToSweep {});
+    }
+
+    let msgs = send_tokens(&to, &swept)?;
+    Ok(Response::new()
+        .add_attribute("action", "sweep_unclaimed")
+        .add_attribute("swept", events.len().to_string())
+        .add_attribute("amount", swept.to_string())
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_events(events)
+    )
+}
+
+// tokens sent to the contract by mistake, escrows and claimable payouts stay covered
+fn try_rescue_funds(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denom_or_token: String,
+    amount: Uint128,
+    to: String,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    let to = deps.api.addr_validate(&to)?;
+    if amount.is_zero() {
+        return Err(ContractError::ZeroBalance {});
+    }
+
+    let tracked = tracked_funds(deps.storage)?;
+    // a cw20 contract answers the balance query, anything else is a native denom
+    let cw20 = deps.api.addr_validate(&denom_or_token).ok().and_then(|token| {
+        let held = query_cw20_balance(deps.as_ref(), &token, &env.contract.address).ok()?;
+        Some((token, held))
+    });
+    let (held, locked, rescued) = match cw20 {
+        Some((token, held)) => {
+            let locked = tracked.cw20.iter().find(|c| c.address == token).map(|c| c.amount).unwrap_or_default();
+            let rescued = GenericBalance {
+                cw20: vec![Cw20CoinVerified { address: token, amount }],
+                ..GenericBalance::default()
+            };
+            (held, locked, rescued)
+        }
+        None => {
+            let held = deps.querier.query_balance(&env.contract.address, &denom_or_token)?.amount;
+            let locked = tracked.native.iter().find(|c| c.denom == denom_or_token).map(|c| c.amount).unwrap_or_default();
+            let rescued = GenericBalance {
+                native: vec![Coin { denom: denom_or_token.clone(), amount }],
+                ..GenericBalance::default()
+            };
+            (held, locked, rescued)
+        }
+    };
+    let available = held.saturating_sub(locked);
+    if amount > available {
+        return Err(ContractError::NotStray { token: denom_or_token, available });
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "rescue_funds")
+        .add_attribute("token", denom_or_token)
+        .add_attribute("amount", amount)
+        .add_attribute("to", &to)
+        .add_submessages(payout_submsgs(deps.storage, send_tokens(&to, &rescued)?)?)
+    )
+}
+
+// Settles up to `limit` expired escrows, those that expired first go first. The payouts
+// come before the subscriber notifications
+fn settle_expired_batch(
+    storage: &mut dyn Storage,
+    querier: QuerierWrapper,
+    env: &Env,
+    limit: u32,
+    reward_keeper: bool,
+) -> Result<(Vec<SubMsg>, Vec<Event>, GenericBalance), ContractError> {
+    if limit == 0 {
+        return Err(ContractError::ZeroLimit {});
+    }
+    let limit = limit.min(MAX_SETTLE_LIMIT) as usize;
+    let config = CONFIG.load(storage)?;
+    // escrows that cannot be settled yet are skipped, later calls come back to them once
+    // the cursor went past the last expired escrow
+    let cursor = EXPIRY_CURSOR.may_load(storage)?;
+    let mut last = None;
+    let mut candidates = vec![];
+    let mut ids = expired_escrow_ids(storage, &env.block, cursor.as_ref().map(|(expires, id)| (expires, id.as_str())));
+    for id in ids.by_ref().take(MAX_EXPIRY_SCAN) {
+        let id = id?;
+        let escrow = escrows_read(storage, &id)?;
+        last = Some((escrow.expires, id.clone()));
+        let settleable = matches!(escrow.status, Status::Pending | Status::Funding | Status::Open)
+            && escrow.grace_ends().is_expired(&env.block)
+            && !escrow.is_failed_crowdfund(env)
+            // releases are paused, the escrow stays for a later call
+            && !(config.releases_paused
+                && escrow.on_expiry == OnExpiry::ReleaseToRecipient
+                && !escrow.balance.is_empty());
+        if settleable {
+            candidates.push((id, escrow));
+        }
+        if candidates.len() == limit {
+            break;
+        }
+    }
+    let done = ids.next().is_none();
+    drop(ids);
+    match last {
+        Some(last) if !done => EXPIRY_CURSOR.save(storage, &last)?,
+        _ => EXPIRY_CURSOR.remove(storage),
+    }
+
+    let mut msgs = vec![];
+    let mut hooks = vec![];
+    let mut events = vec![];
+    let mut reward = GenericBalance::default();
+    let keeper_reward = if reward_keeper { &config.keeper_reward[..] } else { &[] };
+    for (id, escrow) in candidates {
+        let (mut escrow_msgs, event, paid) =
+            settle_expired(storage, querier, env, &config, escrow, &id, keeper_reward)?;
+        reward.add_all(&paid)?;
+        msgs.append(&mut escrow_msgs);
+        hooks.append(&mut notify_subscribers(storage, &id, EscrowChange::Expired)?);
+        events.push(event);
+    }
+    let mut msgs = payout_submsgs(storage, msgs)?;
+    msgs.append(&mut hooks);
+    Ok((msgs, events, reward))
+}
+
+// escrows may be counted twice or missed while a migration goes through them in steps
+fn assert_not_migrating(storage: &dyn Storage) -> Result<(), ContractError> {
+    if MIGRATION.may_load(storage)?.is_some() {
+        return Err(ContractError::MigrationInProgress {});
+    }
+    Ok(())
+}
+
+// loads an escrow that can still be acted upon
+fn load_unsettled(storage: &dyn Storage, id: &str) -> Result<Escrow, ContractError> {
+    if let Some(archived) = archived_escrows_may_load(storage, id)? {
+        return Err(ContractError::AlreadySettled {
+            status: archived.status,
+        });
+    }
+    escrows_read(storage, id).map_err(|err| not_found(err, id))
+}
+
+// like `load_unsettled`, but leaves the balance empty
+fn load_unsettled_without_balance(storage: &dyn Storage, id: &str) -> Result<Escrow, ContractError> {
+    if let Some(archived) = archived_escrows_may_load(storage, id)? {
+        return Err(ContractError::AlreadySettled {
+            status: archived.status,
+        });
+    }
+    escrows_read_without_balance(storage, id).map_err(|err| not_found(err, id))
+}
+
+// names the escrow that does not exist, other errors are passed on
+fn not_found(err: StdError, id: &str) -> ContractError {
+    match err {
+        StdError::NotFound { .. } => ContractError::NotFound { id: id.to_string() },
+        err => err.into(),
+    }
+}
+
+// pays out an expired escrow according to its on_expiry setting
+fn expiry_msgs(
+    storage: &mut dyn Storage,
+    querier: QuerierWrapper,
+    env: &Env,
+    id: &str,
+    config: &Config,
+    escrow: &Escrow,
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    match escrow.on_expiry {
+        OnExpiry::RefundToSource => refund_msgs(storage, env, id, escrow, escrow.balance.clone(), None),
+        OnExpiry::ReleaseToRecipient => {
+            release_msgs(storage, querier, env, config, id, escrow, escrow.balance.clone(), None)
+        }
+    }
+}
+
+// letters, digits and `-`, `_`, `.` need no escaping anywhere
+fn validate_id(id: &str) -> Result<(), ContractError> {
+    if id.is_empty() {
+        return Err(ContractError::EmptyId {});
+    }
+    if !(MIN_ID_LENGTH..=MAX_ID_LENGTH).contains(&id.len()) {
+        return Err(ContractError::InvalidIdLength {
+            min: MIN_ID_LENGTH,
+            max: MAX_ID_LENGTH,
+        });
+    }
+    if let Some(c) = id.chars().find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))) {
+        return Err(ContractError::InvalidIdChar { c });
+    }
+    Ok(())
+}
+
+// a sha256 is 32 bytes, stored lowercase so it compares consistently
+fn validate_terms_hash(hash: String) -> Result<String, ContractError> {
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ContractError::InvalidTermsHash {});
+    }
+    Ok(hash.to_ascii_lowercase())
+}
+
+// cw20 and cw721 contracts have to be on the contract allowlist
+fn assert_tokens_allowed(storage: &dyn Storage, balance: &GenericBalance) -> Result<(), ContractError> {
+    let contracts = balance
+        .cw20
+        .iter()
+        .map(|token| &token.address)
+        .chain(balance.nfts.iter().map(|(contract, _)| contract));
+    for contract in contracts {
+        if !is_token_allowed(storage, contract) {
+            return Err(ContractError::TokenNotAllowed {
+                token: contract.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn validate_bps(bps: u16) -> Result<u16, ContractError> {
+    if bps as u128 > BPS_DENOMINATOR {
+        return Err(ContractError::InvalidBps {});
+    }
+    Ok(bps)
+}
+
+// payouts reply on error, so a failing transfer is reported with the token it moved
+fn payout_submsgs(storage: &mut dyn Storage, msgs: Vec<CosmosMsg>) -> StdResult<Vec<SubMsg>> {
+    let tokens: Vec<String> = msgs.iter().map(payout_token).collect();
+    PAYOUT_TOKENS.save(storage, &tokens)?;
+    Ok(msgs
+        .into_iter()
+        .zip(PAYOUT_REPLY_ID..)
+        .map(|(msg, id)| SubMsg::reply_on_error(msg, id))
+        .collect())
+}
+
+// the payouts of several escrows settled in one transaction are numbered as one list,
+// so a failed payout is reported against its own token. Other submessages go after them
+fn batch_payout_submsgs(storage: &mut dyn Storage, submsgs: Vec<SubMsg>) -> StdResult<Vec<SubMsg>> {
+    let (payouts, mut others): (Vec<_>, Vec<_>) = submsgs.into_iter().partition(|msg| msg.id >= PAYOUT_REPLY_ID);
+    let mut msgs = payout_submsgs(storage, payouts.into_iter().map(|msg| msg.msg).collect())?;
+    msgs.append(&mut others);
+    Ok(msgs)
+}
+
+// the token a payout message moves: the denoms of a bank send, else the token contract
+fn payout_token(msg: &CosmosMsg) -> String {
+    match msg {
+        CosmosMsg::Bank(BankMsg::Send { amount, .. }) => {
+            amount.iter().map(|c| c.denom.as_str()).collect::<Vec<_>>().join(",")
+        }
+        CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) => contract_addr.clone(),
+        CosmosMsg::Ibc(IbcMsg::Transfer { amount, .. }) => amount.denom.clone(),
+        CosmosMsg::Stargate { type_url, value } if type_url == MSG_TRANSFER_TYPE_URL => {
+            transfer_denom(value).unwrap_or_default()
+        }
+        _ => String::new(),
+    }
+}
+
+fn payout_failed(deps: Deps, index: u64) -> Result<Response, ContractError> {
+    let tokens = PAYOUT_TOKENS.load(deps.storage)?;
+    Err(ContractError::PayoutFailed {
+        token: tokens.get(index as usize).cloned().unwrap_or_default(),
+    })
+}
+
+// this is a helper to move the tokens, so the business logic is easy to read
+fn send_tokens(
+    to_address: &Addr,
+    amount: &GenericBalance, 
+) -> StdResult<Vec<CosmosMsg>> {
+    send_tokens_with_hook(to_address, amount, None)
+}
+
+// with a hook, cw20 tokens are sent to the contract `to_address` along with it
+fn send_tokens_with_hook(
+    to_address: &Addr,
+    amount: &GenericBalance,
+    hook: Option<&Binary>,
+) -> StdResult<Vec<CosmosMsg>> {
+    let native_balance = &amount.native;
+    let mut msgs = if native_balance.is_empty() {
+        vec![]
+    } else {
+        vec![BankMsg::Send {
+            to_address: to_address.to_string(),
+            amount: native_balance.to_vec(),
+        }
+        .into()]
+    };
+
+    let cw20_balance = &amount.cw20;
+    let cw20_msgs: StdResult<Vec<_>> = cw20_balance
+        .iter()
+        .map(|c| {
+            let msg = match hook {
+                Some(hook) => Cw20ExecuteMsg::Send {
+                    contract: to_address.to_string(),
+                    amount: c.amount,
+                    msg: hook.clone(),
+                },
+                None => Cw20ExecuteMsg::Transfer {
+                    recipient: to_address.to_string(),
+                    amount: c.amount,
+                },
+            };
+            let exec = WasmMsg::Execute {
+                contract_addr: c.address.to_string(),
+                msg: to_binary(&msg)?,
+                funds: vec![],
+            };
+            Ok(exec.into())
+        })
+        .collect();
+
+    msgs.append(&mut cw20_msgs?);
+
+    let nft_msgs: StdResult<Vec<_>> = amount
+        .nfts
+        .iter()
+        .map(|(contract, token_id)| {
+            let msg = Cw721ExecuteMsg::TransferNft {
+                recipient: to_address.to_string(),
+                token_id: token_id.clone(),
+            };
+            let exec = WasmMsg::Execute {
+                contract_addr: contract.to_string(),
+                msg: to_binary(&msg)?,
+                funds: vec![],
+            };
+            Ok(exec.into())
+        })
+        .collect();
+
+    msgs.append(&mut nft_msgs?);
+
+    Ok(msgs)
+}
+
+
+/// Adds `added`, which the caller must have received from `sender` already, to the escrow
+pub fn try_top_up(
+    deps: DepsMut,
+    env: Env,
+    added: GenericBalance,
+    sender: &Addr,
+    id: String,
+) -> Result<Response, ContractError> {
+    assert_not_migrating(deps.storage)?;
+    if added.is_empty() {
+        return Err(ContractError::ZeroBalance{});
+    }
+    if CONFIG.load(deps.storage)?.paused {
+        return Err(ContractError::Paused {});
+    }
+
+    // only the deposited tokens are touched, the rest of the balance is not loaded
+    let mut escrow = load_unsettled_without_balance(deps.storage, &id)?;
+    if !escrow.may_fund(sender) {
+        return Err(ContractError::NotAllowedFunder {
+            sender: sender.to_string(),
+        });
+    }
+    // campaigns are closed after the deadline
+    if escrow.crowdfund && escrow.is_expired(&env) {
+        return Err(ContractError::Expired {
+            expires: escrow.expires,
+        });
+    }
+
+    assert_tokens_allowed(deps.storage, &added)?;
+    // ensure the token is on the whitelist
+    for token in added.cw20.iter() {
+        if !escrow.cw20_whitelist.iter().any(|t| t == token.address.as_str()) {
+            return Err(ContractError::UnregisteredTokens {
+                token: token.address.to_string(),
+            });
+        }
+    }
+
+    let (totals, new_token) = escrow_balance_add(deps.storage, &id, &added)?;
+    add_contribution(deps.storage, &id, sender, &added)?;
+    add_topped_up(deps.storage, &id, &added)?;
+    record_action(deps.storage, &env, &id, "top_up", Some(sender), &added)?;
+
+    let config = CONFIG.load(deps.storage)?;
+    if new_token && escrow_balance_read(deps.storage, &id)?.num_tokens() > config.max_tokens as usize {
+        return Err(ContractError::TooManyTokens {
+            max: config.max_tokens,
+        });
+    }
+
+    // reaching the funding target activates the escrow
+    if escrow.is_inactive() {
+        escrow.balance = escrow_balance_read(deps.storage, &id)?;
+        if escrow.activate_if_ready() {
+            escrows_save(deps.storage, &escrow, &id)?;
+        }
+    }
+
+    Ok(Response::new()
+        .add_submessages(notify_subscribers(deps.storage, &id, EscrowChange::Funded)?)
+        .add_event(
+            escrow_event("escrow_topped_up", &id)
+                .add_attribute("amount", added.to_string())
+                .add_attribute("balance", totals.to_string())
+                .add_attribute("status", escrow.status.as_str()),
+        ))
+}
+
+// the source and recipient of a swap, who can complete it or withdraw their side
+fn assert_swap_party(escrow: &Escrow, sender: &Addr) -> Result<(), ContractError> {
+    if escrow.swap.is_none() {
+        return Err(ContractError::NotASwap {});
+    }
+    if *sender != escrow.source && *sender != escrow.recipient {
+        return Err(ContractError::Unauthorized {
+            sender: sender.to_string(),
+            expected: String::from("the source or recipient of the swap"),
+        });
+    }
+    Ok(())
+}
+
+fn try_complete_swap(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    assert_swap_party(&escrow, &info.sender)?;
+    if escrow.status == Status::Pending {
+        return Err(ContractError::NotAccepted {});
+    }
+    if escrow.is_expired(&env) {
+        return Err(ContractError::Expired {
+            expires: escrow.expires,
+        });
+    }
+
+    // the recipient's deposit is on record, everything else is the source's side
+    let deposit = CONTRIBUTIONS
+        .may_load(deps.storage, (&id, &escrow.recipient))?
+        .unwrap_or_default();
+    let mut to_source = deposit.clone();
+    to_source.sub_tokens(&escrow.balance.shortfall(&deposit))?;
+    let mut to_recipient = escrow.balance.clone();
+    to_recipient.sub_tokens(&to_source)?;
+    let swap = escrow.swap.as_ref().ok_or(ContractError::NotASwap {})?;
+    let mut missing = to_recipient.shortfall(&swap.offered);
+    missing.add_all(&to_source.shortfall(&swap.wanted))?;
+    if !missing.is_empty() {
+        return Err(ContractError::SwapIncomplete {
+            missing: missing.to_string(),
+        });
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let event = escrow_event("escrow_swapped", &id)
+        .add_attribute("by", &info.sender)
+        .add_attribute("to_recipient", to_recipient.to_string())
+        .add_attribute("to_source", to_source.to_string());
+    let data = payout_data(&id, &escrow.balance)?;
+    let mut msgs = release_msgs(deps.storage, deps.querier, &env, &config, &id, &escrow, to_recipient, None)?;
+    // the protocol fee is taken from both sides
+    if !FEE_EXEMPT.has(deps.storage, &escrow.source) {
+        let fee = to_source.split_bps(escrow.fee_bps);
+        msgs.append(&mut send_tokens(&config.fee_collector, &fee)?);
+    }
+    msgs.append(&mut send_tokens(&escrow.source, &to_source)?);
+
+    record_action(deps.storage, &env, &id, "swap", Some(&info.sender), &escrow.balance)?;
+    escrow.settle(Status::Approved);
+    archive_escrow(deps.storage, &escrow, &id)?;
+
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_submessages(notify_subscribers(deps.storage, &id, EscrowChange::Approved)?)
+        .add_event(event)
+        .set_data(data)
+    )
+}
+
+fn try_withdraw_swap_deposit(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    let mut escrow = load_unsettled(deps.storage, &id)?;
+
+    assert_swap_party(&escrow, &info.sender)?;
+
+    let deposit = CONTRIBUTIONS
+        .may_load(deps.storage, (&id, &info.sender))?
+        .ok_or(ContractError::NothingToClaim {})?;
+    CONTRIBUTIONS.remove(deps.storage, (&id, &info.sender));
+    // arbiters may have refunded part of the balance already
+    let mut withdrawn = deposit.clone();
+    withdrawn.sub_tokens(&escrow.balance.shortfall(&deposit))?;
+    if withdrawn.is_empty() {
+        return Err(ContractError::NothingToClaim {});
+    }
+
+    escrow.balance.sub_tokens(&withdrawn)?;
+    if escrow.balance.is_empty() {
+        escrow.settle(Status::Cancelled);
+        archive_escrow(deps.storage, &escrow, &id)?;
+    } else {
+        escrows_save(deps.storage, &escrow, &id)?;
+    }
+
+    let to = if info.sender == escrow.source {
+        escrow.refund_to().clone()
+    } else {
+        info.sender.clone()
+    };
+    let event = escrow_event("escrow_swap_withdrawn", &id)
+        .add_attribute("by", &info.sender)
+        .add_attribute("to", &to)
+        .add_attribute("amount", withdrawn.to_string())
+        .add_attribute("remaining", escrow.balance.to_string());
+    let data = payout_data(&id, &withdrawn)?;
+    record_action(deps.storage, &env, &id, "withdraw", Some(&info.sender), &withdrawn)?;
+    let msgs = send_tokens(&to, &withdrawn)?;
+
+    Ok(Response::new()
+        .add_submessages(payout_submsgs(deps.storage, msgs)?)
+        .add_submessages(notify_subscribers(deps.storage, &id, EscrowChange::Refunded)?)
+        .add_event(event)
+        .set_data(data)
+    )
+}
+
+fn try_register_arbiter(
+    deps: DepsMut,
+    info: MessageInfo,
+    fee_bps: u16,
+    categories: Vec<String>,
+) -> Result<Response, ContractError> {
+    validate_bps(fee_bps)?;
+    let mut bond = ARBITER_REGISTRY
+        .may_load(deps.storage, &info.sender)?
+        .map(|profile| profile.bond)
+        .unwrap_or_default();
+    bond.add_tokens(Balance::from(info.funds))?;
+
+    let event = Event::new("arbiter_registered")
+        .add_attribute("arbiter", &info.sender)
+        .add_attribute("fee_bps", fee_bps.to_string())
+        .add_attribute("categories", categories.join(","))
+        .add_attribute("bond", bond.to_string());
+    let profile = ArbiterProfile {
+        fee_bps,
+        categories,
+        bond,
+    };
+    ARBITER_REGISTRY.save(deps.storage, &info.sender, &profile)?;
+
+    Ok(Response::new().add_event(event))
+}
+
+fn try_unregister_arbiter(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let profile = ARBITER_REGISTRY
+        .may_load(deps.storage, &info.sender)?
+        .ok_or_else(|| ContractError::ArbiterNotRegistered {
+            arbiter: info.sender.to_string(),
+        })?;
+    // the bond backs the escrows they still have to decide
+    if !escrow_ids_by_arbiter(deps.storage, &info.sender, None, 1)?.is_empty() {
+        return Err(ContractError::ArbiterBusy {});
+    }
+    ARBITER_REGISTRY.remove(deps.storage, &info.sender);
+
+    let event = Event::new("arbiter_unregistered")
+        .add_attribute("arbiter", &info.sender)
+        .add_attribute("bond", profile.bond.to_string());
+    Ok(Response::new()
+        .add_messages(send_tokens(&info.sender, &profile.bond)?)
+        .add_event(event)
+    )
+}
+
+fn try_register_template(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: String,
+    template: TemplateMsg,
+) -> Result<Response, ContractError> {
+    validate_id(&id)?;
+    if let Some(existing) = TEMPLATES.may_load(deps.storage, &id)? {
+        if existing.owner != info.sender {
+            return Err(ContractError::Unauthorized {
+                sender: info.sender.to_string(),
+                expected: format!("the template owner {}", existing.owner),
+            });
+        }
+    }
+
+    let arbiters = validate_arbiters(deps.as_ref(), template.arbiter)?;
+    let threshold = template.threshold.unwrap_or(1);
+    if threshold == 0 || threshold as usize > arbiters.len() {
+        return Err(ContractError::InvalidThreshold {});
+    }
+    let template = Template {
+        owner: info.sender.clone(),
+        arbiters,
+        threshold,
+        arbiter_fee_bps: validate_bps(template.arbiter_fee_bps.unwrap_or_default())?,
+        duration: template.duration,
+        tokens: template.tokens.unwrap_or_default(),
+    };
+    TEMPLATES.save(deps.storage, &id, &template)?;
+
+    let event = Event::new("template_registered")
+        .add_attribute("template_id", &id)
+        .add_attribute("owner", &info.sender);
+    Ok(Response::new().add_event(event))
+}
+
+fn try_remove_template(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    let template = load_template(deps.storage, &id)?;
+    if template.owner != info.sender {
+        assert_owner(deps.storage, &info.sender)?;
+    }
+    TEMPLATES.remove(deps.storage, &id);
+
+    let event = Event::new("template_removed")
+        .add_attribute("template_id", &id)
+        .add_attribute("by", &info.sender);
+    Ok(Response::new().add_event(event))
+}
+
+fn load_template(storage: &dyn Storage, id: &str) -> Result<Template, ContractError> {
+    TEMPLATES
+        .may_load(storage, id)?
+        .ok_or_else(|| ContractError::TemplateNotFound {
+            template_id: id.to_string(),
+        })
+}
+
+// the escrow a template describes, if it accepts the tokens of `balance`
+fn template_create_msg(
+    storage: &dyn Storage,
+    env: &Env,
+    msg: CreateFromTemplateMsg,
+    balance: &GenericBalance,
+) -> Result<CreateMsg, ContractError> {
+    let template = load_template(storage, &msg.template_id)?;
+    if !template.tokens.is_empty() {
+        let tokens = balance
+            .native
+            .iter()
+            .map(|coin| coin.denom.clone())
+            .chain(balance.cw20.iter().map(|token| token.address.to_string()))
+            .chain(balance.nfts.iter().map(|(contract, _)| contract.to_string()));
+        for token in tokens {
+            if !template.tokens.contains(&token) {
+                return Err(ContractError::TokenNotInTemplate {
+                    template_id: msg.template_id,
+                    token,
+                });
+            }
+        }
+    }
+
+    let overrides = msg.overrides.unwrap_or_default();
+    let expires = overrides
+        .expires
+        .or_else(|| template.duration.map(|duration| duration.after(&env.block)))
+        .unwrap_or_default();
+    Ok(CreateMsg {
+        id: msg.id,
+        namespaced: overrides.namespaced,
+        arbiter: Arbiters::Many(template.arbiters.into_iter().map(Addr::into_string).collect()),
+        threshold: Some(template.threshold),
+        recipient: msg.recipient,
+        expires,
+        arbiter_fee_bps: Some(template.arbiter_fee_bps),
+        refund_address: overrides.refund_address,
+        title: overrides.title,
+        description: overrides.description,
+        metadata: overrides.metadata,
+        terms_hash: overrides.terms_hash,
+        ..CreateMsg::default()
+    })
+}
+
+// admin-gated settings can only be changed by the owner
+fn assert_owner(storage: &dyn Storage, sender: &Addr) -> Result<(), ContractError> {
+    if OWNERSHIP.load(storage)?.owner.as_ref() != Some(sender) {
+        return Err(ContractError::Unauthorized {
+            sender: sender.to_string(),
+            expected: String::from("the owner"),
+        });
+    }
+    Ok(())
+}
+
+fn try_update_ownership(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    action: OwnershipAction,
+) -> Result<Response, ContractError> {
+    let mut ownership = OWNERSHIP.load(deps.storage)?;
+    match action {
+        OwnershipAction::TransferOwnership { new_owner, expiry } => {
+            assert_owner(deps.storage, &info.sender)?;
+            if matches!(expiry, Some(expiry) if expiry.is_expired(&env.block)) {
+                return Err(ContractError::TransferExpired {});
+            }
+            ownership.pending_owner = Some(deps.api.addr_validate(&new_owner)?);
+            ownership.pending_expiry = expiry;
+        }
+        OwnershipAction::AcceptOwnership => {
+            if ownership.pending_owner.as_ref() != Some(&info.sender) {
+                return Err(ContractError::NotPendingOwner {});
+            }
+            if matches!(ownership.pending_expiry, Some(expiry) if expiry.is_expired(&env.block)) {
+                return Err(ContractError::TransferExpired {});
+            }
+            ownership = Ownership {
+                owner: Some(info.sender),
+                ..Ownership::default()
+            };
+        }
+        OwnershipAction::RenounceOwnership => {
+            assert_owner(deps.storage, &info.sender)?;
+            ownership = Ownership::default();
+        }
+    }
+    OWNERSHIP.save(deps.storage, &ownership)?;
+
+    let optional = |addr: &Option<Addr>| addr.as_ref().map_or_else(|| "none".to_string(), Addr::to_string);
+    Ok(Response::new()
+        .add_attribute("action", "update_ownership")
+        .add_attribute("owner", optional(&ownership.owner))
+        .add_attribute("pending_owner", optional(&ownership.pending_owner))
+        .add_attribute(
+            "pending_expiry",
+            ownership.pending_expiry.map_or_else(|| "none".to_string(), |expiry| expiry.to_string()),
+        )
+    )
+}
+
+fn try_pause(
+    deps: DepsMut,
+    info: MessageInfo,
+    block_releases: bool,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    pause(deps.storage, block_releases)
+}
+
+fn pause(storage: &mut dyn Storage, block_releases: bool) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(storage)?;
+    config.paused = true;
+    config.releases_paused = block_releases;
+    CONFIG.save(storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "pause")
+        .add_attribute("block_releases", block_releases.to_string()))
+}
+
+fn try_unpause(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    unpause(deps.storage)
+}
+
+fn unpause(storage: &mut dyn Storage) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(storage)?;
+    config.paused = false;
+    config.releases_paused = false;
+    CONFIG.save(storage, &config)?;
+
+    Ok(Response::new().add_attribute("action", "unpause"))
+}
+
+fn try_update_token_allowlist(
+    deps: DepsMut,
+    info: MessageInfo,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+
+    for token in add {
+        let token = deps.api.addr_validate(&token)?;
+        TOKEN_ALLOWLIST.save(deps.storage, &token, &Empty {})?;
+    }
+    for token in remove {
+        let token = deps.api.addr_validate(&token)?;
+        TOKEN_ALLOWLIST.remove(deps.storage, &token);
+    }
+
+    Ok(Response::new().add_attribute("action", "update_token_allowlist"))
+}
+
+fn try_update_fee_exempt(
+    deps: DepsMut,
+    info: MessageInfo,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+
+    for address in add {
+        let address = deps.api.addr_validate(&address)?;
+        FEE_EXEMPT.save(deps.storage, &address, &Empty {})?;
+    }
+    for address in remove {
+        let address = deps.api.addr_validate(&address)?;
+        FEE_EXEMPT.remove(deps.storage, &address);
+    }
+
+    Ok(Response::new().add_attribute("action", "update_fee_exempt"))
+}
+
+fn try_update_open_limit_exempt(
+    deps: DepsMut,
+    info: MessageInfo,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+
+    for source in add {
+        let source = deps.api.addr_validate(&source)?;
+        OPEN_LIMIT_EXEMPT.save(deps.storage, &source, &Empty {})?;
+    }
+    for source in remove {
+        let source = deps.api.addr_validate(&source)?;
+        OPEN_LIMIT_EXEMPT.remove(deps.storage, &source);
+    }
+
+    Ok(Response::new().add_attribute("action", "update_open_limit_exempt"))
+}
+
+fn try_update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    msg: UpdateConfigMsg,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    update_config(deps, msg)
+}
+
+fn try_bind_arbiter_channel(
+    deps: DepsMut,
+    info: MessageInfo,
+    channel_id: String,
+    arbiter: Option<String>,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    let res = Response::new()
+        .add_attribute("action", "bind_arbiter_channel")
+        .add_attribute("channel_id", &channel_id);
+    match arbiter {
+        Some(arbiter) => {
+            let arbiter = deps.api.addr_validate(&arbiter)?;
+            ARBITER_CHANNELS.save(deps.storage, &channel_id, &arbiter)?;
+            Ok(res.add_attribute("arbiter", arbiter))
+        }
+        None => {
+            ARBITER_CHANNELS.remove(deps.storage, &channel_id);
+            Ok(res)
+        }
+    }
+}
+
+// dispatched by `ibc_packet_receive`, so a failure is rolled back and acknowledged as such
+fn try_ibc_arbitrate(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    packet: ArbitrationPacket,
+) -> Result<Response, ContractError> {
+    if info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized {
+            sender: info.sender.to_string(),
+            expected: String::from("the contract itself"),
+        });
+    }
+    let arbiter = ARBITER_CHANNELS
+        .may_load(deps.storage, &channel_id)?
+        .ok_or(ContractError::UnboundChannel { channel_id })?;
+    let info = MessageInfo {
+        sender: arbiter,
+        funds: vec![],
+    };
+    match packet {
+        ArbitrationPacket::Approve { id } => try_approve(deps, env, info, id),
+        ArbitrationPacket::Refund { id } => try_refund(deps, env, info, id),
+    }
+}
+
+pub(crate) fn ack_success() -> StdResult<Binary> {
+    to_binary(&ArbitrationAck::Result(Binary::from(vec![1])))
+}
+
+pub(crate) fn ack_fail(err: String) -> StdResult<Binary> {
+    to_binary(&ArbitrationAck::Error(err))
+}
+
+fn update_config(deps: DepsMut, msg: UpdateConfigMsg) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    if let Some(max_tokens) = msg.max_tokens {
+        config.max_tokens = max_tokens;
+    }
+    if let Some(fee_bps) = msg.fee_bps {
+        config.fee_bps = validate_bps(fee_bps)?;
+    }
+    if let Some(collector) = msg.fee_collector {
+        config.fee_collector = deps.api.addr_validate(&collector)?;
+    }
+    if let Some(appellate_arbiter) = msg.appellate_arbiter {
+        config.appellate_arbiter = Some(deps.api.addr_validate(&appellate_arbiter)?);
+    }
+    if let Some(appeal_window) = msg.appeal_window {
+        config.appeal_window = appeal_window;
+    }
+    if let Some(keeper_reward) = msg.keeper_reward {
+        config.keeper_reward = keeper_reward;
+    }
+    if let Some(unclaimed_timeout) = msg.unclaimed_timeout {
+        config.unclaimed_timeout = (unclaimed_timeout > 0).then_some(unclaimed_timeout);
+    }
+    if let Some(creation_fee) = msg.creation_fee {
+        config.creation_fee = (!creation_fee.amount.is_zero()).then_some(creation_fee);
+    }
+    if let Some(strict_parties) = msg.strict_parties {
+        config.strict_parties = strict_parties;
+    }
+    if let Some(receipt_nft) = msg.receipt_nft {
+        config.receipt_nft = match receipt_nft.as_str() {
+            "" => None,
+            nft => Some(deps.api.addr_validate(nft)?),
+        };
+    }
+    if let Some(max_open_per_source) = msg.max_open_per_source {
+        config.max_open_per_source = (max_open_per_source > 0).then_some(max_open_per_source);
+    }
+    if let Some(max_duration) = msg.max_duration {
+        config.max_duration = (max_duration != Duration::Height(0) && max_duration != Duration::Time(0))
+            .then_some(max_duration);
+    }
+    if let Some(arbiter_relayers) = msg.arbiter_relayers {
+        config.arbiter_relayers = arbiter_relayers
+            .iter()
+            .map(|relayer| deps.api.addr_validate(relayer))
+            .collect::<StdResult<_>>()?;
+    }
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attribute("action", "update_config"))
+}
+
+pub fn query_resolve_id(deps: Deps, creator: Option<String>, id: String) -> StdResult<ResolveIdResponse> {
+    let id = match creator {
+        Some(creator) => namespaced_id(&deps.api.addr_validate(&creator)?, &id),
+        None => id,
+    };
+    if archived_escrows_may_load(deps.storage, &id)?.is_none() {
+        escrows_read_without_balance(deps.storage, &id)?;
+    }
+    Ok(ResolveIdResponse { id })
+}
+
+pub fn query_details(
+    deps: Deps,
+    env: Env,
+    id: String,
+) -> StdResult<DetailsResponse> {
+    let escrow = match archived_escrows_may_load(deps.storage, &id)? {
+        Some(archived) => archived,
+        None => escrows_read(deps.storage, &id)?,
+    };
+    let is_expired = escrow.is_expired(&env);
+
+    // transform tokens
+    let native_balance = escrow.balance.native;
+
+    let cw20_balance: StdResult<Vec<_>> = escrow
+        .balance
+        .cw20
+        .into_iter()
+        .map(|token| {
+            Ok(Cw20Coin {
+                address: token.address.to_string(),
+                amount: token.amount,
+            })
+        })
+        .collect();
+
+    let topped_up = TOPPED_UP.may_load(deps.storage, &id)?.unwrap_or_default();
+    let (swap_offered, swap_for) = match escrow.swap {
+        Some(swap) => (Some(swap.offered.into()), Some(swap.wanted.into())),
+        None => (None, None),
+    };
+    let details = DetailsResponse {
+        id,
+        arbiters: escrow.arbiters.into_iter().map(Addr::into_string).collect(),
+        threshold: escrow.threshold,
+        recipient: escrow.recipient.into_string(),
+        source: escrow.source.into_string(),
+        expires: escrow.expires,
+        start: escrow.start,
+        grace_period: escrow.grace_period,
+        ping_interval: escrow.ping_interval,
+        release_hook: escrow.release_hook,
+        payout_callback: escrow.payout_callback,
+        pull_payouts: escrow.pull_payouts,
+        pull_refunds: escrow.pull_refunds,
+        created_at_height: escrow.created_at_height,
+        created_at_time: escrow.created_at_time,
+        topped_up: topped_up.into(),
+        is_expired,
+        remote_recipient: escrow.remote_recipient,
+        remote_refund_address: escrow.remote_refund_address,
+        arbiter_proxy: escrow.arbiter_proxy.map(Addr::into_string),
+        strict_parties: escrow.strict_parties,
+        receipt: escrow.receipt.map(Addr::into_string),
+        splits: escrow.splits.into_iter().map(|(address, bps)| (address.into_string(), bps)).collect(),
+        swap_offered,
+        swap_for,
+        native_balance,
+        cw20_balance: cw20_balance?,
+        nft_balance: escrow
+            .balance
+            .nfts
+            .into_iter()
+            .map(|(contract, token_id)| (contract.into_string(), token_id))
+            .collect(),
+        cw20_whitelist: escrow.cw20_whitelist,
+        arbiter_fee_bps: escrow.arbiter_fee_bps,
+        refund_address: escrow.refund_address.map(Addr::into_string),
+        on_expiry: escrow.on_expiry,
+        status: escrow.status,
+        title: escrow.title,
+        description: escrow.description,
+        metadata: escrow.metadata,
+        terms_hash: escrow.terms_hash,
+        source_approved: escrow.source_approved,
+        recipient_approved: escrow.recipient_approved,
+        accepted: escrow.accepted,
+        funding_target: escrow.funding_target.map(TokenAmounts::from),
+        arbiter_can_set_recipient: escrow.arbiter_can_set_recipient,
+        schedule: escrow.schedule,
+        claimed: escrow.claimed.into(),
+        allowed_funders: escrow
+            .allowed_funders
+            .map(|funders| funders.into_iter().map(Addr::into_string).collect()),
+        crowdfund: escrow.crowdfund,
+        conditions: escrow.conditions,
+        dispute: escrow.dispute,
+        decision: escrow.decision,
+    };
+    Ok(details)
+}
+
+pub fn query_votes(deps: Deps, id: String) -> StdResult<VotesResponse> {
+    let votes = escrow_votes(deps.storage, &id)?
+        .into_iter()
+        .map(|(arbiter, verdict)| VoteInfo {
+            arbiter: arbiter.into_string(),
+            verdict,
+        })
+        .collect();
+    Ok(VotesResponse { votes })
+}
+
+pub fn query_contributions(deps: Deps, id: String) -> StdResult<ContributionsResponse> {
+    let contributions = escrow_contributions(deps.storage, &id)?
+        .into_iter()
+        .map(|(funder, amounts)| ContributionInfo {
+            funder: funder.into_string(),
+            amounts: amounts.into(),
+        })
+        .collect();
+    Ok(ContributionsResponse { contributions })
+}
+
+pub fn query_history(
+    deps: Deps,
+    id: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<HistoryResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let history = escrow_history(deps.storage, &id, start_after, limit)?
+        .into_iter()
+        .map(|(seq, action)| HistoryEntry {
+            seq,
+            action: action.action,
+            actor: action.actor.map(Addr::into_string),
+            amount: action.amount.into(),
+            height: action.height,
+            time: action.time,
+        })
+        .collect();
+    Ok(HistoryResponse { history })
+}
+
+pub fn query_claimable(deps: Deps, id: String) -> StdResult<ClaimableResponse> {
+    let claimable = escrow_claimable(deps.storage, &id)?
+        .into_iter()
+        .map(|(owner, amounts)| ClaimableInfo {
+            owner: owner.into_string(),
+            amounts: amounts.into(),
+        })
+        .collect();
+    Ok(ClaimableResponse { claimable })
+}
+
+pub fn query_is_expired(deps: Deps, env: Env, id: String) -> StdResult<IsExpiredResponse> {
+    let escrow = match archived_escrows_may_load(deps.storage, &id)? {
+        Some(archived) => archived,
+        None => escrows_read_without_balance(deps.storage, &id)?,
+    };
+    Ok(IsExpiredResponse {
+        id,
+        expired: escrow.is_expired(&env),
+        expires_at: escrow.expires,
+    })
+}
+
+pub fn query_funding(deps: Deps, id: String) -> StdResult<FundingResponse> {
+    let escrow = match archived_escrows_may_load(deps.storage, &id)? {
+        Some(archived) => archived,
+        None => escrows_read(deps.storage, &id)?,
+    };
+    let missing = match &escrow.funding_target {
+        Some(target) => escrow.balance.shortfall(target),
+        None => GenericBalance::default(),
+    };
+    Ok(FundingResponse {
+        id,
+        status: escrow.status,
+        funded: escrow.is_funded(),
+        target: escrow.funding_target.map(TokenAmounts::from),
+        balance: escrow.balance.into(),
+        missing: missing.into(),
+    })
+}
+
+pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let ownership = OWNERSHIP.load(deps.storage)?;
+    let token_allowlist = allowed_tokens(deps.storage)?;
+    Ok(ConfigResponse {
+        owner: ownership.owner.map(Addr::into_string),
+        token_allowlist: token_allowlist.into_iter().map(Addr::into_string).collect(),
+        max_tokens: config.max_tokens,
+        fee_bps: config.fee_bps,
+        fee_collector: config.fee_collector.into_string(),
+        appellate_arbiter: config.appellate_arbiter.map(Addr::into_string),
+        appeal_window: config.appeal_window,
+        paused: config.paused,
+        releases_paused: config.releases_paused,
+        keeper_reward: config.keeper_reward,
+        unclaimed_timeout: config.unclaimed_timeout,
+        arbiter_relayers: config.arbiter_relayers.into_iter().map(Addr::into_string).collect(),
+        max_duration: config.max_duration,
+        max_open_per_source: config.max_open_per_source,
+        creation_fee: config.creation_fee,
+        strict_parties: config.strict_parties,
+        receipt_nft: config.receipt_nft.map(Addr::into_string),
+    })
+}
+
+pub fn query_open_escrows(deps: Deps, source: String) -> StdResult<OpenEscrowsResponse> {
+    let source = deps.api.addr_validate(&source)?;
+    Ok(OpenEscrowsResponse {
+        open: open_escrows_of(deps.storage, &source)?,
+        exempt: OPEN_LIMIT_EXEMPT.has(deps.storage, &source),
+    })
+}
+
+pub fn query_count(deps: Deps, status: Option<Status>, arbiter: Option<String>) -> StdResult<CountResponse> {
+    let arbiter = arbiter.map(|arbiter| deps.api.addr_validate(&arbiter)).transpose()?;
+    Ok(CountResponse {
+        count: count_escrows(deps.storage, status, arbiter.as_ref())?,
+    })
+}
+
+pub fn query_stats(deps: Deps) -> StdResult<StatsResponse> {
+    Ok(StatsResponse {
+        total_locked: total_locked(deps.storage)?.into(),
+        counts: ESCROW_COUNTS.may_load(deps.storage)?.unwrap_or_default(),
+    })
+}
+
+pub fn query_list(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+    status: Option<Status>,
+    arbiter: Option<String>,
+) -> StdResult<ListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let arbiter = arbiter.map(|arbiter| deps.api.addr_validate(&arbiter)).transpose()?;
+    let escrows = match (status, arbiter) {
+        (Some(status), Some(arbiter)) => {
+            escrow_ids_by_status_and_arbiter(deps.storage, status, &arbiter, start_after, limit)?
+        }
+        (None, Some(arbiter)) => escrow_ids_by_arbiter(deps.storage, &arbiter, start_after, limit)?,
+        (Some(status), None) if status.is_settled() => {
+            archived_escrow_ids(deps.storage, Some(status), start_after, limit)?
+        }
+        (Some(status), None) => escrow_ids_by_status(deps.storage, status, start_after, limit)?,
+        (None, None) => all_escrow_ids(deps.storage, start_after, limit)?,
+    };
+    Ok(ListResponse { escrows })
+}
+
+pub fn query_list_archived(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    Ok(ListResponse {
+        escrows: archived_escrow_ids(deps.storage, None, start_after, limit)?,
+    })
+}
+
+pub fn query_list_by_arbiter(
+    deps: Deps,
+    arbiter: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let arbiter = deps.api.addr_validate(&arbiter)?;
+    Ok(ListResponse {
+        escrows: escrow_ids_by_arbiter(deps.storage, &arbiter, start_after, limit)?,
+    })
+}
+
+pub fn query_list_expiring_between(
+    deps: Deps,
+    from: Expiration,
+    to: Expiration,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let (deadline, from, to) = match (from, to) {
+        (Expiration::AtHeight(from), Expiration::AtHeight(to)) => (Deadline::Height, from, to),
+        (Expiration::AtTime(from), Expiration::AtTime(to)) => (Deadline::Time, from.nanos(), to.nanos()),
+        _ => return Err(StdError::generic_err("from and to have to be both heights or both times")),
+    };
+
+    let cursor = match &start_after {
+        Some(id) => Some((deadline.key(&cursor_expiration(deps.storage, id)?), id.as_str())),
+        None => None,
+    };
+    Ok(ListResponse {
+        escrows: escrows_by_deadline(deps.storage, deadline, Some(from), cursor, to)
+            .map(|elem| elem.map(|(id, _)| id))
+            .take(limit)
+            .collect::<StdResult<_>>()?,
+    })
+}
+
+pub fn query_list_by_token(
+    deps: Deps,
+    denom_or_cw20: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    Ok(ListResponse {
+        escrows: escrow_ids_by_token(deps.storage, &denom_or_cw20, start_after, limit)?,
+    })
+}
+
+pub fn query_list_expired(
+    deps: Deps,
+    env: Env,
+    as_of_height: Option<u64>,
+    as_of_time: Option<Timestamp>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let as_of = Env {
+        block: BlockInfo {
+            height: as_of_height.unwrap_or(env.block.height),
+            time: as_of_time.unwrap_or(env.block.time),
+            ..env.block.clone()
+        },
+        ..env
+    };
+
+    let cursor = match start_after {
+        Some(id) => Some((cursor_expiration(deps.storage, &id)?, id)),
+        None => None,
+    };
+    let ids = expired_escrow_ids(deps.storage, &as_of.block, cursor.as_ref().map(|(expires, id)| (expires, id.as_str())));
+    let mut escrows = vec![];
+    for id in ids {
+        let id = id?;
+        let escrow = escrows_read(deps.storage, &id)?;
+        if matches!(escrow.status, Status::Pending | Status::Funding | Status::Open)
+            && escrow.grace_ends().is_expired(&as_of.block)
+            && !escrow.is_failed_crowdfund(&as_of)
+        {
+            escrows.push(id);
+        }
+        if escrows.len() == limit {
+            break;
+        }
+    }
+    Ok(ListResponse { escrows })
+}
+
+// where the last escrow of a page expires, it may have settled since
+fn cursor_expiration(storage: &dyn Storage, id: &str) -> StdResult<Expiration> {
+    match archived_escrows_may_load(storage, id)? {
+        Some(archived) => Ok(archived.expires),
+        None => Ok(escrows_read_without_balance(storage, id)?.expires),
+    }
+}
+
+fn arbiter_info(address: Addr, profile: ArbiterProfile) -> ArbiterInfo {
+    ArbiterInfo {
+        address: address.into_string(),
+        fee_bps: profile.fee_bps,
+        categories: profile.categories,
+        bond: profile.bond.native,
+    }
+}
+
+pub fn query_arbiter(deps: Deps, address: String) -> StdResult<ArbiterInfo> {
+    let address = deps.api.addr_validate(&address)?;
+    let profile = ARBITER_REGISTRY.load(deps.storage, &address)?;
+    Ok(arbiter_info(address, profile))
+}
+
+pub fn query_arbiter_stats(deps: Deps, arbiter: String) -> StdResult<ArbiterStatsResponse> {
+    let arbiter = deps.api.addr_validate(&arbiter)?;
+    let stats = ARBITER_STATS.may_load(deps.storage, &arbiter)?.unwrap_or_default();
+    Ok(ArbiterStatsResponse {
+        arbiter: arbiter.into_string(),
+        escrows_handled: stats.escrows_handled,
+        disputes_resolved: stats.disputes_resolved,
+        decisions_overturned: stats.decisions_overturned,
+        average_resolution_time: stats
+            .total_resolution_time
+            .checked_div(stats.disputes_resolved),
+    })
+}
+
+pub fn query_list_arbiters(
+    deps: Deps,
+    category: Option<String>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ArbitersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?;
+    let arbiters = registered_arbiters(deps.storage, category.as_deref(), start_after.as_ref(), limit)?;
+    Ok(ArbitersResponse {
+        arbiters: arbiters
+            .into_iter()
+            .map(|(address, profile)| arbiter_info(address, profile))
+            .collect(),
+    })
+}
+
+fn template_response(id: String, template: Template) -> TemplateResponse {
+    TemplateResponse {
+        id,
+        owner: template.owner.into_string(),
+        arbiters: template.arbiters.into_iter().map(Addr::into_string).collect(),
+        threshold: template.threshold,
+        arbiter_fee_bps: template.arbiter_fee_bps,
+        duration: template.duration,
+        tokens: template.tokens,
+    }
+}
+
+pub fn query_template(deps: Deps, id: String) -> StdResult<TemplateResponse> {
+    let template = TEMPLATES.load(deps.storage, &id)?;
+    Ok(template_response(id, template))
+}
+
+pub fn query_list_templates(
+    deps: Deps,
+    owner: Option<String>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<TemplatesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let owner = owner.map(|addr| deps.api.addr_validate(&addr)).transpose()?;
+    let templates = registered_templates(deps.storage, owner.as_ref(), start_after.as_deref(), limit)?;
+    Ok(TemplatesResponse {
+        templates: templates
+            .into_iter()
+            .map(|(id, template)| template_response(id, template))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::TemplateOverrides;
+    use crate::state::{EscrowCounts, ARCHIVED_BY_ARBITER, ESCROW_BALANCES, MAX_MEMO_LENGTH};
+    use sha2::{Digest, Sha256};
+    use cw_utils::Duration;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{attr, coin, coins, ContractResult, CosmosMsg, Order, StdError, Storage, ReplyOn, SubMsgResponse, SubMsgResult, SystemResult, Uint128};
+
+    fn default_instantiate(deps: DepsMut) {
+        let msg = InstantiateMsg {
+            admin: Some(String::from("admin")),
+            token_allowlist: Some(vec![String::from("my-token")]),
+            max_tokens: None,
+            fee_bps: None,
+            fee_collector: None,
+            appellate_arbiter: None,
+            appeal_window: None,
+            creation_fee: None,
+            strict_parties: None,
+            receipt_nft: None,
+        };
+        instantiate(deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+    }
+    
+    #[test]
+    fn create_and_approve_escrow() {
+        let env = mock_env();
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let id = "foobar".to_string();
+        let arbiter = "arbiter".to_string();
+        let recipient = "recipient".to_string();
+        let source = "sender".to_string();
+
+        let msg = CreateMsg {
+            id: id.clone(),
+            arbiter: arbiter.clone().into(),
+            recipient: recipient.clone(),
+            expires: Expiration::AtHeight(123456),
+            cw20_whitelist: None,
+            ..CreateMsg::default()
+        };
+        let balance = coins(100, "tokens");
+        let info = mock_info("sender", &balance);
+        let execute_res = execute(deps.as_mut(), env, info, ExecuteMsg::Create(msg)).unwrap();
+        
+
+        assert_eq!(0, execute_res.messages.len());
+        // ensure the details is what we expect
+        let details = query_details(deps.as_ref(), mock_env(), "foobar".to_string()).unwrap();
+        assert_eq!(
+            details,
+            DetailsResponse {
+                id: id.clone(),
+                arbiters: vec![arbiter.clone().to_string()],
+                threshold: 1,
+                recipient: recipient.clone().to_string(),
+                source: source.clone().to_string(),
+                expires: Expiration::AtHeight(123456),
+                start: None,
+                grace_period: None,
+                ping_interval: None,
+                release_hook: None,
+                payout_callback: false,
+                pull_payouts: false,
+                pull_refunds: false,
+                created_at_height: Some(mock_env().block.height),
+                created_at_time: Some(mock_env().block.time),
+                topped_up: TokenAmounts::default(),
+                is_expired: false,
+                remote_recipient: None,
+                remote_refund_address: None,
+                arbiter_proxy: None,
+                strict_parties: false,
+                receipt: None,
+                splits: vec![],
+                swap_offered: None,
+                swap_for: None,
+                native_balance: balance.clone(), 
+                cw20_balance: vec![],
+                nft_balance: vec![],
+                cw20_whitelist: vec![],
+                arbiter_fee_bps: 0,
+                refund_address: None,
+                on_expiry: OnExpiry::RefundToSource,
+                status: Status::Open,
+                title: None,
+                description: None,
+                metadata: None,
+                terms_hash: None,
+                source_approved: false,
+                recipient_approved: false,
+                accepted: true,
+                funding_target: None,
+                arbiter_can_set_recipient: false,
+                schedule: None,
+                claimed: TokenAmounts::default(),
+                allowed_funders: None,
+                crowdfund: false,
+                conditions: vec![],
+                dispute: None,
+            decision: None,
+            }
+        );
+
+        // beneficiary cannot release it
+        let env = mock_env();
+        let info = mock_info("beneficiary", &[]);
+        let approve_res = execute(deps.as_mut(), env, info, ExecuteMsg::Approve{id:id.clone()});
+        match approve_res.unwrap_err() {
+            ContractError::NotArbiter { .. } => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // approve it by arbiter
+        let env = mock_env();
+        let info = mock_info("arbiter", &[]);
+        let approve_res = execute(deps.as_mut(), env, info, ExecuteMsg::Approve{id:id.clone()}).unwrap();
+        assert_eq!(1, approve_res.messages.len());
+        assert_eq!(
+            approve_res.messages.first().expect("no message").msg, 
+            CosmosMsg::Bank(BankMsg::Send{
+                to_address: recipient.clone(),
+                amount: balance.clone(),
+            })
+        );
+    }
+
+    #[test]
+    fn create_and_approve_escrow_with_cw20() {
+        let env = mock_env();
+        let mut deps = mock_dependencies();
+
+        let id = String::from("foobar");
+        let arbiter = String::from("arbiter");
+        let recipient = String::from("recipient");
+        let source = String::from("sender");
+        let token_contract_addr = String::from("my-token");
+
+        let init_msg = InstantiateMsg {
+            admin: None,
+            token_allowlist: Some(vec![token_contract_addr.clone()]),
+            max_tokens: None,
+            fee_bps: None,
+            fee_collector: None,
+            appellate_arbiter: None,
+            appeal_window: None,
+            creation_fee: None,
+            strict_parties: None,
+            receipt_nft: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), init_msg).unwrap();
+
+        let info = mock_info(token_contract_addr.as_str(), &[]);
+
+        let crt_msg = CreateMsg {
+            id: id.clone(),
+            arbiter: arbiter.clone().into(),
+            recipient: recipient.clone(),
+            expires: Expiration::AtHeight(123456),
+            cw20_whitelist: Some(vec![String::from("other-token")]),
+            ..CreateMsg::default()
+        };
+        let rev_msg = Cw20ReceiveMsg {
+            sender: source.clone(),
+            amount: Uint128::from(100u128),
+            msg: to_binary(&ExecuteMsg::Create(crt_msg)).unwrap(),
+        };
+        let execute_res = execute(deps.as_mut(), env, info, ExecuteMsg::Receive(rev_msg)).unwrap();
+        assert_eq!(0, execute_res.messages.len());
+
+        let details = query_details(deps.as_ref(), mock_env(), "foobar".to_string()).unwrap();
+        assert_eq!(
+            details,
+            DetailsResponse{
+                id: id.clone(),
+                arbiters: vec![arbiter.clone()],
+                threshold: 1,
+                recipient: recipient.clone(),
+                source: source.clone(),
+                expires: Expiration::AtHeight(123456),
+                start: None,
+                grace_period: None,
+                ping_interval: None,
+                release_hook: None,
+                payout_callback: false,
+                pull_payouts: false,
+                pull_refunds: false,
+                created_at_height: Some(mock_env().block.height),
+                created_at_time: Some(mock_env().block.time),
+                topped_up: TokenAmounts::default(),
+                is_expired: false,
+                remote_recipient: None,
+                remote_refund_address: None,
+                arbiter_proxy: None,
+                strict_parties: false,
+                receipt: None,
+                splits: vec![],
+                swap_offered: None,
+                swap_for: None,
+                native_balance: vec![],
+                cw20_balance: vec![Cw20Coin{
+                    address: token_contract_addr.clone(),
+                    amount: Uint128::from(100u128),
+                }],
+                nft_balance: vec![],
+                cw20_whitelist: vec![
+                    String::from("other-token"),
+                    String::from("my-token")
+                ],
+                arbiter_fee_bps: 0,
+                refund_address: None,
+                on_expiry: OnExpiry::RefundToSource,
+                status: Status::Open,
+                title: None,
+                description: None,
+                metadata: None,
+                terms_hash: None,
+                source_approved: false,
+                recipient_approved: false,
+                accepted: true,
+                funding_target: None,
+                arbiter_can_set_recipient: false,
+                schedule: None,
+                claimed: TokenAmounts::default(),
+                allowed_funders: None,
+                crowdfund: false,
+                conditions: vec![],
+                dispute: None,
+            decision: None,
+            }
+        );
+
+        // approve it by arbiter
+        let env = mock_env();
+        let info = mock_info("arbiter", &[]);
+        let approve_res = execute(deps.as_mut(), env, info, ExecuteMsg::Approve{id:id.clone()}).unwrap();
+        let send_msg = Cw20ExecuteMsg::Transfer {
+            recipient: recipient.clone(),
+            amount: Uint128::from(100u128),
+        };
+
+        assert_eq!(1, approve_res.messages.len());
+        assert_eq!(
+            approve_res.messages.first().expect("no message").msg, 
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: token_contract_addr.clone(),
+                msg: to_binary(&send_msg).unwrap(),
+                funds: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn list_escrows_paginated() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        for id in ["alpha", "beta", "delta", "gamma"] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                expires: Expiration::Never {},
+                cw20_whitelist: None,
+                ..CreateMsg::default()
+            };
+            let info = mock_info("sender", &coins(100, "tokens"));
+            execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        }
+
+        let page = query_list(deps.as_ref(), None, Some(3), None, None).unwrap();
+        assert_eq!(page.escrows, vec!["alpha", "beta", "delta"]);
+
+        let page = query_list(deps.as_ref(), Some("delta".to_string()), Some(3), None, None).unwrap();
+        assert_eq!(page.escrows, vec!["gamma"]);
+
+        // the limit is capped
+        let page = query_list(deps.as_ref(), None, Some(1000), None, None).unwrap();
+        assert_eq!(page.escrows.len(), 4);
+    }
+
+    #[test]
+    fn list_escrows_by_arbiter() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        for (id, arbiter) in [("one", "judy"), ("two", "carl"), ("three", "judy")] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: arbiter.into(),
+                recipient: String::from("recipient"),
+                expires: Expiration::Never {},
+                cw20_whitelist: None,
+                ..CreateMsg::default()
+            };
+            let info = mock_info("sender", &coins(100, "tokens"));
+            execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        }
+
+        let page = query_list_by_arbiter(deps.as_ref(), "judy".to_string(), None, None).unwrap();
+        assert_eq!(page.escrows, vec!["one", "three"]);
+
+        let page =
+            query_list_by_arbiter(deps.as_ref(), "judy".to_string(), Some("one".to_string()), None)
+                .unwrap();
+        assert_eq!(page.escrows, vec!["three"]);
+
+        // once settled, the escrow moves to the archive
+        let info = mock_info("carl", &[]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Approve { id: "two".to_string() }).unwrap();
+        let page = query_list_by_arbiter(deps.as_ref(), "carl".to_string(), None, None).unwrap();
+        assert!(page.escrows.is_empty());
+    }
+
+    #[test]
+    fn migrate_reindexes_legacy_escrows() {
+        let mut deps = mock_dependencies();
+
+        // write an escrow the way the legacy bucket did: length-prefixed namespace + id,
+        // with the original fields only
+        let legacy = br#"{"arbiter":"arbiter","recipient":"recipient","source":"source","end_height":null,"end_time":null,"balance":{"native":[{"denom":"tokens","amount":"100"}],"cw20":[]},"cw20_whitelist":[]}"#;
+        let escrow = Escrow {
+            arbiters: vec![Addr::unchecked("arbiter")],
+            threshold: 1,
+            recipient: Addr::unchecked("recipient"),
+            source: Addr::unchecked("source"),
+            expires: Expiration::Never {},
+            start: None,
+            grace_period: None,
+            ping_interval: None,
+            release_hook: None,
+            payout_callback: false,
+            pull_payouts: false,
+            pull_refunds: false,
+            created_at_height: None,
+            created_at_time: None,
+            remote_recipient: None,
+            remote_refund_address: None,
+            arbiter_proxy: None,
+            strict_parties: false,
+            receipt: None,
+            splits: vec![],
+            swap: None,
+            balance: GenericBalance {
+                native: coins(100, "tokens"),
+                ..GenericBalance::default()
+            },
+            cw20_whitelist: vec![],
+            fee_bps: 0,
+            arbiter_fee_bps: 0,
+            refund_address: None,
+            on_expiry: OnExpiry::RefundToSource,
+            status: Status::Open,
+            title: None,
+            description: None,
+            metadata: None,
+            terms_hash: None,
+            source_approved: false,
+            recipient_approved: false,
+            accepted: true,
+            funding_target: None,
+            arbiter_can_set_recipient: false,
+            schedule: None,
+            claimed: GenericBalance::default(),
+            allowed_funders: None,
+            crowdfund: false,
+            conditions: vec![],
+            dispute: None,
+            decision: None,
+        };
+        let namespace = b"liability";
+        let mut key = (namespace.len() as u16).to_be_bytes().to_vec();
+        key.extend_from_slice(namespace);
+        key.extend_from_slice(b"legacy");
+        deps.storage.set(&key, legacy);
+        // one with a deadline, expiring once the block height passed it
+        let dated = br#"{"arbiter":"arbiter","recipient":"recipient","source":"source","end_height":12345,"end_time":null,"balance":{"native":[],"cw20":[]},"cw20_whitelist":[]}"#;
+        let mut key = (namespace.len() as u16).to_be_bytes().to_vec();
+        key.extend_from_slice(namespace);
+        key.extend_from_slice(b"dated");
+        deps.storage.set(&key, dated);
+        set_contract_version(&mut deps.storage, CONTRACT_NAME, "0.9.0").unwrap();
+
+        // readable, but not indexed yet
+        assert_eq!(escrows_read(&deps.storage, "legacy").unwrap(), escrow);
+        let page = query_list_by_arbiter(deps.as_ref(), "arbiter".to_string(), None, None).unwrap();
+        assert!(page.escrows.is_empty());
+
+        // nothing could approve or refund without a config
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap_err();
+        assert!(matches!(err, ContractError::ConfigRequired {}));
+        let msg = MigrateMsg {
+            admin: Some(String::from("admin")),
+            ..MigrateMsg::default()
+        };
+        let res = migrate(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.attributes[3].value, "2");
+        assert_eq!(res.attributes[7], attr("config_created", "true"));
+        assert_eq!(get_contract_version(&deps.storage).unwrap().version, CONTRACT_VERSION);
+        assert_eq!(OWNERSHIP.load(&deps.storage).unwrap().owner, Some(Addr::unchecked("admin")));
+        assert_eq!(query_config(deps.as_ref()).unwrap().fee_collector, "admin");
+
+        let page = query_list_by_arbiter(deps.as_ref(), "arbiter".to_string(), None, None).unwrap();
+        assert_eq!(page.escrows, vec!["dated", "legacy"]);
+        let stats = query_stats(deps.as_ref()).unwrap();
+        assert_eq!(stats.total_locked.native, coins(100, "tokens"));
+        assert_eq!(stats.counts.active, 2);
+        let details = query_details(deps.as_ref(), mock_env(), "dated".to_string()).unwrap();
+        assert_eq!(details.expires, Expiration::AtHeight(12346));
+        assert_eq!(query_details(deps.as_ref(), mock_env(), "legacy".to_string()).unwrap().expires, Expiration::Never {});
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), ExecuteMsg::Approve { id: "legacy".to_string() }).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+    }
+
+    #[test]
+    fn migrate_converts_legacy_deadlines() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let env = mock_env();
+        let now = env.block.time.nanos() / 1000;
+        let write = |storage: &mut dyn Storage, id: &str, end_height: u64, end_time: u64| {
+            let legacy = format!(
+                r#"{{"arbiter":"arbiter","recipient":"recipient","source":"source","end_height":{},"end_time":{},"balance":{{"native":[],"cw20":[]}},"cw20_whitelist":[]}}"#,
+                if end_height == 0 { String::from("null") } else { end_height.to_string() },
+                if end_time == 0 { String::from("null") } else { end_time.to_string() },
+            );
+            let namespace = b"liability";
+            let mut key = (namespace.len() as u16).to_be_bytes().to_vec();
+            key.extend_from_slice(namespace);
+            key.extend_from_slice(id.as_bytes());
+            storage.set(&key, legacy.as_bytes());
+        };
+        write(&mut deps.storage, "time", 0, now + 100);
+        // the height passed, so that one counts
+        write(&mut deps.storage, "height", env.block.height - 1, now + 100);
+        // either could pass first
+        write(&mut deps.storage, "both", env.block.height + 10, now + 100);
+        set_contract_version(&mut deps.storage, CONTRACT_NAME, "0.9.0").unwrap();
+
+        let err = migrate(deps.as_mut(), env.clone(), MigrateMsg::default()).unwrap_err();
+        match err {
+            ContractError::AmbiguousDeadlines { ids } => assert_eq!(ids, "both"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // once it is settled
+        deps.storage.remove(&[&[0u8, 9][..], b"liability", b"both"].concat());
+        migrate(deps.as_mut(), env.clone(), MigrateMsg::default()).unwrap();
+        let details = query_details(deps.as_ref(), env.clone(), String::from("time")).unwrap();
+        assert_eq!(details.expires, Expiration::AtTime(Timestamp::from_nanos((now + 100) * 1000 + 1)));
+        let details = query_details(deps.as_ref(), env.clone(), String::from("height")).unwrap();
+        assert_eq!(details.expires, Expiration::AtHeight(env.block.height));
+    }
+
+    #[test]
+    fn migrate_in_steps() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        // legacy entries as in migrate_reindexes_legacy_escrows, plus one settled escrow
+        // that is archived already
+        let legacy = br#"{"arbiter":"arbiter","recipient":"recipient","source":"source","end_height":null,"end_time":null,"balance":{"native":[{"denom":"tokens","amount":"100"}],"cw20":[]},"cw20_whitelist":[]}"#;
+        let namespace = b"liability";
+        for id in ["a", "b", "c"] {
+            let mut key = (namespace.len() as u16).to_be_bytes().to_vec();
+            key.extend_from_slice(namespace);
+            key.extend_from_slice(id.as_bytes());
+            deps.storage.set(&key, legacy);
+        }
+        let mut settled = escrows_read(&deps.storage, "a").unwrap();
+        settled.status = Status::Refunded;
+        crate::state::archive().save(&mut deps.storage, "z", &settled).unwrap();
+        ESCROW_COUNTS.remove(&mut deps.storage);
+        set_contract_version(&mut deps.storage, CONTRACT_NAME, "0.9.0").unwrap();
+
+        let step = MigrateMsg {
+            limit: Some(2),
+            ..MigrateMsg::default()
+        };
+        // counts the archived one, then migrates a
+        let res = migrate(deps.as_mut(), mock_env(), step.clone()).unwrap();
+        assert_eq!(res.attributes[3], attr("reindexed", "1"));
+        assert_eq!(res.attributes[6], attr("done", "false"));
+        assert_eq!(get_contract_version(&deps.storage).unwrap().version, "0.9.0");
+
+        // the contract waits for the migration to finish
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), ExecuteMsg::Approve { id: String::from("a") })
+            .unwrap_err();
+        assert!(matches!(err, ContractError::MigrationInProgress {}));
+
+        let res = migrate(deps.as_mut(), mock_env(), step.clone()).unwrap();
+        assert_eq!(res.attributes[3], attr("reindexed", "2"));
+        assert_eq!(res.attributes[6], attr("done", "false"));
+        let res = migrate(deps.as_mut(), mock_env(), step).unwrap();
+        assert_eq!(res.attributes[3], attr("reindexed", "0"));
+        assert_eq!(res.attributes[6], attr("done", "true"));
+        assert_eq!(get_contract_version(&deps.storage).unwrap().version, CONTRACT_VERSION);
+
+        let page = query_list_by_arbiter(deps.as_ref(), "arbiter".to_string(), None, None).unwrap();
+        assert_eq!(page.escrows, vec!["a", "b", "c"]);
+        let stats = query_stats(deps.as_ref()).unwrap();
+        assert_eq!(stats.total_locked.native, coins(300, "tokens"));
+        let counts = EscrowCounts {
+            active: 3,
+            refunded: 1,
+            ..EscrowCounts::default()
+        };
+        assert_eq!(stats.counts, counts);
+
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), ExecuteMsg::Approve { id: String::from("a") }).unwrap();
+        assert_eq!(query_stats(deps.as_ref()).unwrap().total_locked.native, coins(200, "tokens"));
+
+        // migrating again leaves the totals alone
+        migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+        let stats = query_stats(deps.as_ref()).unwrap();
+        assert_eq!(stats.total_locked.native, coins(200, "tokens"));
+        assert_eq!(stats.counts.active, 2);
+        // escrows archived before the arbiter index existed are in it as well
+        let refunded = escrow_ids_by_status_and_arbiter(&deps.storage, Status::Refunded, &Addr::unchecked("arbiter"), None, 10);
+        assert_eq!(refunded.unwrap(), vec!["z"]);
+    }
+
+    #[test]
+    fn migrate_checks_stored_version() {
+        let mut deps = mock_dependencies();
+
+        // no version stored at all
+        migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap_err();
+
+        set_contract_version(&mut deps.storage, "crates.io:cw20-base", "0.1.0").unwrap();
+        match migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap_err() {
+            ContractError::CannotMigrate { previous_contract } => {
+                assert_eq!(previous_contract, "crates.io:cw20-base")
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        set_contract_version(&mut deps.storage, CONTRACT_NAME, "99.0.0").unwrap();
+        match migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap_err() {
+            ContractError::CannotMigrateVersion { previous_version } => {
+                assert_eq!(previous_version, "99.0.0")
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // same version is fine
+        set_contract_version(&mut deps.storage, CONTRACT_NAME, CONTRACT_VERSION).unwrap();
+        let msg = MigrateMsg {
+            admin: Some(String::from("admin")),
+            ..MigrateMsg::default()
+        };
+        migrate(deps.as_mut(), mock_env(), msg).unwrap();
+
+        let msg = MigrateMsg {
+            limit: Some(0),
+            ..MigrateMsg::default()
+        };
+        let err = migrate(deps.as_mut(), mock_env(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::ZeroLimit {}));
+    }
+
+    #[test]
+    fn migrate_skips_escrows_when_already_indexed() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::Never {},
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+        assert_eq!(res.attributes[3], attr("reindexed", "0"));
+        assert_eq!(res.attributes[6], attr("done", "true"));
+        assert!(MIGRATION.may_load(&deps.storage).unwrap().is_none());
+
+        // stats missing from the store are rebuilt all the same
+        ESCROW_COUNTS.remove(&mut deps.storage);
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+        assert_eq!(res.attributes[3], attr("reindexed", "1"));
+        assert_eq!(query_stats(deps.as_ref()).unwrap().counts.active, 1);
+
+        // and so is the index of settled escrows, without counting them again
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), ExecuteMsg::Approve { id: String::from("foobar") }).unwrap();
+        ARCHIVED_BY_ARBITER.remove(&mut deps.storage, (&Addr::unchecked("arbiter"), "approved", "foobar"));
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+        assert_eq!(res.attributes[6], attr("done", "true"));
+        let approved = escrow_ids_by_status_and_arbiter(&deps.storage, Status::Approved, &Addr::unchecked("arbiter"), None, 10);
+        assert_eq!(approved.unwrap(), vec!["foobar"]);
+        assert_eq!(query_stats(deps.as_ref()).unwrap().counts.approved, 1);
+    }
+
+    #[test]
+    fn create_rejects_invalid_addresses() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "Arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::Never {},
+            cw20_whitelist: None,
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(100, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg.clone())).unwrap_err();
+        assert!(matches!(err, ContractError::Std(StdError::GenericErr { .. })));
+
+        // the cw20 sender is validated as well
+        let msg = CreateMsg {
+            arbiter: "arbiter".into(),
+            ..msg
+        };
+        let receive = Cw20ReceiveMsg {
+            sender: String::from("Sender"),
+            amount: Uint128::new(100),
+            msg: to_binary(&ReceiveMsg::Create(msg)).unwrap(),
+        };
+        let info = mock_info("my-token", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Receive(receive)).unwrap_err();
+        assert!(matches!(err, ContractError::Std(StdError::GenericErr { .. })));
+    }
+
+    #[test]
+    fn create_rejects_invalid_ids() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let create = |id: &str| ExecuteMsg::Create(CreateMsg {
+            id: id.to_string(),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        });
+        let info = mock_info("source", &coins(100, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), create("")).unwrap_err();
+        assert!(matches!(err, ContractError::EmptyId {}));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), create(&"a".repeat(MAX_ID_LENGTH + 1))).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidIdLength { .. }));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), create("order 1")).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidIdChar { c: ' ' }));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), create("order/1")).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidIdChar { c: '/' }));
+
+        execute(deps.as_mut(), mock_env(), info.clone(), create(&"a".repeat(MAX_ID_LENGTH))).unwrap();
+        execute(deps.as_mut(), mock_env(), info, create("Order-1_v2.0")).unwrap();
+    }
+
+    #[test]
+    fn namespaced_ids_do_not_collide() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("order-1"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            namespaced: Some(true),
+            ..CreateMsg::default()
+        };
+        let info = |creator: &str| mock_info(creator, &coins(100, "tokens"));
+        let res = execute(deps.as_mut(), mock_env(), info("shop"), ExecuteMsg::Create(msg.clone())).unwrap();
+        let created: CreateResponse = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(created.id, "shop/order-1");
+        execute(deps.as_mut(), mock_env(), info("market"), ExecuteMsg::Create(msg.clone())).unwrap();
+        let global = CreateMsg { namespaced: None, ..msg.clone() };
+        execute(deps.as_mut(), mock_env(), info("shop"), ExecuteMsg::Create(global)).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), info("shop"), ExecuteMsg::Create(msg)).unwrap_err();
+        assert!(matches!(err, ContractError::IdAlreadyExists { .. }));
+
+        let resolve = |deps: Deps, creator: Option<&str>, id: &str| {
+            query_resolve_id(deps, creator.map(String::from), id.to_string()).map(|res| res.id)
+        };
+        assert_eq!(resolve(deps.as_ref(), Some("market"), "order-1").unwrap(), "market/order-1");
+        assert_eq!(resolve(deps.as_ref(), None, "order-1").unwrap(), "order-1");
+        resolve(deps.as_ref(), Some("nobody"), "order-1").unwrap_err();
+
+        // every other message takes the full id
+        let approve = ExecuteMsg::Approve { id: String::from("market/order-1") };
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        assert_eq!(resolve(deps.as_ref(), Some("market"), "order-1").unwrap(), "market/order-1");
+        let details = query_details(deps.as_ref(), mock_env(), String::from("shop/order-1")).unwrap();
+        assert_eq!(details.source, "shop");
+    }
+
+    #[test]
+    fn details_show_top_ups_and_expiry() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::AtHeight(mock_env().block.height + 10),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        let top_up = ExecuteMsg::TopUp { id: String::from("foobar") };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(20, "tokens")), top_up.clone()).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(5, "tokens")), top_up).unwrap();
+
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.native_balance, coins(125, "tokens"));
+        assert_eq!(details.topped_up.native, coins(25, "tokens"));
+        assert_eq!(details.created_at_height, Some(mock_env().block.height));
+        assert_eq!(details.status, Status::Open);
+        assert!(!details.is_expired);
+
+        let mut env = mock_env();
+        env.block.height += 10;
+        assert!(query_details(deps.as_ref(), env, String::from("foobar")).unwrap().is_expired);
+    }
+
+    #[test]
+    fn history_records_every_action() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        let top_up = ExecuteMsg::TopUp { id: String::from("foobar") };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(20, "tokens")), top_up).unwrap();
+        let set_recipient = ExecuteMsg::SetRecipient {
+            id: String::from("foobar"),
+            new_recipient: String::from("seller"),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), set_recipient).unwrap();
+        let transfer = ExecuteMsg::TransferSourceRights {
+            id: String::from("foobar"),
+            new_source: String::from("buyer"),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &[]), transfer).unwrap();
+        let dispute = ExecuteMsg::RaiseDispute {
+            id: String::from("foobar"),
+            reason: String::from("late"),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), dispute).unwrap();
+        let refund = ExecuteMsg::Refund { id: String::from("foobar") };
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), refund).unwrap();
+
+        let history = query_history(deps.as_ref(), String::from("foobar"), None, None).unwrap().history;
+        let actions: Vec<_> = history.iter().map(|entry| (entry.seq, entry.action.as_str(), entry.actor.as_deref())).collect();
+        assert_eq!(
+            actions,
+            vec![
+                (0, "create", Some("source")),
+                (1, "top_up", Some("source")),
+                (2, "set_recipient", Some("recipient")),
+                (3, "transfer_source", Some("source")),
+                (4, "dispute", Some("seller")),
+                (5, "refund", Some("arbiter")),
+            ]
+        );
+        assert_eq!(history[1].amount.native, coins(20, "tokens"));
+        assert_eq!(history[5].amount.native, coins(120, "tokens"));
+        assert_eq!(history[0].height, mock_env().block.height);
+
+        let page = query_history(deps.as_ref(), String::from("foobar"), Some(3), Some(1)).unwrap().history;
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].action, "dispute");
+
+        // payouts withdrawn later are on record too
+        let msg = CreateMsg {
+            id: String::from("pull"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            pull_payouts: Some(true),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), ExecuteMsg::Approve { id: String::from("pull") }).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), ExecuteMsg::Claim { id: String::from("pull") }).unwrap();
+        let history = query_history(deps.as_ref(), String::from("pull"), None, None).unwrap().history;
+        let last = history.last().unwrap();
+        assert_eq!((last.action.as_str(), last.actor.as_deref()), ("withdraw_claimable", Some("recipient")));
+        assert_eq!(last.amount.native, coins(100, "tokens"));
+    }
+
+    #[test]
+    fn subscribers_are_notified_of_changes() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            subscribers: Some(vec![String::from("market")]),
+            ..CreateMsg::default()
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        let hook = |subscriber: &str, change: EscrowChange| {
+            SubMsg::reply_on_error(
+                WasmMsg::Execute {
+                    contract_addr: subscriber.to_string(),
+                    msg: to_binary(&EscrowHookMsg::EscrowChanged {
+                        id: String::from("foobar"),
+                        change,
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                },
+                HOOK_REPLY_ID,
+            )
+        };
+        assert_eq!(res.messages, vec![hook("market", EscrowChange::Created)]);
+
+        let subscribe = ExecuteMsg::Subscribe {
+            id: String::from("foobar"),
+            subscriber: String::from("auction"),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("stranger", &[]), subscribe.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), subscribe).unwrap();
+
+        let top_up = ExecuteMsg::TopUp { id: String::from("foobar") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("source", &coins(20, "tokens")), top_up).unwrap();
+        assert_eq!(res.messages, vec![hook("auction", EscrowChange::Funded), hook("market", EscrowChange::Funded)]);
+
+        // subscribers can leave on their own
+        let unsubscribe = ExecuteMsg::Unsubscribe {
+            id: String::from("foobar"),
+            subscriber: String::from("auction"),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("auction", &[]), unsubscribe).unwrap();
+        let approve = ExecuteMsg::Approve { id: String::from("foobar") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        assert_eq!(res.messages.len(), 2);
+        assert_eq!(res.messages[1], hook("market", EscrowChange::Approved));
+
+        // a failing subscriber does not revert the approval
+        let failed = Reply {
+            id: HOOK_REPLY_ID,
+            result: SubMsgResult::Err(String::from("out of gas")),
+        };
+        let res = reply(deps.as_mut(), mock_env(), failed).unwrap();
+        assert_eq!(res.attributes, vec![attr("subscriber_failed", "out of gas")]);
+    }
+
+    #[test]
+    fn remote_recipient_gets_ibc_transfer() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let remote = RemoteAddress {
+            chain_channel: String::from("channel-7"),
+            address: String::from("osmo1recipient"),
+            memo: None,
+        };
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            remote_recipient: Some(RemoteAddress {
+                chain_channel: String::from("transfer"),
+                ..remote.clone()
+            }),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(msg.clone())).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidRemoteAddress {}));
+        let msg = CreateMsg {
+            remote_recipient: Some(remote.clone()),
+            ..msg
+        };
+        let pulled = CreateMsg {
+            pull_payouts: Some(true),
+            ..msg.clone()
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(pulled)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidRemotePayout {}));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.remote_recipient, Some(remote));
+
+        let approve = ExecuteMsg::Approve { id: String::from("foobar") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Ibc(IbcMsg::Transfer {
+                channel_id: String::from("channel-7"),
+                to_address: String::from("osmo1recipient"),
+                amount: coin(100, "tokens"),
+                timeout: IbcTimeout::with_timestamp(mock_env().block.time.plus_seconds(IBC_TRANSFER_TIMEOUT)),
+            })
+        );
+    }
+
+    #[test]
+    fn set_recipient_clears_remote_recipient() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            arbiter_can_set_recipient: Some(true),
+            remote_recipient: Some(RemoteAddress {
+                chain_channel: String::from("channel-7"),
+                address: String::from("osmo1recipient"),
+                memo: None,
+            }),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+
+        let set = ExecuteMsg::SetRecipient {
+            id: String::from("foobar"),
+            new_recipient: String::from("new_recipient"),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), set).unwrap();
+        assert_eq!(res.events[0].attributes[4], attr("cleared_remote_recipient", "osmo1recipient"));
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.remote_recipient, None);
+
+        let approve = ExecuteMsg::Approve { id: String::from("foobar") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("new_recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+    }
+
+    #[test]
+    fn remote_memo_sent_with_transfer() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let memo = r#"{"wasm":{"contract":"osmo1vault","msg":{"deposit":{}}}}"#;
+        let remote = RemoteAddress {
+            chain_channel: String::from("channel-7"),
+            address: String::from("osmo1vault"),
+            memo: Some("x".repeat(MAX_MEMO_LENGTH + 1)),
+        };
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            remote_recipient: Some(remote.clone()),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(msg.clone())).unwrap_err();
+        assert!(matches!(err, ContractError::MemoTooLong { .. }));
+        let remote = RemoteAddress {
+            memo: Some(memo.to_string()),
+            ..remote
+        };
+        let msg = CreateMsg {
+            remote_recipient: Some(remote.clone()),
+            ..msg
+        };
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let approve = ExecuteMsg::Approve { id: String::from("foobar") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        let timeout = mock_env().block.time.plus_seconds(IBC_TRANSFER_TIMEOUT);
+        let transfer = transfer_with_memo(&mock_env(), &remote, coin(100, "tokens"), timeout, memo);
+        assert_eq!(res.messages[0].msg, transfer);
+        match &transfer {
+            CosmosMsg::Stargate { type_url, value } => {
+                assert_eq!(type_url, MSG_TRANSFER_TYPE_URL);
+                assert_eq!(transfer_denom(value), Some(String::from("tokens")));
+                assert!(value.windows(memo.len()).any(|w| w == memo.as_bytes()));
+            }
+            msg => panic!("unexpected {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn remote_arbiter_decides_over_ibc() {
+        use crate::ibc::{ibc_channel_open, ibc_packet_receive, ARBITRATION_VERSION};
+        use cosmwasm_std::testing::{mock_ibc_channel_open_init, mock_ibc_packet_recv};
+        use cosmwasm_std::IbcOrder;
+
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let ordered = mock_ibc_channel_open_init("channel-1", IbcOrder::Ordered, ARBITRATION_VERSION);
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), ordered).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidIbcOrder {}));
+        let open = mock_ibc_channel_open_init("channel-1", IbcOrder::Unordered, "ics20-1");
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidIbcVersion { .. }));
+        let open = mock_ibc_channel_open_init("channel-1", IbcOrder::Unordered, ARBITRATION_VERSION);
+        ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap();
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "remote-arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        // the packet is carried out by the contract executing itself
+        let packet = ArbitrationPacket::Approve { id: String::from("foobar") };
+        let recv = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), recv).unwrap();
+        assert_eq!(res.acknowledgement, ack_success().unwrap());
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].id, ARBITRATION_REPLY_ID);
+        assert_eq!(res.messages[0].reply_on, ReplyOn::Error);
+        let arbitrate = ExecuteMsg::IbcArbitrate {
+            channel_id: String::from("channel-1"),
+            packet,
+        };
+
+        let err = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), arbitrate.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), arbitrate.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::UnboundChannel { .. }));
+
+        // a failed packet is acknowledged with the error
+        let failed = Reply {
+            id: ARBITRATION_REPLY_ID,
+            result: SubMsgResult::Err(err.to_string()),
+        };
+        let res = reply(deps.as_mut(), mock_env(), failed).unwrap();
+        let ack: ArbitrationAck = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(ack, ArbitrationAck::Error(err.to_string()));
+
+        let bind = ExecuteMsg::BindArbiterChannel {
+            channel_id: String::from("channel-1"),
+            arbiter: Some(String::from("remote-arbiter")),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), bind.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), bind).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), info, arbitrate).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+    }
+
+    #[test]
+    fn claim_receipt_holder_is_paid() {
+        use cosmwasm_std::WasmQuery;
+
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+        // the receivable was sold on a secondary market
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { contract_addr, .. } if contract_addr == "receipts" => {
+                let res = OwnerOfResponse {
+                    owner: String::from("factor"),
+                    approvals: vec![],
+                };
+                SystemResult::Ok(ContractResult::Ok(to_binary(&res).unwrap()))
+            }
+            _ => SystemResult::Ok(ContractResult::Err(String::from("unexpected query"))),
+        });
+
+        let msg = CreateMsg {
+            id: String::from("invoice"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            mint_receipt: Some(true),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(msg.clone())).unwrap_err();
+        assert!(matches!(err, ContractError::ReceiptsDisabled {}));
+
+        let update = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            receipt_nft: Some(String::from("receipts")),
+            ..UpdateConfigMsg::default()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), update).unwrap();
+        let hooked = CreateMsg {
+            release_hook: Some(to_binary("stake").unwrap()),
+            ..msg.clone()
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(hooked)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidReceipt {}));
+
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        let mint = WasmMsg::Execute {
+            contract_addr: String::from("receipts"),
+            msg: to_binary(&ReceiptMintMsg::Mint {
+                token_id: String::from("invoice"),
+                owner: String::from("recipient"),
+                token_uri: None,
+                extension: None,
+            })
+            .unwrap(),
+            funds: vec![],
+        };
+        assert_eq!(res.messages[0].msg, mint.into());
+
+        let approve = ExecuteMsg::Approve { id: String::from("invoice") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("factor"),
+                amount: coins(100, "tokens"),
+            })
+        );
+    }
+
+    #[test]
+    fn arbiter_proxy_of_trusted_relayer() {
+        use cosmwasm_std::{ContractInfoResponse, SystemError, WasmQuery};
+
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::ContractInfo { contract_addr } if contract_addr == "dao-proxy" => {
+                let mut info = ContractInfoResponse::default();
+                info.creator = String::from("polytone-voice");
+                SystemResult::Ok(ContractResult::Ok(to_binary(&info).unwrap()))
+            }
+            _ => SystemResult::Err(SystemError::NoSuchContract {
+                addr: String::from("unknown"),
+            }),
+        });
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            arbiter_proxy: Some(String::from("dao-proxy")),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(msg.clone())).unwrap_err();
+        assert!(matches!(err, ContractError::UntrustedArbiterProxy { .. }));
+
+        let update = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            arbiter_relayers: Some(vec![String::from("polytone-voice")]),
+            ..UpdateConfigMsg::default()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), update).unwrap();
+        // only contracts instantiated by the relayer are trusted
+        let wallet = CreateMsg {
+            arbiter_proxy: Some(String::from("wallet")),
+            ..msg.clone()
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(wallet)).unwrap_err();
+        assert!(matches!(err, ContractError::UntrustedArbiterProxy { .. }));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.arbiter_proxy, Some(String::from("dao-proxy")));
+
+        let approve = ExecuteMsg::Approve { id: String::from("foobar") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("dao-proxy", &[]), approve).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+    }
+
+    #[test]
+    fn embeds_with_custom_messages() {
+        use cosmwasm_std::CustomMsg;
+        use schemars::JsonSchema;
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+        struct ChainMsg {}
+        impl CustomMsg for ChainMsg {}
+
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            admin: None,
+            token_allowlist: None,
+            max_tokens: None,
+            fee_bps: None,
+            fee_collector: None,
+            appellate_arbiter: None,
+            appeal_window: None,
+            creation_fee: None,
+            strict_parties: None,
+            receipt_nft: None,
+        };
+        let res: Response<ChainMsg> =
+            crate::custom::instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert!(res.messages.is_empty());
+
+        let create = ExecuteMsg::Create(CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        });
+        let info = mock_info("source", &coins(100, "tokens"));
+        crate::custom::execute::<ChainMsg, Empty>(deps.as_mut(), mock_env(), info, create).unwrap();
+        let query = QueryMsg::Details { id: String::from("foobar") };
+        let details: DetailsResponse = from_binary(&crate::custom::query(deps.as_ref(), mock_env(), query).unwrap()).unwrap();
+        assert_eq!(details.native_balance, coins(100, "tokens"));
+        let approve = ExecuteMsg::Approve { id: String::from("foobar") };
+        let res: Response<ChainMsg> =
+            crate::custom::execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+    }
+
+    #[test]
+    fn errors_carry_context() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let approve = ExecuteMsg::Approve { id: String::from("missing") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap_err();
+        assert!(matches!(err, ContractError::NotFound { id } if id == "missing"));
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(msg.clone())).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap_err();
+        assert!(matches!(err, ContractError::IdAlreadyExists { id } if id == "foobar"));
+
+        let accept = ExecuteMsg::Accept { id: String::from("foobar") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("source", &[]), accept).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::Unauthorized { sender, expected } if sender == "source" && expected == "recipient"
+        ));
+
+        let partial = ExecuteMsg::RefundPartial {
+            id: String::from("foobar"),
+            amounts: TokenAmounts {
+                native: coins(150, "tokens"),
+                cw20: vec![],
+                nfts: vec![],
+            },
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), partial).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::InsufficientBalance { token, available, requested }
+                if token == "tokens" && available == Uint128::new(100) && requested == Uint128::new(150)
+        ));
+    }
+
+    #[test]
+    fn token_allowlist() {
+        let mut deps = mock_dependencies();
+
+        let init_msg = InstantiateMsg {
+            admin: Some(String::from("admin")),
+            token_allowlist: None,
+            max_tokens: None,
+            fee_bps: None,
+            fee_collector: None,
+            appellate_arbiter: None,
+            appeal_window: None,
+            creation_fee: None,
+            strict_parties: None,
+            receipt_nft: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), init_msg).unwrap();
+
+        let create = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::Never {},
+            cw20_whitelist: None,
+            ..CreateMsg::default()
+        };
+        let receive = Cw20ReceiveMsg {
+            sender: String::from("source"),
+            amount: Uint128::new(100),
+            msg: to_binary(&ReceiveMsg::Create(create)).unwrap(),
+        };
+        let info = mock_info("my-token", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Receive(receive.clone()))
+            .unwrap_err();
+        assert!(matches!(err, ContractError::TokenNotAllowed { token } if token == "my-token"));
+
+        // only the admin can curate the list
+        let update = ExecuteMsg::UpdateTokenAllowlist {
+            add: vec![String::from("my-token")],
+            remove: vec![],
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), update.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), update).unwrap();
+
+        execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Receive(receive)).unwrap();
+
+        // once removed, top ups with the token fail again
+        let update = ExecuteMsg::UpdateTokenAllowlist {
+            add: vec![],
+            remove: vec![String::from("my-token")],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), update).unwrap();
+        assert!(query_config(deps.as_ref()).unwrap().token_allowlist.is_empty());
+        let top_up = Cw20ReceiveMsg {
+            sender: String::from("source"),
+            amount: Uint128::new(50),
+            msg: to_binary(&ReceiveMsg::TopUp { id: String::from("foobar") }).unwrap(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Receive(top_up)).unwrap_err();
+        assert!(matches!(err, ContractError::TokenNotAllowed { .. }));
+    }
+
+    #[test]
+    fn config_shows_owner_and_token_allowlist() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let config = query_config(deps.as_ref()).unwrap();
+        assert_eq!(config.owner, Some(String::from("admin")));
+        assert_eq!(config.token_allowlist, vec!["my-token"]);
+
+        let update = ExecuteMsg::UpdateTokenAllowlist {
+            add: vec![String::from("other-token"), String::from("new-token")],
+            remove: vec![String::from("my-token")],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), update).unwrap();
+        let config = query_config(deps.as_ref()).unwrap();
+        assert_eq!(config.owner, Some(String::from("admin")));
+        assert_eq!(config.token_allowlist, vec!["new-token", "other-token"]);
+    }
+
+    #[test]
+    fn update_config_and_token_limit() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let config = query_config(deps.as_ref()).unwrap();
+        assert_eq!(
+            config,
+            ConfigResponse {
+                owner: Some(String::from("admin")),
+                token_allowlist: vec![String::from("my-token")],
+                max_tokens: DEFAULT_MAX_TOKENS,
+                fee_bps: 0,
+                fee_collector: String::from("admin"),
+                appellate_arbiter: None,
+                appeal_window: DEFAULT_APPEAL_WINDOW,
+                paused: false,
+                releases_paused: false,
+                keeper_reward: vec![],
+                unclaimed_timeout: None,
+                arbiter_relayers: vec![],
+                max_duration: None,
+                max_open_per_source: None,
+                creation_fee: None,
+                strict_parties: false,
+                receipt_nft: None,
+            }
+        );
+
+        let update = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            max_tokens: Some(2),
+            ..UpdateConfigMsg::default()
+        });
+        let err = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), update.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), update).unwrap();
+        assert_eq!(query_config(deps.as_ref()).unwrap().max_tokens, 2);
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::Never {},
+            cw20_whitelist: None,
+            ..CreateMsg::default()
+        };
+        let funds = vec![coin(10, "atom"), coin(10, "btc"), coin(10, "eth")];
+        let err = execute(deps.as_mut(), mock_env(), mock_info("sender", &funds), ExecuteMsg::Create(msg.clone()))
+            .unwrap_err();
+        assert!(matches!(err, ContractError::TooManyTokens { max: 2 }));
+
+        execute(deps.as_mut(), mock_env(), mock_info("sender", &funds[..2]), ExecuteMsg::Create(msg)).unwrap();
+        // topping up a known denom is fine, a third one is not
+        let top_up = ExecuteMsg::TopUp { id: String::from("foobar") };
+        execute(deps.as_mut(), mock_env(), mock_info("sender", &funds[..1]), top_up.clone()).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("sender", &funds[2..]), top_up).unwrap_err();
+        assert!(matches!(err, ContractError::TooManyTokens { max: 2 }));
+    }
+
+    #[test]
+    fn fee_exempt_partners_pay_no_fees() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            admin: Some(String::from("admin")),
+            fee_bps: Some(1000),
+            fee_collector: Some(String::from("collector")),
+            creation_fee: Some(coin(10, "ujuno")),
+            ..InstantiateMsg::default()
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        let exempt = ExecuteMsg::UpdateFeeExempt {
+            add: vec![String::from("market"), String::from("shop")],
+            remove: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), exempt).unwrap();
+
+        let create = |id: &str, recipient: &str| {
+            ExecuteMsg::Create(CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: recipient.to_string(),
+                ..CreateMsg::default()
+            })
+        };
+        let approve = |id: &str| ExecuteMsg::Approve { id: id.to_string() };
+        let paid_out = |res: Response| -> Vec<CosmosMsg> { res.messages.into_iter().map(|m| m.msg).collect() };
+
+        // created by a partner: no creation fee, no protocol fee
+        let res = execute(deps.as_mut(), mock_env(), mock_info("market", &coins(100, "tokens")), create("a", "seller")).unwrap();
+        assert!(res.messages.is_empty());
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve("a")).unwrap();
+        let to_seller = CosmosMsg::Bank(BankMsg::Send {
+            to_address: String::from("seller"),
+            amount: coins(100, "tokens"),
+        });
+        assert_eq!(paid_out(res), vec![to_seller]);
+
+        // paid to a partner: the creation fee applies, the protocol fee does not
+        let info = mock_info("buyer", &[coin(100, "tokens"), coin(10, "ujuno")]);
+        execute(deps.as_mut(), mock_env(), info, create("b", "shop")).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve("b")).unwrap();
+        let to_shop = CosmosMsg::Bank(BankMsg::Send {
+            to_address: String::from("shop"),
+            amount: coins(100, "tokens"),
+        });
+        assert_eq!(paid_out(res), vec![to_shop]);
+
+        let msg = QueryMsg::FeeExempt { address: String::from("market") };
+        let exempt: FeeExemptResponse = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert!(exempt.exempt);
+    }
+
+    #[test]
+    fn fee_exemption_applies_per_split_payee() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            admin: Some(String::from("admin")),
+            fee_bps: Some(1000),
+            fee_collector: Some(String::from("collector")),
+            ..InstantiateMsg::default()
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        let exempt = ExecuteMsg::UpdateFeeExempt {
+            add: vec![String::from("shop")],
+            remove: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), exempt).unwrap();
+
+        let msg = CreateMsg {
+            id: String::from("sale"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("shop"),
+            splits: Some(vec![(String::from("shop"), 5000), (String::from("agent"), 5000)]),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(200, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+
+        let approve = ExecuteMsg::Approve { id: String::from("sale") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        let sent: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        let send = |to: &str, amount| {
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: to.to_string(),
+                amount: coins(amount, "tokens"),
+            })
+        };
+        // the exempt recipient keeps its share, the agent pays the fee on theirs
+        assert_eq!(sent, vec![send("collector", 10), send("shop", 100), send("agent", 90)]);
+    }
+
+    #[test]
+    fn approve_deducts_snapshotted_fee() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            admin: Some(String::from("admin")),
+            token_allowlist: Some(vec![String::from("my-token")]),
+            max_tokens: None,
+            fee_bps: Some(250),
+            fee_collector: Some(String::from("collector")),
+            appellate_arbiter: None,
+            appeal_window: None,
+            creation_fee: None,
+            strict_parties: None,
+            receipt_nft: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let create = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::Never {},
+            cw20_whitelist: Some(vec![String::from("my-token")]),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &[coin(1000, "atom"), coin(10, "btc")]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(create)).unwrap();
+        let receive = Cw20ReceiveMsg {
+            sender: String::from("sender"),
+            amount: Uint128::new(400),
+            msg: to_binary(&ReceiveMsg::TopUp { id: String::from("foobar") }).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("my-token", &[]), ExecuteMsg::Receive(receive)).unwrap();
+
+        // raising the fee later does not affect the existing escrow
+        let update = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            fee_bps: Some(5000),
+            ..UpdateConfigMsg::default()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), update).unwrap();
+
+        let info = mock_info("arbiter", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Approve { id: String::from("foobar") }).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                // 2.5% of 10btc rounds down to zero, so no fee is taken there
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("collector"),
+                    amount: vec![coin(25, "atom")],
+                }),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: String::from("my-token"),
+                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                        recipient: String::from("collector"),
+                        amount: Uint128::new(10),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("recipient"),
+                    amount: vec![coin(975, "atom"), coin(10, "btc")],
+                }),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: String::from("my-token"),
+                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                        recipient: String::from("recipient"),
+                        amount: Uint128::new(390),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn arbiter_fee_paid_on_resolution() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        for id in ["approved", "refunded"] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                arbiter_fee_bps: Some(100),
+                ..CreateMsg::default()
+            };
+            let info = mock_info("sender", &coins(1000, "tokens"));
+            execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        }
+
+        let info = mock_info("arbiter", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Approve { id: "approved".to_string() })
+            .unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("arbiter"),
+                    amount: coins(10, "tokens"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("recipient"),
+                    amount: coins(990, "tokens"),
+                }),
+            ]
+        );
+
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Refund { id: "refunded".to_string() }).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("arbiter"),
+                amount: coins(10, "tokens"),
+            })
+        );
+        assert_eq!(res.messages.len(), 2);
+
+        // fee above 100% is rejected
+        let msg = CreateMsg {
+            id: String::from("greedy"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            arbiter_fee_bps: Some(10_001),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(1000, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidBps {}));
+    }
+
+    #[test]
+    fn approve_partial() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &[coin(100, "atom"), coin(50, "btc")]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let partial = |native: Vec<Coin>| ExecuteMsg::ApprovePartial {
+            id: String::from("foobar"),
+            amounts: TokenAmounts {
+                native,
+                ..TokenAmounts::default()
+            },
+        };
+
+        // only the arbiter may release
+        let err = execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), partial(coins(10, "atom")))
+            .unwrap_err();
+        assert!(matches!(err, ContractError::NotArbiter { .. }));
+
+        // cannot release more than held, or tokens not held at all
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), partial(coins(101, "atom")))
+            .unwrap_err();
+        assert!(matches!(err, ContractError::InsufficientBalance { token, .. } if token == "atom"));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), partial(coins(1, "eth")))
+            .unwrap_err();
+        assert!(matches!(err, ContractError::InsufficientBalance { token, .. } if token == "eth"));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), partial(coins(0, "atom")))
+            .unwrap_err();
+        assert!(matches!(err, ContractError::ZeroBalance {}));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), partial(coins(100, "atom")))
+            .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "atom"),
+            })
+        );
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.native_balance, coins(50, "btc"));
+
+        // releasing the rest settles the escrow
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), partial(coins(50, "btc"))).unwrap();
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.status, Status::Approved);
+        assert!(details.native_balance.is_empty());
+    }
+
+    #[test]
+    fn approve_tokens() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("mixed"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &[coin(100, "tokens"), coin(5, "stray")]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let approve = |tokens: &[&str]| ExecuteMsg::ApproveTokens {
+            id: String::from("mixed"),
+            tokens: tokens.iter().map(|t| t.to_string()).collect(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), approve(&["tokens"])).unwrap_err();
+        assert!(matches!(err, ContractError::NotArbiter { .. }));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve(&["tokens", "other"])).unwrap_err();
+        assert!(matches!(err, ContractError::TokenNotHeld { token } if token == "other"));
+
+        // only the payment token is released, the stray one stays for a refund
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve(&["tokens"])).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        let details = query_details(deps.as_ref(), mock_env(), String::from("mixed")).unwrap();
+        assert_eq!(details.native_balance, coins(5, "stray"));
+        assert_eq!(details.status, Status::Open);
+    }
+
+    #[test]
+    fn refund_tokens() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("mixed"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &[coin(100, "tokens"), coin(5, "stray")]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let refund = |tokens: &[&str]| ExecuteMsg::RefundTokens {
+            id: String::from("mixed"),
+            tokens: tokens.iter().map(|t| t.to_string()).collect(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("source", &[]), refund(&["stray"])).unwrap_err();
+        assert!(matches!(err, ContractError::NotArbiter { .. }));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), refund(&[])).unwrap_err();
+        assert!(matches!(err, ContractError::ZeroBalance {}));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), refund(&["stray"])).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("source"),
+                amount: coins(5, "stray"),
+            })
+        );
+        let details = query_details(deps.as_ref(), mock_env(), String::from("mixed")).unwrap();
+        assert_eq!(details.native_balance, coins(100, "tokens"));
+        assert_eq!(details.status, Status::Open);
+
+        // returning the last token settles the escrow
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), refund(&["tokens"])).unwrap();
+        let details = query_details(deps.as_ref(), mock_env(), String::from("mixed")).unwrap();
+        assert_eq!(details.status, Status::Refunded);
+    }
+
+    #[test]
+    fn two_sided_swap() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        // the source offers cw20 tokens for native coins of the recipient
+        let msg = CreateMsg {
+            id: String::from("swap"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            swap_for: Some(TokenAmounts {
+                native: coins(50, "ucoin"),
+                ..TokenAmounts::default()
+            }),
+            ..CreateMsg::default()
+        };
+        let receive = Cw20ReceiveMsg {
+            sender: String::from("source"),
+            amount: Uint128::new(100),
+            msg: to_binary(&ReceiveMsg::Create(msg)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("my-token", &[]), ExecuteMsg::Receive(receive)).unwrap();
+
+        let id = || String::from("swap");
+        let err = execute(deps.as_mut(), mock_env(), mock_info("source", &[]), ExecuteMsg::CompleteSwap { id: id() }).unwrap_err();
+        assert!(matches!(err, ContractError::SwapIncomplete { missing } if missing == "50ucoin"));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), ExecuteMsg::Approve { id: id() }).unwrap_err();
+        assert!(matches!(err, ContractError::SwapOnly {}));
+        let info = mock_info("stranger", &coins(50, "ucoin"));
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::TopUp { id: id() }).unwrap_err();
+        assert!(matches!(err, ContractError::NotAllowedFunder { .. }));
+
+        // the recipient can take their side back until the swap completed
+        let info = mock_info("recipient", &coins(50, "ucoin"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::TopUp { id: id() }).unwrap();
+        let info = mock_info("recipient", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::WithdrawSwapDeposit { id: id() }).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(50, "ucoin"),
+            })
+        );
+        let details = query_details(deps.as_ref(), mock_env(), id()).unwrap();
+        assert_eq!(details.native_balance, vec![]);
+        assert_eq!(details.status, Status::Open);
+
+        let info = mock_info("recipient", &coins(50, "ucoin"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::TopUp { id: id() }).unwrap();
+        let info = mock_info("recipient", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::CompleteSwap { id: id() }).unwrap();
+        assert_eq!(res.messages.len(), 2);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("my-token"),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: String::from("recipient"),
+                    amount: Uint128::new(100),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("source"),
+                amount: coins(50, "ucoin"),
+            })
+        );
+        let details = query_details(deps.as_ref(), mock_env(), id()).unwrap();
+        assert_eq!(details.status, Status::Approved);
+    }
+
+    #[test]
+    fn refund_partial() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            cw20_whitelist: Some(vec![String::from("my-token")]),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(100, "atom"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        let receive = Cw20ReceiveMsg {
+            sender: String::from("sender"),
+            amount: Uint128::new(80),
+            msg: to_binary(&ReceiveMsg::TopUp { id: String::from("foobar") }).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("my-token", &[]), ExecuteMsg::Receive(receive)).unwrap();
+
+        let refund = ExecuteMsg::RefundPartial {
+            id: String::from("foobar"),
+            amounts: TokenAmounts {
+                native: coins(30, "atom"),
+                cw20: vec![Cw20Coin {
+                    address: String::from("my-token"),
+                    amount: Uint128::new(80),
+                }],
+                nfts: vec![],
+            },
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("sender", &[]), refund.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotArbiter { .. }));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), refund).unwrap();
+        assert_eq!(res.messages.len(), 2);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("sender"),
+                amount: coins(30, "atom"),
+            })
+        );
+
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.native_balance, coins(70, "atom"));
+        assert!(details.cw20_balance.is_empty());
+    }
+
+    #[test]
+    fn resolve_splits_balance() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &[coin(1000, "atom"), coin(1, "btc")]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let resolve = |recipient_bps| ExecuteMsg::Resolve {
+            id: String::from("foobar"),
+            recipient_bps,
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), resolve(7000)).unwrap_err();
+        assert!(matches!(err, ContractError::NotArbiter { .. }));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), resolve(10_001)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidBps {}));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), resolve(7000)).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("recipient"),
+                    amount: coins(700, "atom"),
+                }),
+                // the indivisible btc goes back to the source
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("sender"),
+                    amount: vec![coin(300, "atom"), coin(1, "btc")],
+                }),
+            ]
+        );
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.status, Status::Resolved);
+    }
+
+    #[test]
+    fn refund_goes_to_source_or_refund_address() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        for (id, refund_address) in [("plain", None), ("treasury", Some(String::from("treasury")))] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                refund_address,
+                ..CreateMsg::default()
+            };
+            let info = mock_info("sender", &coins(100, "tokens"));
+            execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        }
+
+        let info = mock_info("arbiter", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Refund { id: "plain".to_string() })
+            .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("sender"),
+                amount: coins(100, "tokens"),
+            })
+        );
+
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Refund { id: "treasury".to_string() })
+            .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("treasury"),
+                amount: coins(100, "tokens"),
+            })
+        );
+    }
+
+    #[test]
+    fn anyone_can_refund_after_expiration() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let env = mock_env();
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::AtHeight(env.block.height + 10),
+            arbiter_fee_bps: Some(500),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(100, "tokens"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let refund = ExecuteMsg::Refund { id: String::from("foobar") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), refund.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotArbiter { .. }));
+
+        let mut expired = env;
+        expired.block.height += 11;
+        let res = execute(deps.as_mut(), expired, mock_info("anyone", &[]), refund).unwrap();
+        // no arbiter fee, as the arbiter did not act
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("sender"),
+                amount: coins(100, "tokens"),
+            })
+        );
+    }
+
+    #[test]
+    fn settle_expired_follows_on_expiry() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let env = mock_env();
+        for (id, on_expiry) in [("deposit", OnExpiry::ReleaseToRecipient), ("order", OnExpiry::RefundToSource)] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                expires: Expiration::AtHeight(env.block.height + 10),
+                on_expiry: Some(on_expiry),
+                ..CreateMsg::default()
+            };
+            let info = mock_info("sender", &coins(100, "tokens"));
+            execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Create(msg)).unwrap();
+        }
+
+        let settle = |id: &str| ExecuteMsg::SettleExpired { id: id.to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), settle("deposit")).unwrap_err();
+        assert!(matches!(err, ContractError::NotExpired {}));
+
+        let mut expired = env;
+        expired.block.height += 11;
+
+        // a deposit that releases on expiry cannot be refunded by anyone but the arbiter
+        let refund = ExecuteMsg::Refund { id: String::from("deposit") };
+        let err = execute(deps.as_mut(), expired.clone(), mock_info("anyone", &[]), refund).unwrap_err();
+        assert!(matches!(err, ContractError::NotArbiter { .. }));
+
+        let res = execute(deps.as_mut(), expired.clone(), mock_info("anyone", &[]), settle("deposit")).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+
+        let res = execute(deps.as_mut(), expired, mock_info("anyone", &[]), settle("order")).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("sender"),
+                amount: coins(100, "tokens"),
+            })
+        );
+    }
+
+    #[test]
+    fn settled_escrows_are_archived() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        for id in ["one", "two", "three"] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                ..CreateMsg::default()
+            };
+            let info = mock_info("sender", &coins(100, "tokens"));
+            execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        }
+
+        let info = mock_info("arbiter", &[]);
+        execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Approve { id: "one".to_string() }).unwrap();
+        execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Refund { id: "two".to_string() }).unwrap();
+
+        let details = query_details(deps.as_ref(), mock_env(), "one".to_string()).unwrap();
+        assert_eq!(details.status, Status::Approved);
+        assert!(details.native_balance.is_empty());
+        let details = query_details(deps.as_ref(), mock_env(), "two".to_string()).unwrap();
+        assert_eq!(details.status, Status::Refunded);
+
+        // settled escrows cannot be acted upon again
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Refund { id: "one".to_string() }).unwrap_err();
+        assert!(matches!(err, ContractError::AlreadySettled { status: Status::Approved }));
+        let info = mock_info("sender", &coins(50, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::TopUp { id: "two".to_string() }).unwrap_err();
+        assert!(matches!(err, ContractError::AlreadySettled { status: Status::Refunded }));
+
+        let page = query_list(deps.as_ref(), None, None, Some(Status::Open), None).unwrap();
+        assert_eq!(page.escrows, vec!["three"]);
+        let page = query_list(deps.as_ref(), None, None, Some(Status::Refunded), None).unwrap();
+        assert_eq!(page.escrows, vec!["two"]);
+        // only active escrows are iterated, settled ones live in the archive
+        let page = query_list(deps.as_ref(), None, None, None, None).unwrap();
+        assert_eq!(page.escrows, vec!["three"]);
+        let page = query_list_archived(deps.as_ref(), None, None).unwrap();
+        assert_eq!(page.escrows, vec!["one", "two"]);
+
+        // archived ids cannot be reused
+        let msg = CreateMsg {
+            id: "one".to_string(),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(100, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap_err();
+        assert!(matches!(err, ContractError::IdAlreadyExists { .. }));
+    }
+
+    #[test]
+    fn state_transitions_emit_events() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::AtHeight(123456),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &[coin(100, "atom"), coin(5, "btc")]);
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        assert_eq!(res.events.len(), 1);
+        let event = &res.events[0];
+        assert_eq!(event.ty, "escrow_created");
+        assert_eq!(
+            event.attributes,
+            vec![
+                attr("id", "foobar"),
+                attr("arbiters", "arbiter"),
+                attr("recipient", "recipient"),
+                attr("source", "sender"),
+                attr("amount", "100atom,5btc"),
+                attr("on_expiry", "refund_to_source"),
+                attr("expires", Expiration::AtHeight(123456).to_string()),
+            ]
+        );
+
+        let info = mock_info("sender", &coins(50, "atom"));
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::TopUp { id: String::from("foobar") }).unwrap();
+        assert_eq!(res.events[0].ty, "escrow_topped_up");
+        assert_eq!(res.events[0].attributes[1], attr("amount", "50atom"));
+        // the new totals of the deposited tokens only
+        assert_eq!(res.events[0].attributes[2], attr("balance", "150atom"));
+
+        let info = mock_info("arbiter", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Approve { id: String::from("foobar") }).unwrap();
+        assert_eq!(res.events[0].ty, "escrow_approved");
+        assert_eq!(
+            res.events[0].attributes,
+            vec![attr("id", "foobar"), attr("recipient", "recipient"), attr("amount", "150atom,5btc")]
+        );
+    }
+
+    #[test]
+    fn responses_carry_data() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(100, "atom"));
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        let data: CreateResponse = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(data.id, "foobar");
+
+        let info = mock_info("arbiter", &[]);
+        let partial = ExecuteMsg::RefundPartial {
+            id: String::from("foobar"),
+            amounts: TokenAmounts {
+                native: coins(30, "atom"),
+                ..TokenAmounts::default()
+            },
+        };
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), partial).unwrap();
+        let data: PayoutResponse = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(data.native, coins(30, "atom"));
+
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Approve { id: String::from("foobar") }).unwrap();
+        let data: PayoutResponse = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(
+            data,
+            PayoutResponse {
+                id: String::from("foobar"),
+                native: coins(70, "atom"),
+                cw20: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn create_with_deal_context() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            title: Some(String::from("Vintage bike")),
+            description: Some(String::from("Red, barely used")),
+            metadata: Some(Binary::from(b"{\"listing\":42}")),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(100, "atom"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.title.as_deref(), Some("Vintage bike"));
+        assert_eq!(details.description.as_deref(), Some("Red, barely used"));
+        assert_eq!(details.metadata, Some(Binary::from(b"{\"listing\":42}")));
+    }
+
+    #[test]
+    fn terms_hash_is_validated() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let create = |id: &str, terms_hash: &str| {
+            ExecuteMsg::Create(CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                terms_hash: Some(terms_hash.to_string()),
+                ..CreateMsg::default()
+            })
+        };
+        let info = mock_info("sender", &coins(100, "atom"));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), create("short", "abcd")).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidTermsHash {}));
+        let not_hex = "z".repeat(64);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), create("nothex", &not_hex)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidTermsHash {}));
+
+        let hash = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+        execute(deps.as_mut(), mock_env(), info, create("foobar", hash)).unwrap();
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.terms_hash, Some(hash.to_ascii_lowercase()));
+    }
+
+    #[test]
+    fn any_of_multiple_arbiters() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: Arbiters::Many(vec!["alice".to_string(), "bob".to_string(), "alice".to_string()]),
+            recipient: String::from("recipient"),
+            arbiter_fee_bps: Some(1000),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(100, "tokens"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.arbiters, vec!["alice", "bob"]);
+        for arbiter in ["alice", "bob"] {
+            let page = query_list_by_arbiter(deps.as_ref(), arbiter.to_string(), None, None).unwrap();
+            assert_eq!(page.escrows, vec!["foobar"]);
+        }
+
+        let approve = ExecuteMsg::Approve { id: String::from("foobar") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("carol", &[]), approve.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotArbiter { .. }));
+
+        // bob decides, so bob gets the arbiter fee
+        let res = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), approve).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("bob"),
+                amount: coins(10, "tokens"),
+            })
+        );
+        let page = query_list_by_arbiter(deps.as_ref(), "alice".to_string(), None, None).unwrap();
+        assert!(page.escrows.is_empty());
+
+        let msg = CreateMsg {
+            id: String::from("nobody"),
+            arbiter: Arbiters::Many(vec![]),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(100, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap_err();
+        assert!(matches!(err, ContractError::NoArbiters {}));
+    }
+
+    #[test]
+    fn threshold_voting() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let arbiters = Arbiters::Many(vec!["alice".to_string(), "bob".to_string(), "carol".to_string()]);
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: arbiters.clone(),
+            threshold: Some(4),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(100, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(msg.clone())).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidThreshold {}));
+        let msg = CreateMsg { threshold: Some(2), ..msg };
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        // nobody decides alone
+        let approve = ExecuteMsg::Approve { id: String::from("foobar") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), approve).unwrap_err();
+        assert!(matches!(err, ContractError::VoteRequired {}));
+
+        let vote = |verdict| ExecuteMsg::CastVote { id: String::from("foobar"), verdict };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("dave", &[]), vote(Verdict::Release)).unwrap_err();
+        assert!(matches!(err, ContractError::NotArbiter { .. }));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), vote(Verdict::Release)).unwrap();
+        assert!(res.messages.is_empty());
+        let res = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), vote(Verdict::Refund)).unwrap();
+        assert!(res.messages.is_empty());
+        let votes = query_votes(deps.as_ref(), String::from("foobar")).unwrap();
+        assert_eq!(
+            votes.votes,
+            vec![
+                VoteInfo { arbiter: "alice".to_string(), verdict: Verdict::Release },
+                VoteInfo { arbiter: "bob".to_string(), verdict: Verdict::Refund },
+            ]
+        );
+
+        // the second matching vote executes the release
+        let res = execute(deps.as_mut(), mock_env(), mock_info("carol", &[]), vote(Verdict::Release)).unwrap();
+        assert_eq!(res.events[0].ty, "escrow_vote_cast");
+        assert_eq!(res.events[1].ty, "escrow_approved");
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.status, Status::Approved);
+        assert!(query_votes(deps.as_ref(), String::from("foobar")).unwrap().votes.is_empty());
+    }
+
+    #[test]
+    fn mutual_approve_without_arbiter() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            arbiter_fee_bps: Some(1000),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(100, "tokens"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let mutual = ExecuteMsg::MutualApprove { id: String::from("foobar") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), mutual.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), mutual.clone()).unwrap();
+        assert!(res.messages.is_empty());
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert!(details.recipient_approved);
+        assert!(!details.source_approved);
+
+        // once both consented, everything goes to the recipient, no arbiter fee
+        let res = execute(deps.as_mut(), mock_env(), mock_info("sender", &[]), mutual).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.status, Status::Approved);
+    }
+
+    #[test]
+    fn recipient_acceptance() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        for id in ["accepted", "cancelled"] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                requires_acceptance: Some(true),
+                ..CreateMsg::default()
+            };
+            let info = mock_info("sender", &coins(100, "tokens"));
+            execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        }
+        let details = query_details(deps.as_ref(), mock_env(), String::from("accepted")).unwrap();
+        assert_eq!(details.status, Status::Pending);
+
+        let approve = ExecuteMsg::Approve { id: String::from("accepted") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotAccepted {}));
+
+        let accept = ExecuteMsg::Accept { id: String::from("accepted") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("sender", &[]), accept.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), accept.clone()).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), accept).unwrap_err();
+        assert!(matches!(err, ContractError::NotPending {}));
+
+        // accepted escrows can no longer be cancelled, but approved
+        let cancel = |id: &str| ExecuteMsg::Cancel { id: id.to_string() };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("sender", &[]), cancel("accepted")).unwrap_err();
+        assert!(matches!(err, ContractError::NotPending {}));
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("sender", &[]), cancel("cancelled")).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("sender"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        let details = query_details(deps.as_ref(), mock_env(), String::from("cancelled")).unwrap();
+        assert_eq!(details.status, Status::Cancelled);
+    }
+
+    #[test]
+    fn funding_until_target_reached() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            funding_target: Some(TokenAmounts {
+                native: coins(100, "tokens"),
+                ..TokenAmounts::default()
+            }),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(60, "tokens"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        let funding = query_funding(deps.as_ref(), String::from("foobar")).unwrap();
+        assert_eq!(funding.status, Status::Funding);
+        assert_eq!(funding.missing.native, coins(40, "tokens"));
+        assert!(!funding.funded);
+
+        let approve = ExecuteMsg::Approve { id: String::from("foobar") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap_err();
+        assert!(matches!(err, ContractError::NotFunded {}));
+
+        let top_up = ExecuteMsg::TopUp { id: String::from("foobar") };
+        execute(deps.as_mut(), mock_env(), mock_info("sender", &coins(40, "tokens")), top_up).unwrap();
+        let funding = query_funding(deps.as_ref(), String::from("foobar")).unwrap();
+        assert_eq!(funding.status, Status::Open);
+        assert!(funding.missing.native.is_empty());
+        assert!(funding.funded);
+
+        // once active, the source can no longer pull out
+        let cancel = ExecuteMsg::Cancel { id: String::from("foobar") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("sender", &[]), cancel).unwrap_err();
+        assert!(matches!(err, ContractError::NotPending {}));
+    }
+
+    #[test]
+    fn set_recipient() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        for (id, arbiter_can_set_recipient) in [("by_recipient", None), ("by_arbiter", Some(true))] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                arbiter_can_set_recipient,
+                ..CreateMsg::default()
+            };
+            let info = mock_info("sender", &coins(100, "tokens"));
+            execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        }
+
+        let set = |id: &str, new_recipient: &str| ExecuteMsg::SetRecipient {
+            id: id.to_string(),
+            new_recipient: new_recipient.to_string(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), set("by_recipient", "thief")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("sender", &[]), set("by_arbiter", "thief")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), set("by_recipient", "cold_wallet")).unwrap();
+        assert_eq!(res.events[0].ty, "escrow_recipient_changed");
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), set("by_arbiter", "cold_wallet")).unwrap();
+        for id in ["by_recipient", "by_arbiter"] {
+            let details = query_details(deps.as_ref(), mock_env(), id.to_string()).unwrap();
+            assert_eq!(details.recipient, "cold_wallet");
+        }
+    }
+
+    #[test]
+    fn weighted_splits() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("sale"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("seller"),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("buyer", &coins(101, "tokens"));
+        let with_splits = |splits: &[(&str, u16)]| {
+            let splits = splits.iter().map(|(address, bps)| (address.to_string(), *bps)).collect();
+            ExecuteMsg::Create(CreateMsg { splits: Some(splits), ..msg.clone() })
+        };
+        for invalid in [&[("seller", 10000)][..], &[("seller", 7000), ("agent", 2000)], &[("seller", 5000), ("seller", 5000)]] {
+            let err = execute(deps.as_mut(), mock_env(), info.clone(), with_splits(invalid)).unwrap_err();
+            assert!(matches!(err, ContractError::InvalidSplits { .. }));
+        }
+        let splits = [("seller", 7000), ("agent", 2000), ("platform", 1000)];
+        execute(deps.as_mut(), mock_env(), info, with_splits(&splits)).unwrap();
+
+        let approve = ExecuteMsg::Approve { id: String::from("sale") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        let sent: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        let send = |to: &str, amount| {
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: to.to_string(),
+                amount: coins(amount, "tokens"),
+            })
+        };
+        // the first recipient gets what rounding leaves
+        assert_eq!(sent, vec![send("seller", 71), send("agent", 20), send("platform", 10)]);
+    }
+
+    #[test]
+    fn transfer_source_rights() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("deal"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            refund_address: Some(String::from("treasury")),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let transfer = ExecuteMsg::TransferSourceRights {
+            id: String::from("deal"),
+            new_source: String::from("buyer"),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), transfer.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let res = execute(deps.as_mut(), mock_env(), mock_info("source", &[]), transfer).unwrap();
+        assert_eq!(res.events[0].ty, "escrow_source_changed");
+        assert_eq!(
+            res.events[0].attributes[1..],
+            [
+                attr("old_source", "source"),
+                attr("new_source", "buyer"),
+                attr("recipient", "recipient"),
+                attr("cleared_refund_address", "treasury"),
+            ]
+        );
+        assert_eq!(query_open_escrows(deps.as_ref(), String::from("source")).unwrap().open, 0);
+        assert_eq!(query_open_escrows(deps.as_ref(), String::from("buyer")).unwrap().open, 1);
+
+        // the old source has no say anymore, refunds go to the new one
+        let cancel = ExecuteMsg::Cancel { id: String::from("deal") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("source", &[]), cancel).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let refund = ExecuteMsg::Refund { id: String::from("deal") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), refund).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("buyer"),
+                amount: coins(100, "tokens"),
+            })
+        );
+    }
+
+    #[test]
+    fn transfer_source_rights_clears_remote_refund_address() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("deal"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            remote_refund_address: Some(RemoteAddress {
+                chain_channel: String::from("channel-7"),
+                address: String::from("osmo1source"),
+                memo: None,
+            }),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let transfer = ExecuteMsg::TransferSourceRights {
+            id: String::from("deal"),
+            new_source: String::from("buyer"),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("source", &[]), transfer).unwrap();
+        assert_eq!(res.events[0].attributes[4], attr("cleared_remote_refund_address", "osmo1source"));
+        let details = query_details(deps.as_ref(), mock_env(), String::from("deal")).unwrap();
+        assert_eq!(details.remote_refund_address, None);
+
+        // the refund stays on this chain, with the new source
+        let refund = ExecuteMsg::Refund { id: String::from("deal") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), refund).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("buyer"),
+                amount: coins(100, "tokens"),
+            })
+        );
+    }
+
+    #[test]
+    fn strict_parties() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("self_dealing"),
+            arbiter: "sender".into(),
+            recipient: String::from("recipient"),
+            arbiter_can_set_recipient: Some(true),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(100, "tokens"));
+        // lenient by default
+        execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(msg.clone())).unwrap();
+
+        let strict = CreateMsg {
+            id: String::from("strict"),
+            arbiter: "arbiter".into(),
+            strict_parties: Some(true),
+            ..msg.clone()
+        };
+        execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(strict)).unwrap();
+        let set = ExecuteMsg::SetRecipient {
+            id: String::from("strict"),
+            new_recipient: String::from("arbiter"),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), set).unwrap_err();
+        assert!(matches!(err, ContractError::OverlappingParties { address, .. } if address == "arbiter"));
+
+        let update = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            strict_parties: Some(true),
+            ..UpdateConfigMsg::default()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), update).unwrap();
+        let invalid = [
+            ("sender", "recipient", "arbiter and source"),
+            ("arbiter", "arbiter", "arbiter and recipient"),
+            ("arbiter", "sender", "source and recipient"),
+        ];
+        for (arbiter, recipient, overlap) in invalid {
+            let msg = CreateMsg {
+                id: String::from("foobar"),
+                arbiter: arbiter.into(),
+                recipient: recipient.to_string(),
+                ..CreateMsg::default()
+            };
+            let err = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(msg)).unwrap_err();
+            assert!(matches!(err, ContractError::OverlappingParties { roles, .. } if roles == overlap));
+        }
+        let details = query_details(deps.as_ref(), mock_env(), String::from("strict")).unwrap();
+        assert!(details.strict_parties);
+    }
+
+    #[test]
+    fn max_duration_limits_expiry() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+        let update = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            max_duration: Some(Duration::Time(1000)),
+            ..UpdateConfigMsg::default()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), update).unwrap();
+
+        let env = mock_env();
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::AtTime(env.block.time.plus_seconds(1001)),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        // too far out, never, or in blocks
+        let invalid = [
+            msg.expires,
+            Expiration::Never {},
+            Expiration::AtHeight(env.block.height + 1),
+        ];
+        for expires in invalid {
+            let msg = CreateMsg { expires, ..msg.clone() };
+            let err = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Create(msg)).unwrap_err();
+            assert!(matches!(err, ContractError::DurationTooLong { max } if max == Duration::Time(1000)));
+        }
+        let expires = Expiration::AtTime(env.block.time.plus_seconds(1000));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Create(CreateMsg { expires, ..msg })).unwrap();
+
+        // extending counts from now
+        let mut env = env;
+        env.block.time = env.block.time.plus_seconds(500);
+        let extend = |expires| ExecuteMsg::ExtendExpiry {
+            id: String::from("foobar"),
+            expires,
+        };
+        let too_far = Expiration::AtTime(env.block.time.plus_seconds(1001));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("source", &[]), extend(too_far)).unwrap_err();
+        assert!(matches!(err, ContractError::DurationTooLong { .. }));
+        let later = Expiration::AtTime(env.block.time.plus_seconds(1000));
+        execute(deps.as_mut(), env, mock_info("source", &[]), extend(later)).unwrap();
+    }
+
+    #[test]
+    fn open_escrows_per_source_are_limited() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+        let update = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            max_open_per_source: Some(1),
+            ..UpdateConfigMsg::default()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), update).unwrap();
+
+        let create = |id: &str| {
+            ExecuteMsg::Create(CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                ..CreateMsg::default()
+            })
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        execute(deps.as_mut(), mock_env(), info.clone(), create("first")).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), create("second")).unwrap_err();
+        assert!(matches!(err, ContractError::TooManyOpenEscrows { max: 1, .. }));
+        // others are not affected
+        execute(deps.as_mut(), mock_env(), mock_info("other", &coins(100, "tokens")), create("other")).unwrap();
+
+        // settling frees a slot
+        let approve = ExecuteMsg::Approve { id: String::from("first") };
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        execute(deps.as_mut(), mock_env(), info.clone(), create("second")).unwrap();
+
+        let exempt = ExecuteMsg::UpdateOpenLimitExempt {
+            add: vec![String::from("source")],
+            remove: vec![],
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("source", &[]), exempt.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), exempt).unwrap();
+        execute(deps.as_mut(), mock_env(), info, create("third")).unwrap();
+        let msg = QueryMsg::OpenEscrows { source: String::from("source") };
+        let open: OpenEscrowsResponse = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(open, OpenEscrowsResponse { open: 2, exempt: true });
+    }
+
+    #[test]
+    fn creation_fee_goes_to_fee_collector() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            fee_collector: Some(String::from("collector")),
+            creation_fee: Some(coin(10, "ujuno")),
+            ..InstantiateMsg::default()
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+
+        let create = ExecuteMsg::Create(CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        });
+        let underpaid = mock_info("source", &[coin(100, "tokens"), coin(9, "ujuno")]);
+        let err = execute(deps.as_mut(), mock_env(), underpaid, create.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::CreationFeeNotPaid { fee } if fee == coin(10, "ujuno")));
+
+        let info = mock_info("source", &[coin(100, "tokens"), coin(10, "ujuno")]);
+        let res = execute(deps.as_mut(), mock_env(), info, create).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("collector"),
+                amount: coins(10, "ujuno"),
+            })
+        );
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.native_balance, coins(100, "tokens"));
+    }
+
+    #[test]
+    fn extend_expiry() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let env = mock_env();
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::AtHeight(env.block.height + 10),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(100, "tokens"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let extend = |expires| ExecuteMsg::ExtendExpiry {
+            id: String::from("foobar"),
+            expires,
+        };
+        let later = Expiration::AtHeight(env.block.height + 100);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), extend(later)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let sooner = Expiration::AtHeight(env.block.height + 5);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("sender", &[]), extend(sooner)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidExpiry {}));
+        // heights and times do not compare
+        let at_time = Expiration::AtTime(env.block.time.plus_seconds(1000));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("sender", &[]), extend(at_time)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidExpiry {}));
+
+        execute(deps.as_mut(), env.clone(), mock_info("sender", &[]), extend(later)).unwrap();
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.expires, later);
+
+        // past the original deadline, the escrow is still running
+        let mut env = env;
+        env.block.height += 11;
+        let settle = ExecuteMsg::SettleExpired { id: String::from("foobar") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), settle).unwrap_err();
+        assert!(matches!(err, ContractError::NotExpired {}));
+
+        // dropping the deadline is fine, but then there is nothing left to extend
+        execute(deps.as_mut(), env.clone(), mock_info("sender", &[]), extend(Expiration::Never {})).unwrap();
+        let err = execute(deps.as_mut(), env, mock_info("sender", &[]), extend(later)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidExpiry {}));
+    }
+
+    #[test]
+    fn linear_vesting_claims() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        let start = env.block.time.seconds() + 100;
+        let msg = CreateMsg {
+            id: String::from("grant"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            schedule: Some(Schedule::Linear {
+                start_time: start,
+                end_time: start + 1000,
+                cliff: None,
+            }),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(1000, "tokens"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let claim = ExecuteMsg::Claim { id: String::from("grant") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), claim.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToClaim {}));
+
+        env.block.time = env.block.time.plus_seconds(350);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("sender", &[]), claim.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let res = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), claim.clone()).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(250, "tokens"),
+            })
+        );
+
+        // a top-up vests on the same schedule, the vested part of it is claimable at once
+        let top_up = ExecuteMsg::TopUp { id: String::from("grant") };
+        execute(deps.as_mut(), env.clone(), mock_info("sender", &coins(1000, "tokens")), top_up).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), claim.clone()).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(250, "tokens"),
+            })
+        );
+        let details = query_details(deps.as_ref(), mock_env(), String::from("grant")).unwrap();
+        assert_eq!(details.claimed.native, coins(500, "tokens"));
+        assert_eq!(details.native_balance, coins(1500, "tokens"));
+
+        env.block.time = env.block.time.plus_seconds(1000);
+        execute(deps.as_mut(), env, mock_info("recipient", &[]), claim).unwrap();
+        let details = query_details(deps.as_ref(), mock_env(), String::from("grant")).unwrap();
+        assert_eq!(details.status, Status::Approved);
+        assert_eq!(details.claimed.native, coins(2000, "tokens"));
+    }
+
+    #[test]
+    fn periodic_schedule_until_stopped() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        let msg = CreateMsg {
+            id: String::from("retainer"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            schedule: Some(Schedule::Periodic {
+                start_time: env.block.time.seconds(),
+                interval: 100,
+                periods: 4,
+            }),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("sender", &coins(400, "tokens"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let claim = ExecuteMsg::Claim { id: String::from("retainer") };
+        let paid = |amount| {
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(amount, "tokens"),
+            })
+        };
+        // the first period is paid up front, then one per interval
+        let res = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), claim.clone()).unwrap();
+        assert_eq!(res.messages[0].msg, paid(100));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), claim.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToClaim {}));
+
+        // the arbiter cancels the last period while the third runs
+        env.block.time = env.block.time.plus_seconds(250);
+        let stop = ExecuteMsg::StopSchedule { id: String::from("retainer") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), stop.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotArbiter { .. }));
+        let res = execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), stop.clone()).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("sender"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        let err = execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), stop).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSchedule { .. }));
+
+        // the started periods can still be claimed, even later
+        env.block.time = env.block.time.plus_seconds(1000);
+        let res = execute(deps.as_mut(), env, mock_info("recipient", &[]), claim).unwrap();
+        assert_eq!(res.messages[0].msg, paid(200));
+        let details = query_details(deps.as_ref(), mock_env(), String::from("retainer")).unwrap();
+        assert_eq!(details.status, Status::Approved);
+    }
+
+    #[test]
+    fn piecewise_vesting_with_cliff() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        let start = env.block.time.seconds();
+        let create = |schedule| ExecuteMsg::Create(CreateMsg {
+            id: String::from("grant"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            schedule: Some(schedule),
+            ..CreateMsg::default()
+        });
+        let info = mock_info("sender", &coins(1000, "tokens"));
+
+        // curves have to be ordered and vest everything in the end
+        let bad = Schedule::Piecewise {
+            points: vec![(start + 100, 2500), (start + 50, 10000)],
+            cliff: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), create(bad)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSchedule { .. }));
+        let bad = Schedule::Piecewise {
+            points: vec![(start + 100, 2500), (start + 200, 5000)],
+            cliff: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), create(bad)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSchedule { .. }));
+
+        // a quarter after 100s, jumping to half at 200s, then linear to all at 400s.
+        // Nothing can be claimed before the cliff at 150s
+        let schedule = Schedule::Piecewise {
+            points: vec![(start + 100, 2500), (start + 200, 2500), (start + 200, 5000), (start + 400, 10000)],
+            cliff: Some(start + 150),
+        };
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), create(schedule)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSchedule { .. }));
+        let schedule = Schedule::Piecewise {
+            points: vec![(start + 100, 2500), (start + 199, 2500), (start + 200, 5000), (start + 400, 10000)],
+            cliff: Some(start + 150),
+        };
+        execute(deps.as_mut(), env.clone(), info, create(schedule)).unwrap();
+
+        let claim = ExecuteMsg::Claim { id: String::from("grant") };
+        env.block.time = env.block.time.plus_seconds(120);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), claim.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToClaim {}));
+
+        env.block.time = env.block.time.plus_seconds(30);
+        let res = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), claim.clone()).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(250, "tokens"),
+            })
+        );
+
+        env.block.time = env.block.time.plus_seconds(150);
+        let res = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), claim).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(500, "tokens"),
+            })
+        );
+    }
+
+    #[test]
+    fn escrow_nft_with_coins() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let create = ReceiveMsg::Create(CreateMsg {
+            id: String::from("punk"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        });
+        let receive = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg {
+            sender: String::from("source"),
+            token_id: String::from("42"),
+            msg: to_binary(&create).unwrap(),
+        });
+
+        // the collection has to be allowlisted
+        let err = execute(deps.as_mut(), mock_env(), mock_info("punks", &[]), receive.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::TokenNotAllowed { token } if token == "punks"));
+        let allow = ExecuteMsg::UpdateTokenAllowlist {
+            add: vec![String::from("punks")],
+            remove: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), allow).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("punks", &[]), receive).unwrap();
+
+        let top_up = ExecuteMsg::TopUp { id: String::from("punk") };
+        let info = mock_info("source", &coins(100, "tokens"));
+        execute(deps.as_mut(), mock_env(), info, top_up).unwrap();
+        let details = query_details(deps.as_ref(), mock_env(), String::from("punk")).unwrap();
+        assert_eq!(details.nft_balance, vec![(String::from("punks"), String::from("42"))]);
+        assert_eq!(details.native_balance, coins(100, "tokens"));
+
+        let approve = ExecuteMsg::Approve { id: String::from("punk") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        assert_eq!(2, res.messages.len());
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("punks"),
+                msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                    recipient: String::from("recipient"),
+                    token_id: String::from("42"),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn fee_on_transfer_deposit() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let set_balance = |deps: &mut cosmwasm_std::OwnedDeps<_, _, cosmwasm_std::testing::MockQuerier>, amount: u128| {
+            deps.querier.update_wasm(move |_| {
+                let res = BalanceResponse { balance: Uint128::new(amount) };
+                SystemResult::Ok(ContractResult::Ok(to_binary(&res).unwrap()))
+            });
+        };
+        // the contract already holds tokens of other escrows
+        set_balance(&mut deps, 1000);
+
+        let deposit = ExecuteMsg::DepositCw20 {
+            token: String::from("my-token"),
+            amount: Uint128::new(100),
+            msg: ReceiveMsg::Create(CreateMsg {
+                id: String::from("deflationary"),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                ..CreateMsg::default()
+            }),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("source", &[]), deposit).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::reply_on_success(
+                WasmMsg::Execute {
+                    contract_addr: String::from("my-token"),
+                    msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                        owner: String::from("source"),
+                        recipient: mock_env().contract.address.into_string(),
+                        amount: Uint128::new(100),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                },
+                DEPOSIT_REPLY_ID
+            )]
+        );
+
+        // a 5% transfer fee was taken
+        set_balance(&mut deps, 1095);
+        let transferred = Reply {
+            id: DEPOSIT_REPLY_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        };
+        reply(deps.as_mut(), mock_env(), transferred).unwrap();
+
+        let details = query_details(deps.as_ref(), mock_env(), String::from("deflationary")).unwrap();
+        assert_eq!(details.source, "source");
+        assert_eq!(
+            details.cw20_balance,
+            vec![Cw20Coin {
+                address: String::from("my-token"),
+                amount: Uint128::new(95),
+            }]
+        );
+        assert!(PENDING_DEPOSIT.may_load(&deps.storage).unwrap().is_none());
+    }
+
+    #[test]
+    fn create_from_allowance() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+        deps.querier.update_wasm(|_| {
+            let res = BalanceResponse { balance: Uint128::zero() };
+            SystemResult::Ok(ContractResult::Ok(to_binary(&res).unwrap()))
+        });
+
+        let create = ExecuteMsg::CreateFromAllowance {
+            msg: CreateMsg {
+                id: String::from("pulled"),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                ..CreateMsg::default()
+            },
+            token: String::from("my-token"),
+            amount: Uint128::new(100),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("source", &[]), create).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].id, DEPOSIT_REPLY_ID);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("my-token"),
+                msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                    owner: String::from("source"),
+                    recipient: mock_env().contract.address.into_string(),
+                    amount: Uint128::new(100),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+        // nothing is created until the transfer went through
+        assert!(query_details(deps.as_ref(), mock_env(), String::from("pulled")).is_err());
+
+        deps.querier.update_wasm(|_| {
+            let res = BalanceResponse { balance: Uint128::new(100) };
+            SystemResult::Ok(ContractResult::Ok(to_binary(&res).unwrap()))
+        });
+        let transferred = Reply {
+            id: DEPOSIT_REPLY_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        };
+        let res = reply(deps.as_mut(), mock_env(), transferred).unwrap();
+        assert_eq!(res.events[0].ty, "escrow_created");
+
+        let details = query_details(deps.as_ref(), mock_env(), String::from("pulled")).unwrap();
+        assert_eq!(details.source, "source");
+        assert_eq!(
+            details.cw20_balance,
+            vec![Cw20Coin {
+                address: String::from("my-token"),
+                amount: Uint128::new(100),
+            }]
+        );
+        assert_eq!(details.cw20_whitelist, vec!["my-token"]);
+    }
+
+    #[test]
+    fn release_hook_sends_cw20_to_recipient_contract() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let hook = to_binary(&"stake").unwrap();
+        for id in ["hooked", "refunded"] {
+            let create = ReceiveMsg::Create(CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("staking"),
+                release_hook: Some(hook.clone()),
+                ..CreateMsg::default()
+            });
+            let receive = ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: String::from("source"),
+                amount: Uint128::new(100),
+                msg: to_binary(&create).unwrap(),
+            });
+            execute(deps.as_mut(), mock_env(), mock_info("my-token", &[]), receive).unwrap();
+        }
+        let details = query_details(deps.as_ref(), mock_env(), String::from("hooked")).unwrap();
+        assert_eq!(details.release_hook, Some(hook.clone()));
+
+        let approve = ExecuteMsg::Approve { id: String::from("hooked") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("my-token"),
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: String::from("staking"),
+                    amount: Uint128::new(100),
+                    msg: hook,
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+
+        // refunds go back as plain transfers
+        let refund = ExecuteMsg::Refund { id: String::from("refunded") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), refund).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("my-token"),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: String::from("source"),
+                    amount: Uint128::new(100),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn payout_callback_on_recipient_contract() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("order"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("shop"),
+            payout_callback: Some(true),
+            arbiter_fee_bps: Some(1000),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &[coin(100, "tokens"), coin(10, "atom")]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        assert!(query_details(deps.as_ref(), mock_env(), String::from("order")).unwrap().payout_callback);
+
+        let approve = ExecuteMsg::Approve { id: String::from("order") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|msg| msg.msg).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                // the arbiter fee is paid as usual
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("arbiter"),
+                    amount: vec![coin(1, "atom"), coin(10, "tokens")],
+                }),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: String::from("shop"),
+                    msg: to_binary(&PayoutCallbackMsg::EscrowPayout { id: String::from("order") }).unwrap(),
+                    funds: vec![coin(9, "atom"), coin(90, "tokens")],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn pull_payouts_wait_for_claim() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("pull"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            pull_payouts: Some(true),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg.clone())).unwrap();
+        let vesting = CreateMsg {
+            id: String::from("vesting"),
+            schedule: Some(Schedule::Linear {
+                start_time: 0,
+                end_time: 1000,
+                cliff: None,
+            }),
+            ..msg
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(vesting)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSchedule { .. }));
+
+        let claimable = |deps: Deps| -> Vec<ClaimableInfo> { query_claimable(deps, String::from("pull")).unwrap().claimable };
+        let partial = ExecuteMsg::ApprovePartial {
+            id: String::from("pull"),
+            amounts: TokenAmounts {
+                native: coins(40, "tokens"),
+                ..TokenAmounts::default()
+            },
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), partial).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(claimable(deps.as_ref())[0].amounts.native, coins(40, "tokens"));
+
+        // approvals add up until the recipient withdraws
+        let approve = ExecuteMsg::Approve { id: String::from("pull") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        assert!(res.messages.is_empty());
+        let details = query_details(deps.as_ref(), mock_env(), String::from("pull")).unwrap();
+        assert_eq!(details.status, Status::Approved);
+        assert_eq!(claimable(deps.as_ref()), vec![ClaimableInfo {
+            owner: String::from("recipient"),
+            amounts: TokenAmounts {
+                native: coins(100, "tokens"),
+                ..TokenAmounts::default()
+            },
+        }]);
+
+        let claim = ExecuteMsg::Claim { id: String::from("pull") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("source", &[]), claim.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::AlreadySettled { .. }));
+        let res = execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), claim.clone()).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        assert_eq!(res.events[0].ty, "escrow_payout_claimed");
+        assert!(claimable(deps.as_ref()).is_empty());
+        // nothing left
+        execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), claim).unwrap_err();
+    }
+
+    #[test]
+    fn unclaimed_payouts_are_swept_after_timeout() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("pull"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            pull_payouts: Some(true),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        let approve = ExecuteMsg::Approve { id: String::from("pull") };
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+
+        let sweep = ExecuteMsg::SweepUnclaimed {
+            id: String::from("pull"),
+            to: String::from("treasury"),
+        };
+        // no timeout configured
+        let err = execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), sweep.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToSweep {}));
+        let update = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            unclaimed_timeout: Some(1000),
+            ..UpdateConfigMsg::default()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), update).unwrap();
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(999);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), sweep.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToSweep {}));
+
+        env.block.time = env.block.time.plus_seconds(1);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), sweep.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let res = execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), sweep.clone()).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("treasury"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        assert_eq!(res.events[0].ty, "escrow_unclaimed_swept");
+        assert!(res.events[0].attributes.contains(&attr("owner", "recipient")));
+        assert!(res.events[0].attributes.contains(&attr("since", mock_env().block.time.seconds().to_string())));
+        assert!(query_claimable(deps.as_ref(), String::from("pull")).unwrap().claimable.is_empty());
+
+        // the recipient has nothing left to claim
+        let claim = ExecuteMsg::Claim { id: String::from("pull") };
+        execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), claim).unwrap_err();
+        let err = execute(deps.as_mut(), env, mock_info("admin", &[]), sweep).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToSweep {}));
+    }
+
+    #[test]
+    fn rescue_only_moves_stray_funds() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        // 50 tokens were sent to the contract without an escrow
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(150, "tokens"));
+
+        let rescue = |amount: u128| ExecuteMsg::RescueFunds {
+            denom_or_token: String::from("tokens"),
+            amount: Uint128::new(amount),
+            to: String::from("source"),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("source", &[]), rescue(50)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), rescue(51)).unwrap_err();
+        match err {
+            ContractError::NotStray { available, .. } => assert_eq!(available, Uint128::new(50)),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        let res = execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), rescue(50)).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("source"),
+                amount: coins(50, "tokens"),
+            })
+        );
+
+        // cw20 tokens are rescued by contract address
+        deps.querier.update_wasm(|_| {
+            let res = BalanceResponse { balance: Uint128::new(30) };
+            SystemResult::Ok(ContractResult::Ok(to_binary(&res).unwrap()))
+        });
+        let rescue = ExecuteMsg::RescueFunds {
+            denom_or_token: String::from("stray-token"),
+            amount: Uint128::new(30),
+            to: String::from("source"),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), rescue).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("stray-token"),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: String::from("source"),
+                    amount: Uint128::new(30),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn rescue_leaves_arbiter_bonds() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let register = ExecuteMsg::RegisterArbiter {
+            fee_bps: 100,
+            categories: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(50, "bond")), register).unwrap();
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(50, "bond"));
+
+        let rescue = ExecuteMsg::RescueFunds {
+            denom_or_token: String::from("bond"),
+            amount: Uint128::new(50),
+            to: String::from("admin"),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), rescue).unwrap_err();
+        match err {
+            ContractError::NotStray { available, .. } => assert_eq!(available, Uint128::zero()),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn pull_refunds_wait_for_each_funder() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("pull"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            pull_refunds: Some(true),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        let top_up = ExecuteMsg::TopUp { id: String::from("pull") };
+        execute(deps.as_mut(), mock_env(), mock_info("friend", &coins(50, "tokens")), top_up).unwrap();
+
+        let refund = ExecuteMsg::Refund { id: String::from("pull") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), refund).unwrap();
+        assert!(res.messages.is_empty());
+        let claimable = query_claimable(deps.as_ref(), String::from("pull")).unwrap().claimable;
+        let owners: Vec<_> = claimable.iter().map(|c| (c.owner.as_str(), c.amounts.native.clone())).collect();
+        assert_eq!(owners, vec![("friend", coins(50, "tokens")), ("source", coins(100, "tokens"))]);
+
+        let claim = ExecuteMsg::ClaimRefund { id: String::from("pull") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), claim.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::AlreadySettled { .. }));
+        for (funder, amount) in [("friend", 50), ("source", 100)] {
+            let res = execute(deps.as_mut(), mock_env(), mock_info(funder, &[]), claim.clone()).unwrap();
+            assert_eq!(
+                res.messages[0].msg,
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: funder.to_string(),
+                    amount: coins(amount, "tokens"),
+                })
+            );
+        }
+        assert!(query_claimable(deps.as_ref(), String::from("pull")).unwrap().claimable.is_empty());
+    }
+
+    #[test]
+    fn payout_failure_names_token() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let create = ReceiveMsg::Create(CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        });
+        let receive = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("source"),
+            amount: Uint128::new(100),
+            msg: to_binary(&create).unwrap(),
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("my-token", &[]), receive).unwrap();
+        let top_up = ExecuteMsg::TopUp { id: String::from("foobar") };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(50, "atom")), top_up).unwrap();
+
+        let approve = ExecuteMsg::Approve { id: String::from("foobar") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        let ids: Vec<_> = res.messages.iter().map(|m| (m.id, m.reply_on.clone())).collect();
+        assert_eq!(
+            ids,
+            vec![(PAYOUT_REPLY_ID, ReplyOn::Error), (PAYOUT_REPLY_ID + 1, ReplyOn::Error)]
+        );
+
+        // the cw20 transfer fails
+        let failed = Reply {
+            id: PAYOUT_REPLY_ID + 1,
+            result: SubMsgResult::Err(String::from("transfer failed")),
+        };
+        let err = reply(deps.as_mut(), mock_env(), failed).unwrap_err();
+        assert!(matches!(err, ContractError::PayoutFailed { token } if token == "my-token"));
+    }
+
+    #[test]
+    fn top_up_restricted_to_allowed_funders() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            allowed_funders: Some(vec![String::from("backer")]),
+            cw20_whitelist: Some(vec![String::from("my-token")]),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+
+        let top_up = ExecuteMsg::TopUp { id: String::from("foobar") };
+        let info = mock_info("stranger", &coins(10, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info, top_up.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotAllowedFunder { sender } if sender == "stranger"));
+
+        // cw20 deposits are checked against the sender of the tokens
+        let receive = |sender: &str| ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from(sender),
+            amount: Uint128::new(10),
+            msg: to_binary(&ReceiveMsg::TopUp { id: String::from("foobar") }).unwrap(),
+        });
+        let err = execute(deps.as_mut(), mock_env(), mock_info("my-token", &[]), receive("stranger")).unwrap_err();
+        assert!(matches!(err, ContractError::NotAllowedFunder { .. }));
+        execute(deps.as_mut(), mock_env(), mock_info("my-token", &[]), receive("backer")).unwrap();
+
+        // the source and the listed funders may add more
+        execute(deps.as_mut(), mock_env(), mock_info("backer", &coins(10, "tokens")), top_up.clone()).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(10, "tokens")), top_up).unwrap();
+        let details = query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap();
+        assert_eq!(details.native_balance, coins(120, "tokens"));
+        assert_eq!(details.allowed_funders, Some(vec![String::from("backer")]));
+    }
+
+    #[test]
+    fn required_amount_alias() {
+        let msg: CreateMsg = cosmwasm_std::from_slice(
+            br#"{"id":"foobar","arbiter":"arbiter","recipient":"recipient","required_amount":{"native":[{"denom":"tokens","amount":"100"}],"cw20":[]}}"#,
+        )
+        .unwrap();
+        assert_eq!(msg.funding_target.unwrap().native, coins(100, "tokens"));
+    }
+
+    #[test]
+    fn refunds_split_between_funders() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("foobar"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            refund_address: Some(String::from("treasury")),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(msg)).unwrap();
+        let top_up = ExecuteMsg::TopUp { id: String::from("foobar") };
+        execute(deps.as_mut(), mock_env(), mock_info("backer", &coins(33, "tokens")), top_up.clone()).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(20, "atom")), top_up).unwrap();
+
+        let contributions = query_contributions(deps.as_ref(), String::from("foobar")).unwrap();
+        assert_eq!(
+            contributions.contributions,
+            vec![
+                ContributionInfo {
+                    funder: String::from("backer"),
+                    amounts: TokenAmounts {
+                        native: coins(33, "tokens"),
+                        ..TokenAmounts::default()
+                    },
+                },
+                ContributionInfo {
+                    funder: String::from("source"),
+                    amounts: TokenAmounts {
+                        native: vec![coin(100, "tokens"), coin(20, "atom")],
+                        ..TokenAmounts::default()
+                    },
+                },
+            ]
+        );
+
+        // 33 of 133 tokens are the backer's, the atom are all from the source
+        let refund = ExecuteMsg::RefundPartial {
+            id: String::from("foobar"),
+            amounts: TokenAmounts {
+                native: vec![coin(50, "tokens"), coin(10, "atom")],
+                ..TokenAmounts::default()
+            },
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), refund).unwrap();
+        let sends: Vec<_> = res.messages.iter().map(|m| m.msg.clone()).collect();
+        assert_eq!(
+            sends,
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("backer"),
+                    amount: coins(12, "tokens"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("treasury"),
+                    amount: vec![coin(38, "tokens"), coin(10, "atom")],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn crowdfund_campaign() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        let deadline = env.block.height + 100;
+        let campaign = |id: &str| CreateMsg {
+            id: id.to_string(),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            crowdfund: Some(true),
+            funding_target: Some(TokenAmounts {
+                native: coins(100, "tokens"),
+                ..TokenAmounts::default()
+            }),
+            expires: Expiration::AtHeight(deadline),
+            ..CreateMsg::default()
+        };
+        // a goal and a deadline are needed
+        let msg = CreateMsg {
+            expires: Expiration::Never {},
+            ..campaign("nodeadline")
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), ExecuteMsg::Create(msg)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidCrowdfund {}));
+        for id in ["funded", "failed"] {
+            execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), ExecuteMsg::Create(campaign(id))).unwrap();
+        }
+
+        let top_up = |id: &str| ExecuteMsg::TopUp { id: id.to_string() };
+        for backer in ["alice", "bob"] {
+            execute(deps.as_mut(), env.clone(), mock_info(backer, &coins(60, "tokens")), top_up("funded")).unwrap();
+            execute(deps.as_mut(), env.clone(), mock_info(backer, &coins(30, "tokens")), top_up("failed")).unwrap();
+        }
+        assert_eq!(query_details(deps.as_ref(), mock_env(), String::from("funded")).unwrap().status, Status::Open);
+        assert_eq!(query_details(deps.as_ref(), mock_env(), String::from("failed")).unwrap().status, Status::Funding);
+
+        // nothing can be claimed back before the deadline
+        let claim = |id: &str| ExecuteMsg::ClaimRefund { id: id.to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), claim("failed")).unwrap_err();
+        assert!(matches!(err, ContractError::NotExpired {}));
+
+        env.block.height += 200;
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &coins(40, "tokens")), top_up("failed")).unwrap_err();
+        assert!(matches!(err, ContractError::Expired { .. }));
+
+        // the funded campaign goes to the recipient
+        let settle = |id: &str| ExecuteMsg::SettleExpired { id: id.to_string() };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), settle("funded")).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(120, "tokens"),
+            })
+        );
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), claim("funded")).unwrap_err();
+        assert!(matches!(err, ContractError::AlreadySettled { .. }));
+
+        // the failed one is claimed back by every backer
+        let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), settle("failed")).unwrap_err();
+        assert!(matches!(err, ContractError::CrowdfundFailed {}));
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), claim("failed")).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("alice"),
+                amount: coins(30, "tokens"),
+            })
+        );
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), claim("failed")).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToClaim {}));
+        execute(deps.as_mut(), env, mock_info("bob", &[]), claim("failed")).unwrap();
+        assert_eq!(query_details(deps.as_ref(), mock_env(), String::from("failed")).unwrap().status, Status::Refunded);
+    }
+
+    #[test]
+    fn hash_locked_release() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let preimage = "0a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f9";
+        let hash_lock = hex::encode(Sha256::digest(&hex::decode(preimage).unwrap()));
+        let create = |id: &str, hash_lock: &str| CreateMsg {
+            id: id.to_string(),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            hash_lock: Some(hash_lock.to_string()),
+            expires: Expiration::AtHeight(mock_env().block.height + 100),
+            ..CreateMsg::default()
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(create("bad", "xyz"))).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidHashLock {}));
+        execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::Create(create("swap", &hash_lock))).unwrap();
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(create("late", &hash_lock))).unwrap();
+
+        let claim = |id: &str, preimage: &str| ExecuteMsg::ClaimWithPreimage {
+            id: id.to_string(),
+            preimage: preimage.to_string(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("source", &[]), claim("swap", preimage)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), claim("swap", &hash_lock)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidPreimage {}));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), claim("swap", "not hex")).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidPreimage {}));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), claim("swap", preimage)).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        let revealed = res.events.iter().find(|e| e.ty == "escrow_fulfilled").unwrap();
+        assert!(revealed.attributes.iter().any(|a| a.key == "preimage" && a.value == preimage));
+        assert_eq!(query_details(deps.as_ref(), mock_env(), String::from("swap")).unwrap().status, Status::Approved);
+
+        // after expiry only the source gets the tokens back
+        let mut env = mock_env();
+        env.block.height += 200;
+        let err = execute(deps.as_mut(), env, mock_info("recipient", &[]), claim("late", preimage)).unwrap_err();
+        assert!(matches!(err, ContractError::Expired { .. }));
+    }
+
+    #[test]
+    fn release_on_conditions() {
+        use crate::conditions::{ApprovalQuery, ApprovalResponse};
+        use cosmwasm_std::testing::{MockApi, MockQuerier, MockStorage};
+        use cosmwasm_std::{OwnedDeps, SystemError, WasmQuery};
+
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        let msg = CreateMsg {
+            id: String::from("deal"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            conditions: Some(vec![
+                Condition::Height(env.block.height + 10),
+                Condition::OracleQuery {
+                    contract: String::from("oracle"),
+                    msg: to_binary("price").unwrap(),
+                    expected: to_binary("high").unwrap(),
+                },
+                Condition::ExternalContractApproval {
+                    contract: String::from("notary"),
+                },
+            ]),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+
+        let set_answers = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, price: &'static str, approved: bool| {
+            deps.querier.update_wasm(move |query| match query {
+                WasmQuery::Smart { contract_addr, .. } if contract_addr == "oracle" => {
+                    SystemResult::Ok(ContractResult::Ok(to_binary(price).unwrap()))
+                }
+                WasmQuery::Smart { msg, .. } => {
+                    let ApprovalQuery::Approved { escrow_id } = from_binary(msg).unwrap();
+                    assert_eq!(escrow_id, "deal");
+                    SystemResult::Ok(ContractResult::Ok(to_binary(&ApprovalResponse { approved }).unwrap()))
+                }
+                _ => SystemResult::Err(SystemError::NoSuchContract {
+                    addr: String::from("unknown"),
+                }),
+            });
+        };
+        set_answers(&mut deps, "low", false);
+
+        let fulfill = ExecuteMsg::Fulfill { id: String::from("deal"), preimage: None };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("source", &[]), fulfill.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), fulfill.clone()).unwrap_err();
+        assert_eq!(err.to_string(), format!("Condition not met: height {}", env.block.height + 10));
+
+        env.block.height += 10;
+        let err = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), fulfill.clone()).unwrap_err();
+        assert_eq!(err.to_string(), "Condition not met: oracle query to oracle");
+        set_answers(&mut deps, "high", false);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), fulfill.clone()).unwrap_err();
+        assert_eq!(err.to_string(), "Condition not met: approval by notary");
+
+        set_answers(&mut deps, "high", true);
+        let res = execute(deps.as_mut(), env, mock_info("recipient", &[]), fulfill).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        assert_eq!(query_details(deps.as_ref(), mock_env(), String::from("deal")).unwrap().status, Status::Approved);
+    }
+
+    #[test]
+    fn dispute_blocks_automatic_settlement() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        let msg = CreateMsg {
+            id: String::from("deal"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::AtHeight(env.block.height + 100),
+            on_expiry: Some(OnExpiry::ReleaseToRecipient),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+
+        let dispute = ExecuteMsg::RaiseDispute {
+            id: String::from("deal"),
+            reason: String::from("goods never arrived"),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), dispute.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        execute(deps.as_mut(), env.clone(), mock_info("source", &[]), dispute.clone()).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), dispute).unwrap_err();
+        assert!(matches!(err, ContractError::Disputed {}));
+
+        let details = query_details(deps.as_ref(), mock_env(), String::from("deal")).unwrap();
+        assert_eq!(details.status, Status::Disputed);
+        let recorded = details.dispute.unwrap();
+        assert_eq!(recorded.raised_by, Addr::unchecked("source"));
+        assert_eq!(recorded.reason, "goods never arrived");
+        assert_eq!(recorded.raised_at, env.block.time);
+        assert_eq!(recorded.resolved_at, None);
+
+        // the deadline passing does not release it
+        env.block.height += 200;
+        env.block.time = env.block.time.plus_seconds(1000);
+        let settle = ExecuteMsg::SettleExpired { id: String::from("deal") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), settle).unwrap_err();
+        assert!(matches!(err, ContractError::Disputed {}));
+        let approve = ExecuteMsg::MutualApprove { id: String::from("deal") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), approve).unwrap_err();
+        assert!(matches!(err, ContractError::Disputed {}));
+
+        let resolve = ExecuteMsg::Resolve { id: String::from("deal"), recipient_bps: 5000 };
+        execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), resolve).unwrap();
+        let details = query_details(deps.as_ref(), mock_env(), String::from("deal")).unwrap();
+        assert_eq!(details.status, Status::Resolved);
+        assert_eq!(details.dispute.unwrap().resolved_at, Some(env.block.time));
+    }
+
+    #[test]
+    fn appeal_to_appellate_arbiter() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            admin: Some(String::from("admin")),
+            token_allowlist: None,
+            max_tokens: None,
+            fee_bps: None,
+            fee_collector: None,
+            appellate_arbiter: Some(String::from("dao")),
+            appeal_window: Some(100),
+            creation_fee: None,
+            strict_parties: None,
+            receipt_nft: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let mut env = mock_env();
+        for id in ["final", "appealed"] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), env.clone(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+            let dispute = ExecuteMsg::RaiseDispute { id: id.to_string(), reason: String::from("late") };
+            execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), dispute).unwrap();
+            let refund = ExecuteMsg::Refund { id: id.to_string() };
+            let res = execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), refund).unwrap();
+            // the tokens stay until the appeal window closed
+            assert!(res.messages.is_empty());
+        }
+        let details = query_details(deps.as_ref(), mock_env(), String::from("final")).unwrap();
+        assert_eq!(details.status, Status::Decided);
+        assert_eq!(details.decision.unwrap().appeal_until, env.block.time.plus_seconds(100));
+
+        let finalize = |id: &str| ExecuteMsg::FinalizeDecision { id: id.to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), finalize("final")).unwrap_err();
+        assert!(matches!(err, ContractError::AppealPending {}));
+        let approve = ExecuteMsg::Approve { id: String::from("final") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), approve).unwrap_err();
+        assert!(matches!(err, ContractError::AppealPending {}));
+
+        // only the losing party can appeal
+        let appeal = ExecuteMsg::Appeal { id: String::from("appealed") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("source", &[]), appeal.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), appeal).unwrap();
+
+        env.block.time = env.block.time.plus_seconds(101);
+        let appeal = ExecuteMsg::Appeal { id: String::from("final") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("recipient", &[]), appeal).unwrap_err();
+        assert!(matches!(err, ContractError::AppealWindowClosed {}));
+        let res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), finalize("final")).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("source"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        assert_eq!(query_details(deps.as_ref(), mock_env(), String::from("final")).unwrap().status, Status::Refunded);
+
+        // the appellate arbiter overturns the decision
+        let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), finalize("appealed")).unwrap_err();
+        assert!(matches!(err, ContractError::AppealPending {}));
+        let decide = ExecuteMsg::DecideAppeal { id: String::from("appealed"), recipient_bps: 10000 };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), decide.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let res = execute(deps.as_mut(), env, mock_info("dao", &[]), decide).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        assert_eq!(query_details(deps.as_ref(), mock_env(), String::from("appealed")).unwrap().status, Status::Approved);
+    }
+
+    #[test]
+    fn partial_payouts_wait_for_appeal() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            admin: Some(String::from("admin")),
+            token_allowlist: None,
+            max_tokens: None,
+            fee_bps: None,
+            fee_collector: None,
+            appellate_arbiter: Some(String::from("dao")),
+            appeal_window: Some(100),
+            creation_fee: None,
+            strict_parties: None,
+            receipt_nft: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        for id in ["decided", "appealed"] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+            let dispute = ExecuteMsg::RaiseDispute { id: id.to_string(), reason: String::from("late") };
+            execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), dispute).unwrap();
+            execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), ExecuteMsg::Refund { id: id.to_string() }).unwrap();
+        }
+        let appeal = ExecuteMsg::Appeal { id: String::from("appealed") };
+        execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), appeal).unwrap();
+
+        // the decision cannot be bypassed by paying out the tokens one by one
+        for id in ["decided", "appealed"] {
+            let tokens = vec![String::from("tokens")];
+            let amounts = TokenAmounts {
+                native: coins(100, "tokens"),
+                ..TokenAmounts::default()
+            };
+            let msgs = [
+                ExecuteMsg::ApprovePartial { id: id.to_string(), amounts: amounts.clone() },
+                ExecuteMsg::ApproveTokens { id: id.to_string(), tokens: tokens.clone() },
+                ExecuteMsg::RefundPartial { id: id.to_string(), amounts },
+                ExecuteMsg::RefundTokens { id: id.to_string(), tokens },
+            ];
+            for msg in msgs {
+                let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), msg).unwrap_err();
+                assert!(matches!(err, ContractError::AppealPending {}));
+            }
+        }
+        let details = query_details(deps.as_ref(), mock_env(), String::from("appealed")).unwrap();
+        assert_eq!(details.status, Status::Appealed);
+        assert_eq!(details.native_balance, coins(100, "tokens"));
+    }
+
+    #[test]
+    fn create_from_template() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let template = TemplateMsg {
+            arbiter: "arbiter".into(),
+            arbiter_fee_bps: Some(100),
+            duration: Some(Duration::Time(3600)),
+            tokens: Some(vec![String::from("tokens")]),
+            ..TemplateMsg::default()
+        };
+        let register = |template: TemplateMsg| ExecuteMsg::RegisterTemplate {
+            id: String::from("market-deal"),
+            template,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("market", &[]), register(template.clone())).unwrap();
+        // only the owner can replace it
+        let hijack = TemplateMsg { arbiter: "thief".into(), ..template };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("thief", &[]), register(hijack)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+
+        let create = |id: &str| {
+            ExecuteMsg::CreateFromTemplate(CreateFromTemplateMsg {
+                template_id: String::from("market-deal"),
+                id: id.to_string(),
+                recipient: String::from("seller"),
+                overrides: Some(TemplateOverrides {
+                    title: Some(String::from("Used bike")),
+                    ..TemplateOverrides::default()
+                }),
+            })
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100, "other")), create("deal")).unwrap_err();
+        assert!(matches!(err, ContractError::TokenNotInTemplate { token, .. } if token == "other"));
+        execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100, "tokens")), create("deal")).unwrap();
+
+        let details = query_details(deps.as_ref(), mock_env(), String::from("deal")).unwrap();
+        assert_eq!(details.arbiters, vec![String::from("arbiter")]);
+        assert_eq!(details.arbiter_fee_bps, 100);
+        assert_eq!(details.expires, Expiration::AtTime(mock_env().block.time.plus_seconds(3600)));
+        assert_eq!(details.source, "buyer");
+        assert_eq!(details.title, Some(String::from("Used bike")));
+
+        let templates = query_list_templates(deps.as_ref(), Some(String::from("market")), None, None).unwrap();
+        assert_eq!(templates.templates.len(), 1);
+        assert_eq!(templates.templates[0].owner, "market");
+
+        // the admin can take down any template, the escrows stay
+        let remove = ExecuteMsg::RemoveTemplate { id: String::from("market-deal") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("thief", &[]), remove.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), remove).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100, "tokens")), create("deal2")).unwrap_err();
+        assert!(matches!(err, ContractError::TemplateNotFound { .. }));
+        query_details(deps.as_ref(), mock_env(), String::from("deal")).unwrap();
+    }
+
+    #[test]
+    fn arbiter_registry() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let register = |fee_bps: u16, categories: &[&str]| ExecuteMsg::RegisterArbiter {
+            fee_bps,
+            categories: categories.iter().map(|c| c.to_string()).collect(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(50, "bond")), register(100, &["nft", "freelance"])).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), register(200, &["freelance"])).unwrap();
+        // registering again updates the profile and adds to the bond
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(25, "bond")), register(150, &["nft"])).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("carol", &[]), register(10001, &[])).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidBps {}));
+
+        let alice = query_arbiter(deps.as_ref(), String::from("alice")).unwrap();
+        assert_eq!(
+            alice,
+            ArbiterInfo {
+                address: String::from("alice"),
+                fee_bps: 150,
+                categories: vec![String::from("nft")],
+                bond: coins(75, "bond"),
+            }
+        );
+        let listed = |deps: Deps, category: Option<&str>| -> Vec<String> {
+            query_list_arbiters(deps, category.map(String::from), None, None)
+                .unwrap()
+                .arbiters
+                .into_iter()
+                .map(|arbiter| arbiter.address)
+                .collect()
+        };
+        assert_eq!(listed(deps.as_ref(), None), vec!["alice", "bob"]);
+        assert_eq!(listed(deps.as_ref(), Some("freelance")), vec!["bob"]);
+
+        // the bond stays while they handle an escrow
+        let msg = CreateMsg {
+            id: String::from("deal"),
+            arbiter: "alice".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), ExecuteMsg::UnregisterArbiter {}).unwrap_err();
+        assert!(matches!(err, ContractError::ArbiterBusy {}));
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), ExecuteMsg::Approve { id: String::from("deal") }).unwrap();
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), ExecuteMsg::UnregisterArbiter {}).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("alice"),
+                amount: coins(75, "bond"),
+            })
+        );
+        assert_eq!(listed(deps.as_ref(), None), vec!["bob"]);
+        let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), ExecuteMsg::UnregisterArbiter {}).unwrap_err();
+        assert!(matches!(err, ContractError::ArbiterNotRegistered { .. }));
+    }
+
+    #[test]
+    fn arbiter_stats() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            admin: Some(String::from("admin")),
+            token_allowlist: None,
+            max_tokens: None,
+            fee_bps: None,
+            fee_collector: None,
+            appellate_arbiter: Some(String::from("dao")),
+            appeal_window: Some(100),
+            creation_fee: None,
+            strict_parties: None,
+            receipt_nft: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let mut env = mock_env();
+        for id in ["plain", "disputed"] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), env.clone(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        }
+        execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), ExecuteMsg::Approve { id: String::from("plain") }).unwrap();
+        let dispute = ExecuteMsg::RaiseDispute { id: String::from("disputed"), reason: String::from("late") };
+        execute(deps.as_mut(), env.clone(), mock_info("source", &[]), dispute).unwrap();
+
+        env.block.time = env.block.time.plus_seconds(60);
+        let resolve = ExecuteMsg::Resolve { id: String::from("disputed"), recipient_bps: 7000 };
+        execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), resolve).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("source", &[]), ExecuteMsg::Appeal { id: String::from("disputed") }).unwrap();
+        let decide = ExecuteMsg::DecideAppeal { id: String::from("disputed"), recipient_bps: 5000 };
+        execute(deps.as_mut(), env, mock_info("dao", &[]), decide).unwrap();
+
+        let stats = query_arbiter_stats(deps.as_ref(), String::from("arbiter")).unwrap();
+        assert_eq!(
+            stats,
+            ArbiterStatsResponse {
+                arbiter: String::from("arbiter"),
+                escrows_handled: 2,
+                disputes_resolved: 1,
+                decisions_overturned: 1,
+                average_resolution_time: Some(60),
+            }
+        );
+        let stats = query_arbiter_stats(deps.as_ref(), String::from("nobody")).unwrap();
+        assert_eq!(stats.escrows_handled, 0);
+        assert_eq!(stats.average_resolution_time, None);
+    }
+
+    #[test]
+    fn pause_blocks_deposits_not_refunds() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let create = |id: &str| {
+            ExecuteMsg::Create(CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                ..CreateMsg::default()
+            })
+        };
+        for id in ["one", "two"] {
+            execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), create(id)).unwrap();
+        }
+
+        let pause = ExecuteMsg::Pause { block_releases: Some(true) };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), pause.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), pause).unwrap();
+
+        let err = execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), create("three")).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+        let top_up = ExecuteMsg::TopUp { id: String::from("one") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("source", &coins(10, "tokens")), top_up).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+        let approve = ExecuteMsg::Approve { id: String::from("one") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), ExecuteMsg::Refund { id: String::from("two") }).unwrap();
+
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), ExecuteMsg::Unpause {}).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), create("three")).unwrap();
+    }
+
+    #[test]
+    fn two_step_ownership_transfer() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let update = |action: OwnershipAction| ExecuteMsg::UpdateOwnership(action);
+        let transfer = |new_owner: &str, expiry: Option<Expiration>| {
+            update(OwnershipAction::TransferOwnership {
+                new_owner: new_owner.to_string(),
+                expiry,
+            })
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("mallory", &[]), transfer("mallory", None)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+
+        let expiry = Expiration::AtHeight(mock_env().block.height + 10);
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), transfer("dao", Some(expiry))).unwrap();
+        // the old owner stays in charge until the transfer is accepted
+        let pause = ExecuteMsg::Pause { block_releases: None };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("dao", &[]), pause.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("mallory", &[]), update(OwnershipAction::AcceptOwnership)).unwrap_err();
+        assert!(matches!(err, ContractError::NotPendingOwner {}));
+        let mut env = mock_env();
+        env.block.height += 11;
+        let err = execute(deps.as_mut(), env, mock_info("dao", &[]), update(OwnershipAction::AcceptOwnership)).unwrap_err();
+        assert!(matches!(err, ContractError::TransferExpired {}));
+
+        execute(deps.as_mut(), mock_env(), mock_info("dao", &[]), update(OwnershipAction::AcceptOwnership)).unwrap();
+        let ownership: Ownership = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Ownership {}).unwrap()).unwrap();
+        assert_eq!(
+            ownership,
+            Ownership {
+                owner: Some(Addr::unchecked("dao")),
+                pending_owner: None,
+                pending_expiry: None,
+            }
+        );
+        execute(deps.as_mut(), mock_env(), mock_info("dao", &[]), pause.clone()).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), ExecuteMsg::Unpause {}).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+
+        execute(deps.as_mut(), mock_env(), mock_info("dao", &[]), update(OwnershipAction::RenounceOwnership)).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("dao", &[]), ExecuteMsg::Unpause {}).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+    }
+
+    #[test]
+    fn migrate_moves_admin_to_ownership() {
+        let mut deps = mock_dependencies();
+        let legacy = br#"{"admin":"admin","max_tokens":10,"fee_bps":0,"fee_collector":"admin"}"#;
+        deps.storage.set(b"config", legacy);
+        set_contract_version(&mut deps.storage, CONTRACT_NAME, "0.9.0").unwrap();
+
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+        assert_eq!(res.attributes[5], attr("ownership_migrated", "true"));
+        assert_eq!(OWNERSHIP.load(&deps.storage).unwrap().owner, Some(Addr::unchecked("admin")));
+        assert_eq!(query_config(deps.as_ref()).unwrap().max_tokens, 10);
+
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+        assert_eq!(res.attributes[5], attr("ownership_migrated", "false"));
+    }
+
+    #[test]
+    fn sudo_operations() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let msg = CreateMsg {
+            id: String::from("deal"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        let dispute = ExecuteMsg::RaiseDispute { id: String::from("deal"), reason: String::from("fraud") };
+        execute(deps.as_mut(), mock_env(), mock_info("recipient", &[]), dispute).unwrap();
+
+        // governance can refund even a disputed escrow
+        let res = sudo(deps.as_mut(), mock_env(), SudoMsg::ForceRefund { id: String::from("deal") }).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("source"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        assert_eq!(query_details(deps.as_ref(), mock_env(), String::from("deal")).unwrap().status, Status::Refunded);
+        let err = sudo(deps.as_mut(), mock_env(), SudoMsg::ForceRefund { id: String::from("deal") }).unwrap_err();
+        assert!(matches!(err, ContractError::AlreadySettled { .. }));
+
+        let update = UpdateConfigMsg {
+            max_tokens: Some(3),
+            ..UpdateConfigMsg::default()
+        };
+        sudo(deps.as_mut(), mock_env(), SudoMsg::UpdateConfig(update)).unwrap();
+        sudo(deps.as_mut(), mock_env(), SudoMsg::Pause { block_releases: None }).unwrap();
+        let config = query_config(deps.as_ref()).unwrap();
+        assert_eq!(config.max_tokens, 3);
+        assert!(config.paused);
+        sudo(deps.as_mut(), mock_env(), SudoMsg::Unpause {}).unwrap();
+        assert!(!query_config(deps.as_ref()).unwrap().paused);
+    }
+
+    #[test]
+    fn sudo_processes_expirations_in_batches() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        let deadlines = [
+            ("a", Expiration::AtHeight(env.block.height + 10)),
+            ("b", Expiration::Never {}),
+            ("c", Expiration::AtHeight(env.block.height + 5)),
+        ];
+        for (id, expires) in deadlines {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                expires,
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), env.clone(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        }
+
+        let process = SudoMsg::ProcessExpirations { limit: 1 };
+        let res = sudo(deps.as_mut(), env.clone(), process.clone()).unwrap();
+        assert_eq!(res.attributes[1], attr("settled", "0"));
+
+        env.block.height += 20;
+        // c expired first
+        let res = sudo(deps.as_mut(), env.clone(), process.clone()).unwrap();
+        assert_eq!(res.attributes[1], attr("settled", "1"));
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("source"),
+                amount: coins(100, "tokens"),
+            })
+        );
+        assert_eq!(query_details(deps.as_ref(), mock_env(), "c".to_string()).unwrap().status, Status::Expired);
+        assert_eq!(query_details(deps.as_ref(), mock_env(), "a".to_string()).unwrap().status, Status::Open);
+        // then a, b has no deadline
+        let res = sudo(deps.as_mut(), env.clone(), process.clone()).unwrap();
+        assert_eq!(res.attributes[1], attr("settled", "1"));
+        let res = sudo(deps.as_mut(), env, process).unwrap();
+        assert_eq!(res.attributes[1], attr("settled", "0"));
+
+        for (id, status) in [("a", Status::Expired), ("b", Status::Open), ("c", Status::Expired)] {
+            assert_eq!(query_details(deps.as_ref(), mock_env(), id.to_string()).unwrap().status, status);
+        }
+    }
+
+    #[test]
+    fn expiry_batches_scan_a_bounded_number_of_escrows() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        // releases cannot go out while paused, these escrows stay where they are
+        for i in 0..MAX_EXPIRY_SCAN {
+            let msg = CreateMsg {
+                id: format!("release-{}", i),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                expires: Expiration::AtHeight(env.block.height + 5),
+                on_expiry: Some(OnExpiry::ReleaseToRecipient),
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), env.clone(), mock_info("source", &coins(1, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        }
+        let msg = CreateMsg {
+            id: String::from("refund"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::AtHeight(env.block.height + 10),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("source", &coins(1, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        let pause = ExecuteMsg::Pause { block_releases: Some(true) };
+        execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), pause).unwrap();
+        env.block.height += 20;
+
+        let err = sudo(deps.as_mut(), env.clone(), SudoMsg::ProcessExpirations { limit: 0 }).unwrap_err();
+        assert!(matches!(err, ContractError::ZeroLimit {}));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bot", &[]), ExecuteMsg::Crank { limit: 0 }).unwrap_err();
+        assert!(matches!(err, ContractError::ZeroLimit {}));
+
+        // the first call stops after looking at as many escrows as allowed
+        let process = SudoMsg::ProcessExpirations { limit: u32::MAX };
+        let res = sudo(deps.as_mut(), env.clone(), process.clone()).unwrap();
+        assert_eq!(res.attributes[1], attr("settled", "0"));
+        assert!(EXPIRY_CURSOR.may_load(&deps.storage).unwrap().is_some());
+        // the next one continues there, then starts over
+        let res = sudo(deps.as_mut(), env.clone(), process.clone()).unwrap();
+        assert_eq!(res.attributes[1], attr("settled", "1"));
+        assert_eq!(query_details(deps.as_ref(), mock_env(), String::from("refund")).unwrap().status, Status::Expired);
+        assert!(EXPIRY_CURSOR.may_load(&deps.storage).unwrap().is_none());
+
+        // at most MAX_SETTLE_LIMIT are settled at once
+        execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), ExecuteMsg::Unpause {}).unwrap();
+        let res = sudo(deps.as_mut(), env.clone(), process.clone()).unwrap();
+        assert_eq!(res.attributes[1], attr("settled", MAX_SETTLE_LIMIT.to_string()));
+    }
+
+    #[test]
+    fn expiry_index_scans_by_deadline() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let env = mock_env();
+        let height = env.block.height;
+        let time = env.block.time;
+        let deadlines = [
+            ("a", Expiration::AtHeight(height + 10)),
+            ("b", Expiration::AtTime(time.plus_seconds(10))),
+            ("c", Expiration::AtHeight(height + 5)),
+            ("d", Expiration::Never {}),
+        ];
+        for (id, expires) in deadlines {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                expires,
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), env.clone(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        }
+
+        let expired = |storage: &dyn Storage, height: u64, time: Timestamp| -> Vec<String> {
+            let block = BlockInfo {
+                height,
+                time,
+                ..mock_env().block
+            };
+            expired_escrow_ids(storage, &block, None).collect::<StdResult<_>>().unwrap()
+        };
+        assert!(expired(&deps.storage, height, time).is_empty());
+        assert_eq!(expired(&deps.storage, height + 5, time), vec!["c"]);
+        assert_eq!(expired(&deps.storage, height + 10, time.plus_seconds(10)), vec!["c", "a", "b"]);
+        assert_eq!(expired(&deps.storage, height, time.plus_seconds(10)), vec!["b"]);
+        assert!(expired(&deps.storage, height + 4, time.plus_nanos(9_999_999_999)).is_empty());
+
+        // settled escrows leave the index
+        execute(deps.as_mut(), env, mock_info("arbiter", &[]), ExecuteMsg::Refund { id: "c".to_string() }).unwrap();
+        assert_eq!(expired(&deps.storage, height + 10, time.plus_seconds(10)), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn list_expired_escrows() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        let deadlines = [
+            ("a", Expiration::AtHeight(env.block.height + 10)),
+            ("b", Expiration::Never {}),
+            ("c", Expiration::AtHeight(env.block.height + 5)),
+            ("d", Expiration::AtHeight(env.block.height + 1)),
+        ];
+        for (id, expires) in deadlines {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                expires,
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), env.clone(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        }
+        execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), ExecuteMsg::Refund { id: "d".to_string() }).unwrap();
+
+        let list = |deps: Deps, env: Env, as_of_height: Option<u64>, start_after: Option<&str>, limit: Option<u32>| -> Vec<String> {
+            let msg = QueryMsg::ListExpired {
+                as_of_height,
+                as_of_time: None,
+                start_after: start_after.map(String::from),
+                limit,
+            };
+            from_binary::<ListResponse>(&query(deps, env, msg).unwrap()).unwrap().escrows
+        };
+        assert!(list(deps.as_ref(), env.clone(), None, None, None).is_empty());
+        assert_eq!(list(deps.as_ref(), env.clone(), Some(env.block.height + 20), None, None), vec!["c", "a"]);
+
+        env.block.height += 20;
+        assert_eq!(list(deps.as_ref(), env.clone(), None, None, Some(1)), vec!["c"]);
+        assert_eq!(list(deps.as_ref(), env.clone(), None, Some("c"), Some(1)), vec!["a"]);
+        assert!(list(deps.as_ref(), env.clone(), None, Some("a"), None).is_empty());
+
+        // settled ones drop out
+        execute(deps.as_mut(), env.clone(), mock_info("bot", &[]), ExecuteMsg::Crank { limit: 1 }).unwrap();
+        assert_eq!(list(deps.as_ref(), env.clone(), None, None, None), vec!["a"]);
+        // paging goes on after an escrow that settled in between
+        assert_eq!(list(deps.as_ref(), env, None, Some("c"), None), vec!["a"]);
+    }
+
+    #[test]
+    fn is_expired_query() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        let at_height = Expiration::AtHeight(env.block.height + 10);
+        let at_time = Expiration::AtTime(env.block.time.plus_seconds(10));
+        for (id, expires) in [("height", at_height), ("time", at_time), ("none", Expiration::Never {})] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                expires,
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), env.clone(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        }
+
+        let is_expired = |deps: Deps, env: Env, id: &str| -> IsExpiredResponse {
+            let msg = QueryMsg::IsExpired { id: id.to_string() };
+            from_binary(&query(deps, env, msg).unwrap()).unwrap()
+        };
+        let res = is_expired(deps.as_ref(), env.clone(), "height");
+        assert!(!res.expired);
+        assert_eq!(res.expires_at, at_height);
+        assert_eq!(is_expired(deps.as_ref(), env.clone(), "time").expires_at, at_time);
+        assert_eq!(is_expired(deps.as_ref(), env.clone(), "none").expires_at, Expiration::Never {});
+
+        env.block.height += 10;
+        assert!(is_expired(deps.as_ref(), env.clone(), "height").expired);
+        assert!(!is_expired(deps.as_ref(), env.clone(), "time").expired);
+        assert!(!is_expired(deps.as_ref(), env.clone(), "none").expired);
+
+        // in agreement with `Expiration` down to the nanosecond
+        env.block.time = env.block.time.plus_nanos(9_999_999_999);
+        assert!(!is_expired(deps.as_ref(), env.clone(), "time").expired);
+        env.block.time = env.block.time.plus_nanos(1);
+        assert!(is_expired(deps.as_ref(), env.clone(), "time").expired);
+        assert!(at_time.is_expired(&env.block));
+    }
+
+    #[test]
+    fn stats_track_locked_value_and_counts() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        for id in ["a", "b", "c"] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                cw20_whitelist: Some(vec![String::from("my-token")]),
+                ..CreateMsg::default()
+            };
+            let funds = vec![coin(100, "tokens"), coin(10, "atom")];
+            execute(deps.as_mut(), mock_env(), mock_info("source", &funds), ExecuteMsg::Create(msg)).unwrap();
+        }
+        let receive = Cw20ReceiveMsg {
+            sender: String::from("source"),
+            amount: Uint128::new(50),
+            msg: to_binary(&ReceiveMsg::TopUp { id: String::from("a") }).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("my-token", &[]), ExecuteMsg::Receive(receive)).unwrap();
+        let top_up = ExecuteMsg::TopUp { id: String::from("b") };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(5, "atom")), top_up).unwrap();
+
+        let stats = query_stats(deps.as_ref()).unwrap();
+        assert_eq!(stats.total_locked.native, vec![coin(35, "atom"), coin(300, "tokens")]);
+        assert_eq!(
+            stats.total_locked.cw20,
+            vec![Cw20Coin {
+                address: String::from("my-token"),
+                amount: Uint128::new(50),
+            }]
+        );
+        assert_eq!(stats.counts, EscrowCounts { active: 3, ..EscrowCounts::default() });
 
-    if let Balance::Cw20(token) = &balance {
-        // ensure the token is on the whitelist
-        if !escrow.cw20_whitelist.iter().any(|t| t == &token.address.to_string()) {
-            return Err(ContractError::UnregisteredTokens{});
+        let approve = ExecuteMsg::Approve { id: String::from("a") };
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+        let refund = ExecuteMsg::Refund { id: String::from("b") };
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), refund).unwrap();
+
+        let stats = query_stats(deps.as_ref()).unwrap();
+        assert_eq!(stats.total_locked.native, vec![coin(10, "atom"), coin(100, "tokens")]);
+        assert!(stats.total_locked.cw20.is_empty());
+        let counts = EscrowCounts {
+            active: 1,
+            approved: 1,
+            refunded: 1,
+            ..EscrowCounts::default()
+        };
+        assert_eq!(stats.counts, counts);
+
+        let page = query_list_by_token(deps.as_ref(), String::from("atom"), None, None).unwrap();
+        assert_eq!(page.escrows, vec!["c"]);
+    }
+
+    #[test]
+    fn filter_escrows_by_status_and_arbiter() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        for (id, arbiter) in [("a", "alice"), ("b", "alice"), ("c", "bob"), ("d", "alice")] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: arbiter.into(),
+                recipient: String::from("recipient"),
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
         }
-    };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), ExecuteMsg::Approve { id: String::from("a") }).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), ExecuteMsg::Approve { id: String::from("c") }).unwrap();
+        let dispute = ExecuteMsg::RaiseDispute {
+            id: String::from("b"),
+            reason: String::from("late"),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &[]), dispute).unwrap();
 
-    escrow.balance.add_tokens(balance);
+        let count = |deps: Deps, status: Option<Status>, arbiter: Option<&str>| -> u64 {
+            let msg = QueryMsg::Count {
+                status,
+                arbiter: arbiter.map(String::from),
+            };
+            from_binary::<CountResponse>(&query(deps, mock_env(), msg).unwrap()).unwrap().count
+        };
+        assert_eq!(count(deps.as_ref(), None, None), 2);
+        assert_eq!(count(deps.as_ref(), None, Some("alice")), 2);
+        assert_eq!(count(deps.as_ref(), None, Some("bob")), 0);
+        assert_eq!(count(deps.as_ref(), Some(Status::Open), None), 1);
+        assert_eq!(count(deps.as_ref(), Some(Status::Disputed), Some("alice")), 1);
+        assert_eq!(count(deps.as_ref(), Some(Status::Approved), None), 2);
+        assert_eq!(count(deps.as_ref(), Some(Status::Approved), Some("bob")), 1);
+        assert_eq!(count(deps.as_ref(), Some(Status::Refunded), Some("bob")), 0);
 
-    escrows_save(deps.storage, &escrow, &id)?;
-    Ok(Response::new().add_attribute("action", "top_up"))
-}
+        // List pages through the same escrows
+        let list = |deps: Deps, status: Option<Status>, arbiter: Option<&str>, start_after: Option<&str>| -> Vec<String> {
+            let msg = QueryMsg::List {
+                start_after: start_after.map(String::from),
+                limit: None,
+                status,
+                arbiter: arbiter.map(String::from),
+            };
+            from_binary::<ListResponse>(&query(deps, mock_env(), msg).unwrap()).unwrap().escrows
+        };
+        assert_eq!(list(deps.as_ref(), None, Some("alice"), None), vec!["b", "d"]);
+        assert_eq!(list(deps.as_ref(), Some(Status::Disputed), Some("alice"), None), vec!["b"]);
+        assert!(list(deps.as_ref(), Some(Status::Open), Some("bob"), None).is_empty());
+        assert_eq!(list(deps.as_ref(), Some(Status::Approved), Some("bob"), None), vec!["c"]);
+        assert_eq!(list(deps.as_ref(), Some(Status::Approved), None, Some("a")), vec!["c"]);
+        assert!(list(deps.as_ref(), Some(Status::Approved), Some("alice"), Some("a")).is_empty());
+    }
 
-fn query_details(
-    deps: Deps,
-    id: String,
-) -> StdResult<DetailsResponse> {
-    let escrow = escrows_read(deps.storage, &id)?;
+    #[test]
+    fn list_escrows_by_status_and_arbiter_in_pages() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
 
-    // transform tokens
-    let native_balance = escrow.balance.native;
+        for (id, arbiter) in [("a", "alice"), ("b", "alice"), ("c", "alice"), ("d", "bob"), ("e", "alice"), ("f", "alice")] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: arbiter.into(),
+                recipient: String::from("recipient"),
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        }
+        for (id, arbiter) in [("a", "alice"), ("c", "alice"), ("d", "bob"), ("e", "alice")] {
+            execute(deps.as_mut(), mock_env(), mock_info(arbiter, &[]), ExecuteMsg::Refund { id: id.to_string() }).unwrap();
+        }
 
-    let cw20_balance: StdResult<Vec<_>> = escrow
-        .balance
-        .cw20
-        .into_iter()
-        .map(|token| {
-            Ok(Cw20Coin {
-                address: token.address.to_string(),
-                amount: token.amount,
-            })
-        })
-        .collect();
+        let page = |status: Status, start_after: Option<&str>| -> Vec<String> {
+            query_list(deps.as_ref(), start_after.map(String::from), Some(2), Some(status), Some(String::from("alice")))
+                .unwrap()
+                .escrows
+        };
+        // settled ones
+        assert_eq!(page(Status::Refunded, None), vec!["a", "c"]);
+        assert_eq!(page(Status::Refunded, Some("c")), vec!["e"]);
+        assert!(page(Status::Refunded, Some("e")).is_empty());
+        // active ones
+        assert_eq!(page(Status::Open, None), vec!["b", "f"]);
+        assert!(page(Status::Open, Some("f")).is_empty());
+        assert!(page(Status::Approved, None).is_empty());
+    }
 
-    let details = DetailsResponse {
-        id,
-        arbiter:escrow.arbiter,
-        recipient: escrow.recipient,
-        source: escrow.source,
-        end_height: escrow.end_height,
-        end_time: escrow.end_time,
-        native_balance,
-        cw20_balance: cw20_balance?,
-        cw20_whitelist: escrow.cw20_whitelist,
-    };
-    Ok(details)
-}
+    #[test]
+    fn list_escrows_by_token() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
 
-// fn query_list(
-//     deps: Deps
-// ) ->  StdResult<ListResponse> {
-//     Ok( 
-//         ListResponse{
-//             escrows: all_escrow_ids(deps.storage).unwrap()
-//         },
-//     )
-// }
+        for (id, denom) in [("a", "atom"), ("b", "tokens"), ("c", "atom"), ("d", "atom")] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                cw20_whitelist: Some(vec![String::from("my-token")]),
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, denom)), ExecuteMsg::Create(msg)).unwrap();
+        }
+        let receive = Cw20ReceiveMsg {
+            sender: String::from("source"),
+            amount: Uint128::new(50),
+            msg: to_binary(&ReceiveMsg::TopUp { id: String::from("b") }).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("my-token", &[]), ExecuteMsg::Receive(receive)).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), ExecuteMsg::Refund { id: String::from("c") }).unwrap();
+
+        let list = |deps: Deps, token: &str, start_after: Option<&str>, limit: Option<u32>| -> Vec<String> {
+            let msg = QueryMsg::ListByToken {
+                denom_or_cw20: token.to_string(),
+                start_after: start_after.map(String::from),
+                limit,
+            };
+            from_binary::<ListResponse>(&query(deps, mock_env(), msg).unwrap()).unwrap().escrows
+        };
+        assert_eq!(list(deps.as_ref(), "atom", None, None), vec!["a", "d"]);
+        assert_eq!(list(deps.as_ref(), "atom", None, Some(1)), vec!["a"]);
+        assert_eq!(list(deps.as_ref(), "atom", Some("a"), None), vec!["d"]);
+        assert_eq!(list(deps.as_ref(), "tokens", None, None), vec!["b"]);
+        assert_eq!(list(deps.as_ref(), "my-token", None, None), vec!["b"]);
+        assert!(list(deps.as_ref(), "btc", None, None).is_empty());
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coins, CosmosMsg, Uint128};
-    
     #[test]
-    fn create_and_approve_escrow() {
-        let env = mock_env();
+    fn list_escrows_expiring_between() {
         let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
 
-        let id = "foobar".to_string();
-        let arbiter = "arbiter".to_string();
-        let recipient = "recipient".to_string();
-        let source = "sender".to_string();
+        let env = mock_env();
+        let height = env.block.height;
+        let time = env.block.time;
+        let deadlines = [
+            ("a", Expiration::AtHeight(height + 30)),
+            ("b", Expiration::AtTime(time.plus_seconds(10))),
+            ("c", Expiration::AtHeight(height + 10)),
+            ("d", Expiration::AtHeight(height + 20)),
+            ("e", Expiration::AtTime(time.plus_seconds(50))),
+            ("f", Expiration::Never {}),
+        ];
+        for (id, expires) in deadlines {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                expires,
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), env.clone(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        }
 
-        let msg = CreateMsg {
-            id: id.clone(),
-            arbiter: arbiter.clone().into(),
-            recipient: recipient.clone().into(),
-            end_time: None,
-            end_height: Some(123456),
-            cw20_whitelist: None,
+        let list = |deps: Deps, from: Expiration, to: Expiration, start_after: Option<&str>, limit: Option<u32>| {
+            query_list_expiring_between(deps, from, to, start_after.map(String::from), limit).map(|page| page.escrows)
         };
-        let balance = coins(100, "tokens");
-        let info = mock_info("sender", &balance);
-        let execute_res = execute(deps.as_mut(), env, info, ExecuteMsg::Create(msg)).unwrap();
-        
+        let at_height = |offset: u64| Expiration::AtHeight(height + offset);
+        let at_time = |offset: u64| Expiration::AtTime(time.plus_seconds(offset));
 
-        assert_eq!(0, execute_res.messages.len());
-        // ensure the details is what we expect
-        let details = query_details(deps.as_ref(), "foobar".to_string()).unwrap();
+        assert_eq!(list(deps.as_ref(), at_height(0), at_height(100), None, None).unwrap(), vec!["c", "d", "a"]);
+        assert_eq!(list(deps.as_ref(), at_height(10), at_height(30), None, None).unwrap(), vec!["c", "d"]);
+        assert_eq!(list(deps.as_ref(), at_height(11), at_height(31), None, None).unwrap(), vec!["d", "a"]);
+        assert_eq!(list(deps.as_ref(), at_height(0), at_height(100), None, Some(1)).unwrap(), vec!["c"]);
+        assert_eq!(list(deps.as_ref(), at_height(0), at_height(100), Some("c"), None).unwrap(), vec!["d", "a"]);
+        assert_eq!(list(deps.as_ref(), at_time(0), at_time(100), None, None).unwrap(), vec!["b", "e"]);
+        assert_eq!(list(deps.as_ref(), at_time(20), at_time(100), None, None).unwrap(), vec!["e"]);
+        // the cursor does not have to be in the window, or active
+        assert_eq!(list(deps.as_ref(), at_height(11), at_height(31), Some("c"), None).unwrap(), vec!["d", "a"]);
+        execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), ExecuteMsg::Refund { id: "d".to_string() }).unwrap();
+        assert_eq!(list(deps.as_ref(), at_height(0), at_height(100), Some("d"), None).unwrap(), vec!["a"]);
+
+        let err = list(deps.as_ref(), at_height(0), at_time(100), None, None).unwrap_err();
+        assert!(matches!(err, StdError::GenericErr { .. }));
+    }
+
+    #[test]
+    fn nothing_released_before_start() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        let start = Expiration::AtHeight(env.block.height + 10);
+        let create = |start, expires| {
+            ExecuteMsg::Create(CreateMsg {
+                id: String::from("foobar"),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                start: Some(start),
+                expires,
+                ..CreateMsg::default()
+            })
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), create(start, start)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidStart {}));
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), create(Expiration::Never {}, Expiration::Never {})).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidStart {}));
+        let res = execute(deps.as_mut(), env.clone(), info, create(start, Expiration::Never {})).unwrap();
+        assert_eq!(res.events[0].attributes.last().unwrap(), attr("start", start.to_string()));
+        assert_eq!(query_details(deps.as_ref(), mock_env(), String::from("foobar")).unwrap().start, Some(start));
+
+        let approve = ExecuteMsg::Approve { id: String::from("foobar") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), approve.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotStarted { start: s } if s == start));
+        let mutual = ExecuteMsg::MutualApprove { id: String::from("foobar") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("source", &[]), mutual).unwrap_err();
+        assert!(matches!(err, ContractError::NotStarted { .. }));
+        // funds are locked all the same, topping up is fine
+        let top_up = ExecuteMsg::TopUp { id: String::from("foobar") };
+        execute(deps.as_mut(), env.clone(), mock_info("source", &coins(50, "tokens")), top_up).unwrap();
+
+        env.block.height += 10;
+        let res = execute(deps.as_mut(), env, mock_info("arbiter", &[]), approve).unwrap();
         assert_eq!(
-            details,
-            DetailsResponse {
-                id: id.clone(),
-                arbiter: arbiter.clone().to_string(),
-                recipient: recipient.clone().to_string(),
-                source: source.clone().to_string(),
-                end_height: Some(123456),
-                end_time: None,
-                native_balance: balance.clone(), 
-                cw20_balance: vec![],
-                cw20_whitelist: vec![],
-            }
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("recipient"),
+                amount: coins(150, "tokens"),
+            })
         );
+    }
 
-        // beneficiary cannot release it
-        let env = mock_env();
-        let info = mock_info("beneficiary", &[]);
-        let approve_res = execute(deps.as_mut(), env, info, ExecuteMsg::Approve{id:id.clone()});
-        match approve_res.unwrap_err() {
-            ContractError::Unauthorized { .. } => {}
-            e => panic!("unexpected error: {:?}", e),
+    #[test]
+    fn only_arbiters_act_during_grace_period() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        let expires = Expiration::AtHeight(env.block.height + 10);
+        let create = |id: &str, grace_period| {
+            ExecuteMsg::Create(CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                expires,
+                grace_period: Some(grace_period),
+                ..CreateMsg::default()
+            })
+        };
+        let info = mock_info("source", &coins(100, "tokens"));
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), create("late", Duration::Time(60))).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidGracePeriod {}));
+        for id in ["late", "lapsed"] {
+            execute(deps.as_mut(), env.clone(), info.clone(), create(id, Duration::Height(5))).unwrap();
         }
+        let details = query_details(deps.as_ref(), mock_env(), String::from("late")).unwrap();
+        assert_eq!(details.grace_period, Some(Duration::Height(5)));
 
-        // approve it by arbiter
-        let env = mock_env();
-        let info = mock_info("arbiter", &[]);
-        let approve_res = execute(deps.as_mut(), env, info, ExecuteMsg::Approve{id:id.clone()}).unwrap();
-        assert_eq!(1, approve_res.messages.len());
+        // expired, but nobody but the arbiter can settle yet
+        env.block.height += 10;
+        let ends = Expiration::AtHeight(env.block.height + 5);
+        let refund = ExecuteMsg::Refund { id: String::from("lapsed") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), refund.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::GracePeriod { ends: e } if e == ends));
+        let settle = ExecuteMsg::SettleExpired { id: String::from("lapsed") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), settle).unwrap_err();
+        assert!(matches!(err, ContractError::GracePeriod { .. }));
+        let res = sudo(deps.as_mut(), env.clone(), SudoMsg::ProcessExpirations { limit: 10 }).unwrap();
+        assert_eq!(res.attributes[1], attr("settled", "0"));
+        let list = QueryMsg::ListExpired {
+            as_of_height: None,
+            as_of_time: None,
+            start_after: None,
+            limit: None,
+        };
+        let expired: ListResponse = from_binary(&query(deps.as_ref(), env.clone(), list.clone()).unwrap()).unwrap();
+        assert!(expired.escrows.is_empty());
+
+        // the arbiter still approves a late delivery
+        let approve = |id: &str| ExecuteMsg::Approve { id: id.to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), approve("late")).unwrap();
+
+        env.block.height += 5;
+        let expired: ListResponse = from_binary(&query(deps.as_ref(), env.clone(), list).unwrap()).unwrap();
+        assert_eq!(expired.escrows, vec!["lapsed"]);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), approve("lapsed")).unwrap_err();
+        assert!(matches!(err, ContractError::Expired { .. }));
+        let res = execute(deps.as_mut(), env, mock_info("anyone", &[]), refund).unwrap();
         assert_eq!(
-            approve_res.messages.get(0).expect("no message").msg, 
-            CosmosMsg::Bank(BankMsg::Send{
-                to_address: recipient.clone().into(),
-                amount: balance.clone(),
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("source"),
+                amount: coins(100, "tokens"),
             })
         );
     }
 
     #[test]
-    fn create_and_approve_escrow_with_cw20() {
-        let env = mock_env();
+    fn dead_mans_switch_releases_without_pings() {
         let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
 
-        let id = String::from("foobar");
-        let arbiter = String::from("arbiter");
-        let recipient = String::from("recipient");
-        let source = String::from("sender");
-        let token_contract_addr = String::from("my-token");
-        let info = mock_info(token_contract_addr.as_str(), &vec![]);
-
-        let crt_msg = CreateMsg {
-            id: id.clone(),
-            arbiter: arbiter.clone().into(),
-            recipient: recipient.clone().into(),
-            end_time: None,
-            end_height: Some(123456),
-            cw20_whitelist: Some(vec![String::from("other-token")]),
+        let mut env = mock_env();
+        let switch = CreateMsg {
+            id: String::from("switch"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("heir"),
+            ping_interval: Some(Duration::Height(100)),
+            ..CreateMsg::default()
         };
-        let rev_msg = Cw20ReceiveMsg {
-            sender: source.clone(),
-            amount: Uint128::from(100u128),
-            msg: to_binary(&ExecuteMsg::Create(crt_msg)).unwrap(),
+        let info = mock_info("source", &coins(100, "tokens"));
+        let invalid = [
+            CreateMsg {
+                expires: Expiration::AtHeight(env.block.height + 10),
+                ..switch.clone()
+            },
+            CreateMsg {
+                on_expiry: Some(OnExpiry::RefundToSource),
+                ..switch.clone()
+            },
+            CreateMsg {
+                ping_interval: Some(Duration::Time(0)),
+                ..switch.clone()
+            },
+        ];
+        for msg in invalid {
+            let err = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Create(msg)).unwrap_err();
+            assert!(matches!(err, ContractError::InvalidSwitch {}));
+        }
+        execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Create(switch)).unwrap();
+        let details = query_details(deps.as_ref(), mock_env(), String::from("switch")).unwrap();
+        assert_eq!(details.expires, Expiration::AtHeight(env.block.height + 100));
+        assert_eq!(details.on_expiry, OnExpiry::ReleaseToRecipient);
+
+        let ping = ExecuteMsg::Ping { id: String::from("switch") };
+        env.block.height += 60;
+        let err = execute(deps.as_mut(), env.clone(), mock_info("heir", &[]), ping.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { .. }));
+        let res = execute(deps.as_mut(), env.clone(), mock_info("source", &[]), ping.clone()).unwrap();
+        let expires = Expiration::AtHeight(env.block.height + 100);
+        assert_eq!(res.events[0].attributes[1], attr("expires", expires.to_string()));
+        assert_eq!(query_details(deps.as_ref(), mock_env(), String::from("switch")).unwrap().expires, expires);
+
+        // the original deadline passed, but the ping moved it
+        env.block.height += 60;
+        let settle = ExecuteMsg::SettleExpired { id: String::from("switch") };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), settle.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotExpired {}));
+
+        // a missed ping is final
+        env.block.height += 40;
+        let err = execute(deps.as_mut(), env.clone(), mock_info("source", &[]), ping).unwrap_err();
+        assert!(matches!(err, ContractError::Expired { .. }));
+        let res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), settle).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("heir"),
+                amount: coins(100, "tokens"),
+            })
+        );
+
+        // only switches take pings
+        let msg = CreateMsg {
+            id: String::from("plain"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("heir"),
+            ..CreateMsg::default()
         };
-        let execute_res = execute(deps.as_mut(), env, info, ExecuteMsg::Receive(rev_msg)).unwrap();
-        assert_eq!(0, execute_res.messages.len());
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Create(msg)).unwrap();
+        let ping = ExecuteMsg::Ping { id: String::from("plain") };
+        let err = execute(deps.as_mut(), env, mock_info("source", &[]), ping).unwrap_err();
+        assert!(matches!(err, ContractError::NoSwitch {}));
+    }
+
+    #[test]
+    fn approve_many_skips_what_cannot_be_approved() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        for (id, arbiter) in [("a", "arbiter"), ("b", "arbiter"), ("c", "other")] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: arbiter.into(),
+                recipient: String::from("recipient"),
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        }
 
-        let details = query_details(deps.as_ref(), "foobar".to_string()).unwrap();
+        let ids = ["a", "c", "missing", "b", "a"].map(String::from).to_vec();
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), ExecuteMsg::ApproveMany { ids }).unwrap();
+        assert_eq!(res.attributes, vec![attr("action", "approve_many"), attr("approved", "2"), attr("skipped", "3")]);
+        assert_eq!(res.messages.len(), 2);
+        // one reply id per payout of the batch
+        let ids: Vec<_> = res.messages.iter().map(|msg| msg.id).collect();
+        assert_eq!(ids, vec![PAYOUT_REPLY_ID, PAYOUT_REPLY_ID + 1]);
+        assert_eq!(PAYOUT_TOKENS.load(&deps.storage).unwrap(), vec!["tokens", "tokens"]);
+        let events: Vec<_> = res
+            .events
+            .iter()
+            .map(|event| (event.ty.as_str(), event.attributes[0].value.as_str()))
+            .collect();
         assert_eq!(
-            details,
-            DetailsResponse{
-                id: id.clone(),
-                arbiter: arbiter.clone(),
-                recipient: recipient.clone(),
-                source: source.clone(),
-                end_height: Some(123456),
-                end_time: None,
-                native_balance: vec![],
-                cw20_balance: vec![Cw20Coin{
-                    address: token_contract_addr.clone(),
-                    amount: Uint128::from(100u128),
-                }],
-                cw20_whitelist: vec![
-                    String::from("other-token"),
-                    String::from("my-token")
-                ],
-            }
+            events,
+            vec![
+                ("escrow_approved", "a"),
+                ("escrow_approve_skipped", "c"),
+                ("escrow_approve_skipped", "missing"),
+                ("escrow_approved", "b"),
+                ("escrow_approve_skipped", "a"),
+            ]
         );
+        let not_arbiter = ContractError::NotArbiter {
+            sender: String::from("arbiter"),
+        };
+        assert_eq!(res.events[1].attributes[1], attr("reason", not_arbiter.to_string()));
+        let settled = ContractError::AlreadySettled { status: Status::Approved };
+        assert_eq!(res.events[4].attributes[1], attr("reason", settled.to_string()));
+        assert_eq!(query_details(deps.as_ref(), mock_env(), String::from("c")).unwrap().status, Status::Open);
 
-        // approve it by arbiter
-        let env = mock_env();
-        let info = mock_info("arbiter", &[]);
-        let approve_res = execute(deps.as_mut(), env, info, ExecuteMsg::Approve{id:id.clone()}).unwrap();
-        let send_msg = Cw20ExecuteMsg::Transfer {
-            recipient: recipient.clone(),
-            amount: Uint128::from(100u128),
+        // pausing releases fails the whole batch
+        let pause = ExecuteMsg::Pause { block_releases: Some(true) };
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), pause).unwrap();
+        let ids = vec![String::from("c")];
+        let err = execute(deps.as_mut(), mock_env(), mock_info("other", &[]), ExecuteMsg::ApproveMany { ids }).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+    }
+
+    #[test]
+    fn refund_many_skips_what_cannot_be_refunded() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+
+        let mut env = mock_env();
+        let deadlines = [
+            ("a", "arbiter", Expiration::Never {}),
+            ("b", "other", Expiration::AtHeight(env.block.height + 10)),
+            ("c", "other", Expiration::Never {}),
+        ];
+        for (id, arbiter, expires) in deadlines {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: arbiter.into(),
+                recipient: String::from("recipient"),
+                expires,
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), env.clone(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        }
+        env.block.height += 10;
+
+        // a as its arbiter, b because it expired
+        let ids = ["a", "b", "c"].map(String::from).to_vec();
+        let res = execute(deps.as_mut(), env, mock_info("arbiter", &[]), ExecuteMsg::RefundMany { ids }).unwrap();
+        assert_eq!(res.attributes, vec![attr("action", "refund_many"), attr("refunded", "2"), attr("skipped", "1")]);
+        let ids: Vec<_> = res.messages.iter().map(|msg| msg.id).collect();
+        assert_eq!(ids, vec![PAYOUT_REPLY_ID, PAYOUT_REPLY_ID + 1]);
+        assert_eq!(PAYOUT_TOKENS.load(&deps.storage).unwrap(), vec!["tokens", "tokens"]);
+        let events: Vec<_> = res.events.iter().map(|event| event.ty.as_str()).collect();
+        assert_eq!(events, vec!["escrow_refunded", "escrow_refunded", "escrow_refund_skipped"]);
+        let not_arbiter = ContractError::NotArbiter {
+            sender: String::from("arbiter"),
         };
+        assert_eq!(res.events[2].attributes, vec![attr("id", "c"), attr("reason", not_arbiter.to_string())]);
+        for (id, status) in [("a", Status::Refunded), ("b", Status::Refunded), ("c", Status::Open)] {
+            assert_eq!(query_details(deps.as_ref(), mock_env(), id.to_string()).unwrap().status, status);
+        }
+        // only a counts as handled by the arbiter
+        let stats = query_arbiter_stats(deps.as_ref(), String::from("arbiter")).unwrap();
+        assert_eq!(stats.escrows_handled, 1);
+    }
 
-        assert_eq!(1, approve_res.messages.len());
+    #[test]
+    fn crank_pays_keeper_reward() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+        let update = UpdateConfigMsg {
+            keeper_reward: Some(coins(5, "tokens")),
+            ..UpdateConfigMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), ExecuteMsg::UpdateConfig(update)).unwrap();
+
+        let mut env = mock_env();
+        for (id, amount) in [("a", 100), ("b", 100), ("c", 3)] {
+            let msg = CreateMsg {
+                id: id.to_string(),
+                arbiter: "arbiter".into(),
+                recipient: String::from("recipient"),
+                expires: Expiration::AtHeight(env.block.height + 10),
+                ..CreateMsg::default()
+            };
+            execute(deps.as_mut(), env.clone(), mock_info("source", &coins(amount, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        }
+        // escrows past their deadline cannot be created to be cranked right away
+        let msg = CreateMsg {
+            id: String::from("d"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            expires: Expiration::AtHeight(env.block.height),
+            ..CreateMsg::default()
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap_err();
+        assert!(matches!(err, ContractError::Expired { .. }));
+        env.block.height += 20;
+
+        // every escrow pays its own reward, the rest is refunded
+        let res = execute(deps.as_mut(), env.clone(), mock_info("bot", &[]), ExecuteMsg::Crank { limit: 2 }).unwrap();
+        assert_eq!(res.attributes[1], attr("settled", "2"));
+        assert_eq!(res.attributes[2], attr("reward", "10tokens"));
         assert_eq!(
-            approve_res.messages.get(0).expect("no message").msg, 
-            CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: token_contract_addr.clone(),
-                msg: to_binary(&send_msg).unwrap(),
-                funds: vec![],
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("source"),
+                amount: coins(95, "tokens"),
+            })
+        );
+        assert_eq!(
+            res.messages.last().unwrap().msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("bot"),
+                amount: coins(10, "tokens"),
+            })
+        );
+        // no more than the escrow holds
+        let res = execute(deps.as_mut(), env, mock_info("bot", &[]), ExecuteMsg::Crank { limit: 2 }).unwrap();
+        assert_eq!(res.attributes[1], attr("settled", "1"));
+        assert_eq!(
+            res.messages.last().unwrap().msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("bot"),
+                amount: coins(3, "tokens"),
             })
         );
     }
+
+    // a balance with every kind of token
+    fn mixed_balance() -> GenericBalance {
+        GenericBalance {
+            native: coins(100, "tokens"),
+            cw20: vec![Cw20CoinVerified {
+                address: Addr::unchecked("my-token"),
+                amount: Uint128::new(50),
+            }],
+            nfts: vec![(Addr::unchecked("nfts"), String::from("1"))],
+        }
+    }
+
+    #[test]
+    fn escrow_balances_round_trip() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+        let msg = CreateMsg {
+            id: String::from("deal"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(1, "other")), ExecuteMsg::Create(msg)).unwrap();
+
+        let mut escrow = escrows_read(&deps.storage, "deal").unwrap();
+        escrow.balance = mixed_balance();
+        escrows_save(&mut deps.storage, &escrow, "deal").unwrap();
+
+        // one entry per token, what was held before is gone
+        let keys: Vec<String> = ESCROW_BALANCES
+            .prefix("deal")
+            .keys(&deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(keys, vec!["cw20:my-token", "native:tokens", "nft:nfts:1"]);
+        assert_eq!(escrow_balance_read(&deps.storage, "deal").unwrap(), mixed_balance());
+        assert_eq!(escrows_read(&deps.storage, "deal").unwrap().balance, mixed_balance());
+    }
+
+    #[test]
+    fn top_ups_only_write_deposited_tokens() {
+        use cosmwasm_std::testing::MockStorage;
+        use cosmwasm_std::Record;
+
+        // remembers the keys written to
+        struct RecordingStorage<'a> {
+            inner: &'a mut MockStorage,
+            written: Vec<Vec<u8>>,
+        }
+        impl Storage for RecordingStorage<'_> {
+            fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+                self.inner.get(key)
+            }
+            fn range<'a>(&'a self, start: Option<&[u8]>, end: Option<&[u8]>, order: Order) -> Box<dyn Iterator<Item = Record> + 'a> {
+                self.inner.range(start, end, order)
+            }
+            fn set(&mut self, key: &[u8], value: &[u8]) {
+                self.written.push(key.to_vec());
+                self.inner.set(key, value)
+            }
+            fn remove(&mut self, key: &[u8]) {
+                self.written.push(key.to_vec());
+                self.inner.remove(key)
+            }
+        }
+
+        let mut storage = MockStorage::new();
+        escrow_balance_add(&mut storage, "deal", &mixed_balance()).unwrap();
+
+        let mut recording = RecordingStorage {
+            inner: &mut storage,
+            written: vec![],
+        };
+        let deposit = GenericBalance {
+            native: coins(10, "tokens"),
+            ..GenericBalance::default()
+        };
+        let (totals, new_token) = escrow_balance_add(&mut recording, "deal", &deposit).unwrap();
+        assert_eq!(totals.native, coins(110, "tokens"));
+        assert!(!new_token);
+        // the last key part is not length prefixed, this is where the entries of deal start
+        let entries = ESCROW_BALANCES.key(("deal", "")).to_vec();
+        let balance_writes: Vec<&Vec<u8>> = recording.written.iter().filter(|key| key.starts_with(&entries)).collect();
+        assert_eq!(balance_writes, vec![&ESCROW_BALANCES.key(("deal", "native:tokens")).to_vec()]);
+
+        // the other tokens are still there
+        let mut balance = mixed_balance();
+        balance.native = coins(110, "tokens");
+        assert_eq!(escrow_balance_read(&storage, "deal").unwrap(), balance);
+    }
+
+    #[test]
+    fn settled_escrows_move_to_the_archive() {
+        use crate::state::{archive, escrows};
+
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+        let msg = CreateMsg {
+            id: String::from("deal"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg)).unwrap();
+        let arbiter = Addr::unchecked("arbiter");
+        assert_eq!(escrow_ids_by_arbiter(&deps.storage, &arbiter, None, 10).unwrap(), vec!["deal"]);
+
+        let approve = ExecuteMsg::Approve { id: String::from("deal") };
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), approve).unwrap();
+
+        assert!(escrows().may_load(&deps.storage, "deal").unwrap().is_none());
+        assert!(escrow_ids_by_arbiter(&deps.storage, &arbiter, None, 10).unwrap().is_empty());
+        let archived = archive().load(&deps.storage, "deal").unwrap();
+        assert_eq!(archived.status, Status::Approved);
+
+        // the id stays taken
+        let err = escrows_update(&mut deps.storage, archived, "deal").unwrap_err();
+        assert!(matches!(err, ContractError::IdAlreadyExists { .. }));
+        assert!(escrows().may_load(&deps.storage, "deal").unwrap().is_none());
+    }
+
+    #[test]
+    fn exported_handlers_wait_for_migrations() {
+        let mut deps = mock_dependencies();
+        default_instantiate(deps.as_mut());
+        let msg = CreateMsg {
+            id: String::from("deal"),
+            arbiter: "arbiter".into(),
+            recipient: String::from("recipient"),
+            ..CreateMsg::default()
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("source", &coins(100, "tokens")), ExecuteMsg::Create(msg.clone())).unwrap();
+        MIGRATION.save(&mut deps.storage, &MigrationProgress::default()).unwrap();
+
+        let deposit = GenericBalance {
+            native: coins(100, "tokens"),
+            ..GenericBalance::default()
+        };
+        let source = Addr::unchecked("source");
+        let create = CreateMsg {
+            id: String::from("other"),
+            ..msg
+        };
+        let errs = [
+            try_create(deps.as_mut(), mock_env(), create, deposit.clone(), &source).unwrap_err(),
+            try_top_up(deps.as_mut(), mock_env(), deposit, &source, String::from("deal")).unwrap_err(),
+            try_approve(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), String::from("deal")).unwrap_err(),
+            try_refund(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), String::from("deal")).unwrap_err(),
+        ];
+        for err in errs {
+            assert!(matches!(err, ContractError::MigrationInProgress {}));
+        }
+    }
 }
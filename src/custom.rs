@@ -0,0 +1,195 @@
+//! The entry points generic over a chain's custom message and query types, so the escrow
+//! can be embedded in contracts built with chain bindings (Injective, Neutron, Osmosis...).
+//! The escrow itself only ever sends standard messages and queries, these convert the
+//! dependencies going in and the responses coming out of the entry points in `contract`
+//! and `ibc`.
+use cosmwasm_std::{
+    Api, Binary, CosmosMsg, CustomMsg, CustomQuery, Deps, DepsMut, Empty, Env, IbcBasicResponse, IbcChannelCloseMsg,
+    IbcChannelConnectMsg, IbcChannelOpenMsg, IbcChannelOpenResponse, IbcPacketAckMsg, IbcPacketReceiveMsg,
+    IbcPacketTimeoutMsg, IbcReceiveResponse, MessageInfo, QuerierWrapper, Reply, Response, StdError, StdResult,
+    Storage, SubMsg,
+};
+
+use crate::error::ContractError;
+use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, SudoMsg};
+use crate::{contract, ibc};
+
+pub fn instantiate<C: CustomMsg, Q: CustomQuery>(
+    deps: DepsMut<Q>,
+    env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response<C>, ContractError> {
+    let querier = deps.querier;
+    let res = contract::instantiate(empty_deps_mut(deps.storage, deps.api, &querier), env, info, msg)?;
+    Ok(into_custom(res)?)
+}
+
+pub fn execute<C: CustomMsg, Q: CustomQuery>(
+    deps: DepsMut<Q>,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response<C>, ContractError> {
+    let querier = deps.querier;
+    let res = contract::execute(empty_deps_mut(deps.storage, deps.api, &querier), env, info, msg)?;
+    Ok(into_custom(res)?)
+}
+
+pub fn sudo<C: CustomMsg, Q: CustomQuery>(
+    deps: DepsMut<Q>,
+    env: Env,
+    msg: SudoMsg,
+) -> Result<Response<C>, ContractError> {
+    let querier = deps.querier;
+    let res = contract::sudo(empty_deps_mut(deps.storage, deps.api, &querier), env, msg)?;
+    Ok(into_custom(res)?)
+}
+
+pub fn reply<C: CustomMsg, Q: CustomQuery>(
+    deps: DepsMut<Q>,
+    env: Env,
+    msg: Reply,
+) -> Result<Response<C>, ContractError> {
+    let querier = deps.querier;
+    let res = contract::reply(empty_deps_mut(deps.storage, deps.api, &querier), env, msg)?;
+    Ok(into_custom(res)?)
+}
+
+pub fn query<Q: CustomQuery>(deps: Deps<Q>, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    let deps = Deps {
+        storage: deps.storage,
+        api: deps.api,
+        querier: QuerierWrapper::new(&*deps.querier),
+    };
+    contract::query(deps, env, msg)
+}
+
+pub fn migrate<C: CustomMsg, Q: CustomQuery>(
+    deps: DepsMut<Q>,
+    env: Env,
+    msg: MigrateMsg,
+) -> Result<Response<C>, ContractError> {
+    let querier = deps.querier;
+    let res = contract::migrate(empty_deps_mut(deps.storage, deps.api, &querier), env, msg)?;
+    Ok(into_custom(res)?)
+}
+
+pub fn ibc_channel_open<Q: CustomQuery>(
+    deps: DepsMut<Q>,
+    env: Env,
+    msg: IbcChannelOpenMsg,
+) -> Result<IbcChannelOpenResponse, ContractError> {
+    let querier = deps.querier;
+    ibc::ibc_channel_open(empty_deps_mut(deps.storage, deps.api, &querier), env, msg)
+}
+
+pub fn ibc_channel_connect<C: CustomMsg, Q: CustomQuery>(
+    deps: DepsMut<Q>,
+    env: Env,
+    msg: IbcChannelConnectMsg,
+) -> StdResult<IbcBasicResponse<C>> {
+    let querier = deps.querier;
+    into_custom_basic(ibc::ibc_channel_connect(empty_deps_mut(deps.storage, deps.api, &querier), env, msg)?)
+}
+
+pub fn ibc_channel_close<C: CustomMsg, Q: CustomQuery>(
+    deps: DepsMut<Q>,
+    env: Env,
+    msg: IbcChannelCloseMsg,
+) -> StdResult<IbcBasicResponse<C>> {
+    let querier = deps.querier;
+    into_custom_basic(ibc::ibc_channel_close(empty_deps_mut(deps.storage, deps.api, &querier), env, msg)?)
+}
+
+pub fn ibc_packet_receive<C: CustomMsg, Q: CustomQuery>(
+    deps: DepsMut<Q>,
+    env: Env,
+    msg: IbcPacketReceiveMsg,
+) -> StdResult<IbcReceiveResponse<C>> {
+    let querier = deps.querier;
+    let res = ibc::ibc_packet_receive(empty_deps_mut(deps.storage, deps.api, &querier), env, msg)?;
+    let messages = into_custom_submsgs(res.messages)?;
+    Ok(IbcReceiveResponse::new()
+        .set_ack(res.acknowledgement)
+        .add_submessages(messages)
+        .add_attributes(res.attributes)
+        .add_events(res.events))
+}
+
+pub fn ibc_packet_ack<C: CustomMsg, Q: CustomQuery>(
+    deps: DepsMut<Q>,
+    env: Env,
+    msg: IbcPacketAckMsg,
+) -> StdResult<IbcBasicResponse<C>> {
+    let querier = deps.querier;
+    into_custom_basic(ibc::ibc_packet_ack(empty_deps_mut(deps.storage, deps.api, &querier), env, msg)?)
+}
+
+pub fn ibc_packet_timeout<C: CustomMsg, Q: CustomQuery>(
+    deps: DepsMut<Q>,
+    env: Env,
+    msg: IbcPacketTimeoutMsg,
+) -> StdResult<IbcBasicResponse<C>> {
+    let querier = deps.querier;
+    into_custom_basic(ibc::ibc_packet_timeout(empty_deps_mut(deps.storage, deps.api, &querier), env, msg)?)
+}
+
+// the escrow does not query anything chain specific, so the custom querier can answer
+// its queries as they are
+fn empty_deps_mut<'a, Q: CustomQuery>(
+    storage: &'a mut dyn Storage,
+    api: &'a dyn Api,
+    querier: &'a QuerierWrapper<Q>,
+) -> DepsMut<'a, Empty> {
+    DepsMut {
+        storage,
+        api,
+        querier: QuerierWrapper::new(&**querier),
+    }
+}
+
+/// Turns a response of the escrow into one of a chain with custom messages
+pub fn into_custom<C: CustomMsg>(res: Response) -> StdResult<Response<C>> {
+    let messages = into_custom_submsgs(res.messages)?;
+    let mut custom = Response::new()
+        .add_submessages(messages)
+        .add_attributes(res.attributes)
+        .add_events(res.events);
+    custom.data = res.data;
+    Ok(custom)
+}
+
+fn into_custom_basic<C: CustomMsg>(res: IbcBasicResponse) -> StdResult<IbcBasicResponse<C>> {
+    let messages = into_custom_submsgs(res.messages)?;
+    Ok(IbcBasicResponse::new()
+        .add_submessages(messages)
+        .add_attributes(res.attributes)
+        .add_events(res.events))
+}
+
+fn into_custom_submsgs<C: CustomMsg>(messages: Vec<SubMsg>) -> StdResult<Vec<SubMsg<C>>> {
+    messages
+        .into_iter()
+        .map(|sub| {
+            Ok(SubMsg {
+                id: sub.id,
+                msg: into_custom_msg(sub.msg)?,
+                gas_limit: sub.gas_limit,
+                reply_on: sub.reply_on,
+            })
+        })
+        .collect()
+}
+
+fn into_custom_msg<C: CustomMsg>(msg: CosmosMsg) -> StdResult<CosmosMsg<C>> {
+    Ok(match msg {
+        CosmosMsg::Bank(msg) => CosmosMsg::Bank(msg),
+        CosmosMsg::Wasm(msg) => CosmosMsg::Wasm(msg),
+        CosmosMsg::Ibc(msg) => CosmosMsg::Ibc(msg),
+        CosmosMsg::Gov(msg) => CosmosMsg::Gov(msg),
+        CosmosMsg::Stargate { type_url, value } => CosmosMsg::Stargate { type_url, value },
+        // the escrow sends no staking, distribution or custom messages
+        msg => return Err(StdError::generic_err(format!("Unexpected message {:?}", msg))),
+    })
+}
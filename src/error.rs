@@ -1,4 +1,8 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{Coin, StdError, Uint128};
+use cw20::Expiration;
+use cw_utils::Duration;
+
+use crate::state::Status;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,24 +10,261 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
-    #[error("Unauthorized")]
-    Unauthorized {},
+    /// `expected` names who may do this instead, an address or a role like "the owner"
+    #[error("Sender {sender} is not allowed to do this, only {expected}")]
+    Unauthorized { sender: String, expected: String },
 
-    #[error("Escrow expired (end_height {end_height:?} end_time {end_time:?})")]
-    Expired {
-        end_height: Option<u64>,
-        end_time: Option<u64>,
-    },
+    #[error("Escrow {id} not found")]
+    NotFound { id: String },
+
+    /// The sender is not (one of) the escrow's arbiter(s). For contract arbiters such as a
+    /// cw3 multisig, members have to act through the contract, not directly
+    #[error("Sender {sender} is not an arbiter of this escrow (a contract arbiter has to execute the action itself)")]
+    NotArbiter { sender: String },
+
+    #[error("Escrow expired ({expires})")]
+    Expired { expires: Expiration },
+
+    #[error("Sender {sender} is not allowed to fund this escrow")]
+    NotAllowedFunder { sender: String },
+
+    #[error("Escrow is already settled ({status})")]
+    AlreadySettled { status: Status },
+
+    #[error("Escrow has not been accepted by the recipient yet")]
+    NotAccepted {},
+
+    #[error("Escrow has not reached its funding target yet")]
+    NotFunded {},
+
+    #[error("Escrow is no longer pending")]
+    NotPending {},
+
+    #[error("Expiry can only be extended")]
+    InvalidExpiry {},
+
+    #[error("Escrow has not started yet ({start})")]
+    NotStarted { start: Expiration },
+
+    #[error("{creator} already has {max} active escrows")]
+    TooManyOpenEscrows { creator: String, max: u32 },
+
+    #[error("Escrow has to expire within {max} from now")]
+    DurationTooLong { max: Duration },
+
+    #[error("Start has to be before expiry")]
+    InvalidStart {},
+
+    #[error("A dead man's switch needs a non-zero ping interval, no expiry, cannot be a crowdfund and has to release on expiry")]
+    InvalidSwitch {},
+
+    #[error("Escrow is not a dead man's switch")]
+    NoSwitch {},
+
+    #[error("Grace period has to be in the unit of the expiry")]
+    InvalidGracePeriod {},
+
+    #[error("Only arbiters may act until the grace period ends ({ends})")]
+    GracePeriod { ends: Expiration },
+
+    #[error("Invalid schedule: {reason}")]
+    InvalidSchedule { reason: String },
+
+    #[error("Invalid splits: {reason}")]
+    InvalidSplits { reason: String },
+
+    #[error("Invalid swap: {reason}")]
+    InvalidSwap { reason: String },
+
+    #[error("Escrow is not a swap")]
+    NotASwap {},
+
+    #[error("Swaps are only delivered with CompleteSwap")]
+    SwapOnly {},
+
+    #[error("Swap is still missing {missing}")]
+    SwapIncomplete { missing: String },
+
+    #[error("Creating an escrow costs {fee} on top of its funds")]
+    CreationFeeNotPaid { fee: Coin },
+
+    #[error("Payout of {token} failed")]
+    PayoutFailed { token: String },
+
+    #[error("A crowdfund needs a goal, a deadline and has to release on expiry")]
+    InvalidCrowdfund {},
+
+    #[error("Crowdfund missed its goal, contributors claim their funds back")]
+    CrowdfundFailed {},
+
+    #[error("Nothing to claim")]
+    NothingToClaim {},
+
+    #[error("Nothing unclaimed for longer than the unclaimed timeout")]
+    NothingToSweep {},
+
+    #[error("Only {available} {token} are not held for escrows")]
+    NotStray { token: String, available: Uint128 },
 
     #[error("Escrow not expired")]
     NotExpired {},
 
+    #[error("Escrow is disputed, only an arbiter can settle it")]
+    Disputed {},
+
+    #[error("Decision can still be appealed or is waiting for the appellate arbiter")]
+    AppealPending {},
+
+    #[error("Appeal window closed")]
+    AppealWindowClosed {},
+
+    #[error("No appealable decision on this escrow")]
+    NoDecision {},
+
+    #[error("Arbiter {arbiter} is not registered")]
+    ArbiterNotRegistered { arbiter: String },
+
+    #[error("Arbiter still handles active escrows")]
+    ArbiterBusy {},
+
+    #[error("Contract is paused")]
+    Paused {},
+
+    #[error("Sender is not the pending owner")]
+    NotPendingOwner {},
+
+    #[error("Ownership transfer expired")]
+    TransferExpired {},
+
+    #[error("Migration in progress, migrate again to finish it")]
+    MigrationInProgress {},
+
     #[error("Balance must, be greater than zero")]
     ZeroBalance {},
 
-    #[error("escrow id already in use")]
-    IdAlreadyExists {},
+    #[error("Limit must be greater than zero")]
+    ZeroLimit {},
+
+    /// Legacy escrows that expire at a height or a time, whichever comes first. They have
+    /// to be settled before migrating
+    #[error("Escrows {ids} expire at both a height and a time, settle them before migrating")]
+    AmbiguousDeadlines { ids: String },
+
+    #[error("Escrow holds {available} {token}, not the {requested} needed")]
+    InsufficientBalance {
+        token: String,
+        available: Uint128,
+        requested: Uint128,
+    },
+
+    #[error("Escrow holds no {token}")]
+    TokenNotHeld { token: String },
+
+    #[error("Escrow id {id} already in use")]
+    IdAlreadyExists { id: String },
+
+    #[error("Remote addresses need a transfer channel like \"channel-0\" and an address")]
+    InvalidRemoteAddress {},
+
+    #[error("Transfer memos cannot be longer than {max} bytes")]
+    MemoTooLong { max: usize },
+
+    #[error("Payouts to a remote address cannot be pulled or use a payout callback")]
+    InvalidRemotePayout {},
+
+    #[error("Arbiter proxy {proxy} was not instantiated by a configured relayer")]
+    UntrustedArbiterProxy { proxy: String },
+
+    #[error("Arbitration channels must be unordered")]
+    InvalidIbcOrder {},
+
+    #[error("Arbitration channels must use version {expected}, not {version}")]
+    InvalidIbcVersion { version: String, expected: String },
+
+    #[error("No arbiter bound to channel {channel_id}")]
+    UnboundChannel { channel_id: String },
+
+    #[error("An escrow cannot notify more than {max} subscribers")]
+    TooManySubscribers { max: u32 },
+
+    #[error("Escrow id must not be empty")]
+    EmptyId {},
+
+    #[error("Escrow id must be {min} to {max} characters long")]
+    InvalidIdLength { min: usize, max: usize },
+
+    #[error("Escrow id may only contain letters, digits, '-', '_' and '.', not '{c}'")]
+    InvalidIdChar { c: char },
+
+    #[error("Token {token} is not on the cw20_whitelist of the escrow")]
+    UnregisteredTokens { token: String },
+
+    #[error("Escrow cannot hold more than {max} different tokens")]
+    TooManyTokens { max: u32 },
+
+    #[error("Escrow needs at least one arbiter")]
+    NoArbiters {},
+
+    #[error("Threshold must be between 1 and the number of arbiters")]
+    InvalidThreshold {},
+
+    #[error("{address} cannot be both {roles} of a strict escrow")]
+    OverlappingParties { address: String, roles: String },
+
+    #[error("Escrow is decided by arbiter vote")]
+    VoteRequired {},
+
+    #[error("Basis points must not exceed 10000")]
+    InvalidBps {},
+
+    #[error("Terms hash must be a hex encoded sha256")]
+    InvalidTermsHash {},
+
+    #[error("Hash lock must be a hex encoded sha256")]
+    InvalidHashLock {},
+
+    #[error("Escrow has no hash lock")]
+    NoHashLock {},
+
+    #[error("Preimage does not match the hash lock")]
+    InvalidPreimage {},
+
+    #[error("Escrow has no conditions")]
+    NoConditions {},
+
+    #[error("Condition not met: {condition}")]
+    ConditionNotMet { condition: String },
+
+    #[error("Token {token} is not on the contract allowlist")]
+    TokenNotAllowed { token: String },
+
+    #[error("No receipt NFT contract is configured")]
+    ReceiptsDisabled {},
+
+    #[error("Claim receipts need plain payouts: no schedule, payout callback, release hook or remote recipient")]
+    InvalidReceipt {},
+
+    #[error("Template {template_id} not found")]
+    TemplateNotFound { template_id: String },
+
+    #[error("Template {template_id} does not accept {token}")]
+    TokenNotInTemplate { template_id: String, token: String },
+
+    #[error("Cannot migrate from different contract type: {previous_contract}")]
+    CannotMigrate { previous_contract: String },
+
+    #[error("Cannot migrate from newer version ({previous_version})")]
+    CannotMigrateVersion { previous_version: String },
+
+    #[error("The contract has no config yet, migrate with an admin to create one")]
+    ConfigRequired {},
+
+    #[error("Invalid contract version: {0}")]
+    SemVer(String),
+}
 
-    #[error("Only accepts tokens on the cw20_whitelist")]
-    UnregisteredTokens {},
+impl From<semver::Error> for ContractError {
+    fn from(err: semver::Error) -> Self {
+        Self::SemVer(err.to_string())
+    }
 }
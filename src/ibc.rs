@@ -0,0 +1,176 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
+use cosmwasm_std::{
+    from_binary, to_binary, Binary, Coin, CosmosMsg, DepsMut, Env, IbcBasicResponse, IbcChannelCloseMsg, IbcChannelConnectMsg,
+    IbcChannelOpenMsg, IbcChannelOpenResponse, IbcOrder, IbcPacketAckMsg, IbcPacketReceiveMsg, IbcPacketTimeoutMsg,
+    IbcReceiveResponse, StdResult, SubMsg, Timestamp, WasmMsg,
+};
+use prost::Message;
+
+use crate::contract::{ack_fail, ack_success, ARBITRATION_REPLY_ID};
+use crate::error::ContractError;
+use crate::msg::{ArbitrationPacket, ExecuteMsg};
+use crate::state::{RemoteAddress, ARBITER_CHANNELS};
+
+/// Channel version spoken by remote arbiters
+pub const ARBITRATION_VERSION: &str = "escrow-arbitration-1";
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_open(
+    _deps: DepsMut,
+    _env: Env,
+    msg: IbcChannelOpenMsg,
+) -> Result<IbcChannelOpenResponse, ContractError> {
+    let channel = msg.channel();
+    // packets are independent decisions, one stuck packet must not hold up the others
+    if channel.order != IbcOrder::Unordered {
+        return Err(ContractError::InvalidIbcOrder {});
+    }
+    let versions = std::iter::once(channel.version.as_str()).chain(msg.counterparty_version());
+    for version in versions {
+        if version != ARBITRATION_VERSION {
+            return Err(ContractError::InvalidIbcVersion {
+                version: version.to_string(),
+                expected: ARBITRATION_VERSION.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// the admin still has to bind the channel to an arbiter with `BindArbiterChannel`
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_connect(
+    _deps: DepsMut,
+    _env: Env,
+    msg: IbcChannelConnectMsg,
+) -> StdResult<IbcBasicResponse> {
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "ibc_channel_connect")
+        .add_attribute("channel_id", &msg.channel().endpoint.channel_id))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_close(
+    deps: DepsMut,
+    _env: Env,
+    msg: IbcChannelCloseMsg,
+) -> StdResult<IbcBasicResponse> {
+    let channel_id = &msg.channel().endpoint.channel_id;
+    ARBITER_CHANNELS.remove(deps.storage, channel_id);
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "ibc_channel_close")
+        .add_attribute("channel_id", channel_id))
+}
+
+// The packet is carried out by executing the contract itself, so if it fails, its state
+// changes are rolled back and `reply` acknowledges the error instead
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_receive(
+    _deps: DepsMut,
+    env: Env,
+    msg: IbcPacketReceiveMsg,
+) -> StdResult<IbcReceiveResponse> {
+    let channel_id = msg.packet.dest.channel_id;
+    let packet: ArbitrationPacket = match from_binary(&msg.packet.data) {
+        Ok(packet) => packet,
+        Err(err) => return Ok(IbcReceiveResponse::new().set_ack(ack_fail(err.to_string())?)),
+    };
+    let arbitrate = WasmMsg::Execute {
+        contract_addr: env.contract.address.into_string(),
+        msg: to_binary(&ExecuteMsg::IbcArbitrate {
+            channel_id: channel_id.clone(),
+            packet,
+        })?,
+        funds: vec![],
+    };
+    Ok(IbcReceiveResponse::new()
+        .set_ack(ack_success()?)
+        .add_submessage(SubMsg::reply_on_error(arbitrate, ARBITRATION_REPLY_ID))
+        .add_attribute("action", "ibc_packet_receive")
+        .add_attribute("channel_id", channel_id))
+}
+
+// the contract sends no packets of its own
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_ack(_deps: DepsMut, _env: Env, _msg: IbcPacketAckMsg) -> StdResult<IbcBasicResponse> {
+    Ok(IbcBasicResponse::new())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_timeout(_deps: DepsMut, _env: Env, _msg: IbcPacketTimeoutMsg) -> StdResult<IbcBasicResponse> {
+    Ok(IbcBasicResponse::new())
+}
+
+pub const MSG_TRANSFER_TYPE_URL: &str = "/ibc.applications.transfer.v1.MsgTransfer";
+
+/// `ibc.applications.transfer.v1.MsgTransfer`, which unlike `IbcMsg::Transfer` has a memo
+#[derive(Clone, PartialEq, Message)]
+struct MsgTransfer {
+    #[prost(string, tag = "1")]
+    source_port: String,
+    #[prost(string, tag = "2")]
+    source_channel: String,
+    #[prost(message, optional, tag = "3")]
+    token: Option<ProtoCoin>,
+    #[prost(string, tag = "4")]
+    sender: String,
+    #[prost(string, tag = "5")]
+    receiver: String,
+    #[prost(message, optional, tag = "6")]
+    timeout_height: Option<Height>,
+    #[prost(uint64, tag = "7")]
+    timeout_timestamp: u64,
+    #[prost(string, tag = "8")]
+    memo: String,
+}
+
+/// `cosmos.base.v1beta1.Coin`
+#[derive(Clone, PartialEq, Message)]
+struct ProtoCoin {
+    #[prost(string, tag = "1")]
+    denom: String,
+    #[prost(string, tag = "2")]
+    amount: String,
+}
+
+/// `ibc.core.client.v1.Height`
+#[derive(Clone, PartialEq, Message)]
+struct Height {
+    #[prost(uint64, tag = "1")]
+    revision_number: u64,
+    #[prost(uint64, tag = "2")]
+    revision_height: u64,
+}
+
+/// An ICS-20 transfer of `amount` from this contract, carrying `memo`
+pub(crate) fn transfer_with_memo(
+    env: &Env,
+    to: &RemoteAddress,
+    amount: Coin,
+    timeout: Timestamp,
+    memo: &str,
+) -> CosmosMsg {
+    let msg = MsgTransfer {
+        source_port: String::from("transfer"),
+        source_channel: to.chain_channel.clone(),
+        token: Some(ProtoCoin {
+            denom: amount.denom,
+            amount: amount.amount.to_string(),
+        }),
+        sender: env.contract.address.to_string(),
+        receiver: to.address.clone(),
+        timeout_height: None,
+        timeout_timestamp: timeout.nanos(),
+        memo: memo.to_string(),
+    };
+    CosmosMsg::Stargate {
+        type_url: MSG_TRANSFER_TYPE_URL.to_string(),
+        value: Binary::from(msg.encode_to_vec()),
+    }
+}
+
+/// The denom sent by an encoded `MsgTransfer`
+pub(crate) fn transfer_denom(value: &Binary) -> Option<String> {
+    MsgTransfer::decode(value.as_slice()).ok()?.token.map(|token| token.denom)
+}
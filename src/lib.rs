@@ -1,4 +1,14 @@
+pub mod conditions;
 pub mod contract;
-mod error;
+pub mod custom;
+pub mod error;
+pub mod ibc;
 pub mod msg;
 pub mod state;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+#[cfg(test)]
+mod multitest;
+
+pub use crate::error::ContractError;
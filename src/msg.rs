@@ -1,33 +1,402 @@
-use cosmwasm_std::{ Addr, Coin };
+use cosmwasm_schema::QueryResponses;
+use cosmwasm_std::{ Addr, Binary, Coin, Empty, Timestamp, Uint128 };
 use schemars::JsonSchema;
 use serde::{ Deserialize, Serialize };
-use cw20::{ Cw20Coin, Cw20ReceiveMsg };
+use cw20::{ Cw20Coin, Cw20ReceiveMsg, Expiration };
+use cw721::Cw721ReceiveMsg;
+use cw_utils::Duration;
 
-#[derive(Serialize, Deserialize, JsonSchema)]
-pub struct InstantiateMsg {}
+use crate::conditions::Condition;
+use crate::state::{Decision, Dispute, EscrowCounts, GenericBalance, OnExpiry, Ownership, RemoteAddress, Schedule, Status, TokenId, Verdict};
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct InstantiateMsg {
+    /// Owner, can update the contract wide settings. Defaults to the instantiating address.
+    pub admin: Option<String>,
+    /// cw20 contracts that escrows may hold. Tokens not on this list are rejected.
+    pub token_allowlist: Option<Vec<String>>,
+    /// Maximum number of different tokens a single escrow can hold. Defaults to 10.
+    pub max_tokens: Option<u32>,
+    /// Protocol fee in basis points taken on approve. Defaults to no fee.
+    pub fee_bps: Option<u16>,
+    /// Receives the protocol fees. Defaults to the admin.
+    pub fee_collector: Option<String>,
+    /// Second-level arbiter (e.g. a DAO) deciding appeals against arbiter decisions on
+    /// disputed escrows. Without it, decisions are final.
+    pub appellate_arbiter: Option<String>,
+    /// Seconds after a decision on a disputed escrow during which the losing party can
+    /// appeal. The payout waits until then. Defaults to a week.
+    pub appeal_window: Option<u64>,
+    /// Flat fee paid to the fee collector for every escrow created with native funds,
+    /// on top of what is escrowed. Defaults to none.
+    pub creation_fee: Option<Coin>,
+    /// Rejects every new escrow where the arbiter, source and recipient are not all
+    /// different addresses. Defaults to false, escrows can still opt in themselves.
+    pub strict_parties: Option<bool>,
+    /// cw721 contract (e.g. cw721-base) minting claim receipts for escrows created with
+    /// `mint_receipt`. The escrow contract has to be its minter. Defaults to none.
+    pub receipt_nft: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct UpdateConfigMsg {
+    pub max_tokens: Option<u32>,
+    pub fee_bps: Option<u16>,
+    pub fee_collector: Option<String>,
+    pub appellate_arbiter: Option<String>,
+    pub appeal_window: Option<u64>,
+    pub keeper_reward: Option<Vec<Coin>>,
+    /// Seconds after which unclaimed payouts can be swept, 0 turns sweeping off
+    pub unclaimed_timeout: Option<u64>,
+    /// Replaces the relayers whose proxies can be set as `arbiter_proxy`
+    pub arbiter_relayers: Option<Vec<String>>,
+    /// Longest an escrow may run until it expires, a zero duration removes the limit
+    pub max_duration: Option<Duration>,
+    /// Most active escrows a source can have at once, 0 removes the limit
+    pub max_open_per_source: Option<u32>,
+    /// Flat fee for creating an escrow, a zero amount removes it
+    pub creation_fee: Option<Coin>,
+    /// Whether all escrows must have distinct arbiters, source and recipient
+    pub strict_parties: Option<bool>,
+    /// Contract minting claim receipts, an empty string turns receipts off
+    pub receipt_nft: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct MigrateMsg {
+    /// Escrows to go through in this step, all of them if not set, must not be zero. Only
+    /// upgrades from before 0.11.0 go through them. Migrate again until the `done`
+    /// attribute is true, the contract rejects messages in between
+    #[serde(default)]
+    pub limit: Option<u32>,
+    /// Owner of contracts deployed before the config existed, which get a default one.
+    /// Required for those, ignored otherwise
+    #[serde(default)]
+    pub admin: Option<String>,
+    /// Receives the protocol fees of such contracts, defaults to the admin
+    #[serde(default)]
+    pub fee_collector: Option<String>,
+}
+
+/// Called by the chain itself, e.g. after a governance proposal passed
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
+pub enum SudoMsg {
+    /// Refunds an unsettled escrow right away, whatever its status
+    ForceRefund { id: String },
+    UpdateConfig(UpdateConfigMsg),
+    Pause { block_releases: Option<bool> },
+    Unpause {},
+    /// Settles up to `limit` expired escrows according to their `on_expiry`, for chains
+    /// calling the contract every block. Those that expired first go first. `limit` must
+    /// not be zero and is capped at 30. A call looks at no more than 100 expired escrows,
+    /// those that cannot be settled yet are skipped until a later call comes back to them
+    ProcessExpirations { limit: u32 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+#[serde(rename_all = "snake_case")]
 pub struct CreateMsg {
+    /// 1 to 64 letters, digits, '-', '_' or '.'
     pub id: String,
-    pub arbiter: String,
+    /// If true, the escrow is stored as `<creator>/<id>`, so the id only has to be unique
+    /// among the escrows of the creator. Every other message takes that full id, see
+    /// the `ResolveId` query
+    pub namespaced: Option<bool>,
+    /// A single address or a list, any of which may approve or refund
+    pub arbiter: Arbiters,
+    /// If set above 1, this many arbiters must agree via CastVote
+    pub threshold: Option<u32>,
+    /// If true, the escrow can only be approved after the recipient called Accept.
+    /// Until then the source may cancel it
+    pub requires_acceptance: Option<bool>,
+    /// If set, the escrow stays in `Funding` (and can be cancelled by the source) until
+    /// top-ups brought the balance up to these amounts
+    #[serde(alias = "required_amount")]
+    pub funding_target: Option<TokenAmounts>,
+    /// Allows arbiters to change the recipient, besides the recipient themselves
+    pub arbiter_can_set_recipient: Option<bool>,
+    /// Makes this a vesting escrow, the recipient claims vested tokens over time
+    pub schedule: Option<Schedule>,
+    /// If set, only these addresses (and the source) can top up the escrow
+    pub allowed_funders: Option<Vec<String>>,
+    /// Campaign mode: the `funding_target` is the goal and the expiry the deadline. If the
+    /// goal is reached, the escrow releases to the recipient at the deadline, otherwise every
+    /// contributor claims their funds back with `ClaimRefund`. Creating it needs no deposit
+    pub crowdfund: Option<bool>,
+    /// Hex encoded sha256 hash. The recipient can release the escrow to themselves
+    /// before expiry by revealing its preimage with `ClaimWithPreimage`, e.g. for
+    /// atomic swaps with another chain. Shorthand for a `Condition::Preimage`
+    pub hash_lock: Option<String>,
+    /// If set, the recipient can release the escrow to themselves before expiry with
+    /// `Fulfill` once all of these are met
+    pub conditions: Option<Vec<Condition>>,
     pub recipient: String,
-    /// When end height set and block height exceeds this value, the escrow is expired.
-    /// Once an escrow is expired, it can be returned to the original funder (via "refund").
-    pub end_height: Option<u64>,
-    /// When end time (in seconds since epoch 00:00:00 UTC on 1 January 1970) is set and
-    /// block time exceeds this value, the escrow is expired.
-    /// Once an escrow is expired, it can be returned to the original funder (via "refund").
-    pub end_time: Option<u64>,
+    /// From this height or time on, the escrow is expired and settles according to
+    /// `on_expiry` (by default, it can be returned to the original funder via "refund").
+    /// Never by default
+    #[serde(default)]
+    pub expires: Expiration,
+    /// If set, funds are locked right away, but the escrow cannot be approved or claimed
+    /// before this height or time. Has to come before `expires`
+    pub start: Option<Expiration>,
+    /// Blocks (for a height expiry) or seconds (for a time expiry) after expiry during
+    /// which only arbiters may act, e.g. to approve a late delivery. Only afterwards can
+    /// anyone settle the escrow
+    pub grace_period: Option<Duration>,
+    /// Makes this a dead man's switch: the source has to `Ping` within every interval,
+    /// otherwise the escrow expires and releases to the recipient, e.g. for inheritance
+    /// schemes. Leave `expires` and `on_expiry` unset, they follow from it
+    pub ping_interval: Option<Duration>,
+    /// If set, cw20 tokens are released to the recipient contract with `Send` carrying this
+    /// message rather than `Transfer`, e.g. to stake them right away. NFTs and native tokens
+    /// are sent as usual
+    pub release_hook: Option<Binary>,
+    /// Declares the recipient a contract implementing `PayoutCallbackMsg`. Native tokens
+    /// are then released by executing `EscrowPayout` on it with the tokens attached, so it
+    /// can act on the payout in the same transaction
+    pub payout_callback: Option<bool>,
+    /// If true, approving the escrow only sets the tokens aside for the recipient, who
+    /// withdraws them with `Claim`. For recipients that may reject pushed tokens, and
+    /// partial approvals add up until the next withdrawal. Not for vesting escrows
+    pub pull_payouts: Option<bool>,
+    /// If true, refunds only set the tokens aside for the source (or refund address) and
+    /// the other funders, who withdraw their share with `ClaimRefund`. For funders that
+    /// are contracts with strict receive logic
+    pub pull_refunds: Option<bool>,
+    /// Contracts implementing `EscrowHookMsg` to notify of changes to the escrow, from its
+    /// creation on. See `Subscribe`
+    pub subscribers: Option<Vec<String>>,
     // pub whitelist: <Vec<String>> // to avoid DoS attack
-    pub cw20_whitelist: Option<Vec<String>>
+    pub cw20_whitelist: Option<Vec<String>>,
+    /// Share of the balance (in basis points) paid to the arbiter when they approve or refund
+    pub arbiter_fee_bps: Option<u16>,
+    /// Refunds are sent here instead of to the source (e.g. a treasury wallet)
+    pub refund_address: Option<String>,
+    /// If set, native tokens released to the recipient are sent to this address on
+    /// another chain with an ICS-20 transfer. The `recipient` still acts on the escrow here
+    /// and receives cw20 tokens and NFTs. Transfers that time out come back to this
+    /// contract, see `RescueFunds`. Not with `payout_callback` or `pull_payouts`
+    pub remote_recipient: Option<RemoteAddress>,
+    /// Like `remote_recipient` for native tokens refunded to the source (or refund
+    /// address). Not with `pull_refunds`
+    pub remote_refund_address: Option<RemoteAddress>,
+    /// Proxy executing messages for an arbiter on another chain, e.g. a DAO reached
+    /// through Polytone. It can do everything an arbiter can, and must have been
+    /// instantiated by one of the configured `arbiter_relayers`
+    pub arbiter_proxy: Option<String>,
+    /// If true, the arbiters, source and recipient must all be different addresses, also
+    /// when the recipient is changed later. Always the case if the contract is strict
+    pub strict_parties: Option<bool>,
+    /// If true, a claim receipt with the escrow id as token id is minted to the recipient
+    /// on the configured `receipt_nft` contract. Whoever owns it when tokens are released
+    /// receives them, so the receivable can be sold. Not with `schedule`,
+    /// `payout_callback`, `release_hook` or `remote_recipient`
+    pub mint_receipt: Option<bool>,
+    /// Shares released tokens between several addresses, by basis points adding up to
+    /// 10000. What rounding leaves and NFTs go to the first. The `recipient` still acts
+    /// on the escrow. Not with `mint_receipt`, `payout_callback`, `release_hook` or
+    /// `remote_recipient`
+    pub splits: Option<Vec<(String, u16)>>,
+    /// Makes this a two-sided swap of the tokens sent on create against these amounts,
+    /// which only the recipient can deposit with `TopUp`. Once both sides are in, either
+    /// party delivers them crosswise with `CompleteSwap`, until then either can take
+    /// their own deposit back with `WithdrawSwapDeposit`. Expired swaps refund both sides
+    pub swap_for: Option<TokenAmounts>,
+    /// What happens once the escrow expired, defaults to refunding the source.
+    /// Rent deposits for example may rather release to the recipient.
+    pub on_expiry: Option<OnExpiry>,
+    /// Human readable context of the deal, e.g. for marketplaces
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Free-form data attached by the creator, not interpreted by the contract
+    pub metadata: Option<Binary>,
+    /// Hex encoded sha256 of the off-chain agreement the escrow is bound to
+    pub terms_hash: Option<String>,
+}
+
+/// Terms of a template, see `RegisterTemplate`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct TemplateMsg {
+    /// A single address or a list, any of which may approve or refund
+    pub arbiter: Arbiters,
+    /// If set above 1, this many arbiters must agree via CastVote
+    pub threshold: Option<u32>,
+    /// Share of the balance (in basis points) paid to the arbiter when they approve or refund
+    pub arbiter_fee_bps: Option<u16>,
+    /// Escrows expire this long after they were created, never if not set
+    pub duration: Option<Duration>,
+    /// Native denoms, cw20 and cw721 contracts the escrows may hold, any if not set
+    pub tokens: Option<Vec<String>>,
+}
+
+/// Creates an escrow on the terms of a registered template
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct CreateFromTemplateMsg {
+    pub template_id: String,
+    /// id of the new escrow, see `CreateMsg`
+    pub id: String,
+    pub recipient: String,
+    pub overrides: Option<TemplateOverrides>,
+}
+
+/// Deal specific settings of an escrow created from a template, see `CreateMsg`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct TemplateOverrides {
+    pub namespaced: Option<bool>,
+    /// Replaces the expiry following from the `duration` of the template
+    pub expires: Option<Expiration>,
+    pub refund_address: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub metadata: Option<Binary>,
+    pub terms_hash: Option<String>,
+}
+
+/// Mints a claim receipt on the `receipt_nft` contract, in the format of cw721-base
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptMintMsg {
+    Mint {
+        token_id: String,
+        owner: String,
+        token_uri: Option<String>,
+        extension: Option<Empty>,
+    },
+}
+
+/// Executed on recipient contracts that declared a `payout_callback`, with the released
+/// native tokens attached as funds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PayoutCallbackMsg {
+    EscrowPayout { id: String },
+}
+
+/// Sent by a remote arbiter over a channel bound with `BindArbiterChannel`, carried out
+/// as if the bound arbiter sent `Approve` or `Refund`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ArbitrationPacket {
+    Approve { id: String },
+    Refund { id: String },
+}
+
+/// Acknowledgement of an `ArbitrationPacket`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ArbitrationAck {
+    Result(Binary),
+    Error(String),
+}
+
+/// Executed on the contracts subscribed to an escrow whenever it changes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EscrowHookMsg {
+    EscrowChanged { id: String, change: EscrowChange },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EscrowChange {
+    Created,
+    Funded,
+    Approved,
+    Refunded,
+    Resolved,
+    Expired,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OwnershipAction {
+    /// Proposes a new owner, who has to accept before `expiry`. Only the owner can do this,
+    /// proposing again replaces the pending transfer
+    TransferOwnership {
+        new_owner: String,
+        expiry: Option<Expiration>,
+    },
+    /// Completes a transfer. Only the pending owner can do this
+    AcceptOwnership,
+    /// Leaves the contract without owner for good, admin-gated settings are frozen.
+    /// Only the owner can do this
+    RenounceOwnership,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum Arbiters {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for Arbiters {
+    fn default() -> Self {
+        Arbiters::Many(vec![])
+    }
+}
+
+impl From<&str> for Arbiters {
+    fn from(arbiter: &str) -> Self {
+        Arbiters::One(arbiter.to_string())
+    }
+}
+
+impl From<String> for Arbiters {
+    fn from(arbiter: String) -> Self {
+        Arbiters::One(arbiter)
+    }
+}
+
+impl Arbiters {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            Arbiters::One(arbiter) => vec![arbiter],
+            Arbiters::Many(arbiters) => arbiters,
+        }
+    }
+}
+
+/// A set of native and cw20 token amounts, plus NFTs
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct TokenAmounts {
+    pub native: Vec<Coin>,
+    pub cw20: Vec<Cw20Coin>,
+    /// cw721 NFTs as (contract, token id)
+    #[serde(default)]
+    pub nfts: Vec<(String, TokenId)>,
+}
+
+impl From<GenericBalance> for TokenAmounts {
+    fn from(balance: GenericBalance) -> Self {
+        TokenAmounts {
+            native: balance.native,
+            cw20: balance
+                .cw20
+                .into_iter()
+                .map(|c| Cw20Coin {
+                    address: c.address.into_string(),
+                    amount: c.amount,
+                })
+                .collect(),
+            nfts: balance
+                .nfts
+                .into_iter()
+                .map(|(contract, id)| (contract.into_string(), id))
+                .collect(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
 pub enum ReceiveMsg {
     Create(CreateMsg),
-    /// Adds all sent native tokens to the contract
+    CreateFromTemplate(CreateFromTemplateMsg),
+    /// Adds the sent tokens to the escrow
     TopUp {
         id: String,
     },
@@ -35,31 +404,438 @@ pub enum ReceiveMsg {
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
 pub enum ExecuteMsg {
     Create(CreateMsg),
+    /// Creates an escrow with the arbiters, fees, duration and tokens of a template
+    CreateFromTemplate(CreateFromTemplateMsg),
     // Approve sends all tokens to the recipient. Only the arbiter can do this
     Approve {  
         id: String,
     },
-     // Refund returns all remaining tokens to the original sender, The arbiter can do this any time, or anyone can do this after a timeout  
+    /// Approves each of the escrows, e.g. for a marketplace settling the day's orders.
+    /// Escrows the sender cannot approve are skipped, an `escrow_approve_skipped` event
+    /// gives the reason
+    ApproveMany {
+        ids: Vec<String>,
+    },
+    /// Sends part of the tokens to the recipient, the escrow stays open with the remainder.
+    /// Only the arbiter can do this
+    ApprovePartial {
+        id: String,
+        amounts: TokenAmounts,
+    },
+    /// Sends everything held of the named native denoms, cw20 or cw721 contracts to the
+    /// recipient, e.g. only the payment token. The escrow stays open with the rest.
+    /// Only the arbiter can do this
+    ApproveTokens {
+        id: String,
+        tokens: Vec<String>,
+    },
+    // Refund returns all remaining tokens to the original sender (or the refund address).
+    // The arbiter can do this any time, or anyone can do this after a timeout
     Refund {
         id: String,
     },
+    /// Refunds each of the escrows, under the same rules as `Refund`. Escrows the sender
+    /// cannot refund are skipped, an `escrow_refund_skipped` event gives the reason
+    RefundMany {
+        ids: Vec<String>,
+    },
+    /// Returns part of the tokens to the source, the escrow stays open with the remainder.
+    /// Only the arbiter can do this
+    RefundPartial {
+        id: String,
+        amounts: TokenAmounts,
+    },
+    /// Returns everything held of the named native denoms, cw20 or cw721 contracts to the
+    /// source, e.g. tokens deposited by mistake. The escrow stays open with the rest.
+    /// Only the arbiter can do this
+    RefundTokens {
+        id: String,
+        tokens: Vec<String>,
+    },
+    /// Settles the escrow by splitting every token: `recipient_bps` basis points go to the
+    /// recipient, the rest back to the source. Only the arbiter can do this
+    Resolve {
+        id: String,
+        recipient_bps: u16,
+    },
     TopUp {
         id: String,
     },
+    /// Delivers the deposit of the source to the recipient and the recipient's to the
+    /// source, once both cover the terms of the swap. Either party can do this
+    CompleteSwap {
+        id: String,
+    },
+    /// The source or recipient of a swap takes back their own deposit before it completed
+    WithdrawSwapDeposit {
+        id: String,
+    },
+    /// Settles an expired escrow according to its `on_expiry` setting. Anyone can do this
+    SettleExpired {
+        id: String,
+    },
+    /// The recipient accepts a pending escrow, which makes it approvable once funded
+    Accept {
+        id: String,
+    },
+    /// The source withdraws a pending or funding (not yet accepted or not fully funded) escrow,
+    /// all tokens are refunded
+    Cancel {
+        id: String,
+    },
+    /// Changes where released tokens go, e.g. after a key compromise. A `remote_recipient`
+    /// is cleared. The recipient can do this, arbiters only if allowed at creation
+    SetRecipient {
+        id: String,
+        new_recipient: String,
+    },
+    /// Assigns the claim to refunds and every other right of the source to `new_source`,
+    /// e.g. to sell it. A `refund_address` or `remote_refund_address` is cleared. Only the
+    /// source can do this
+    TransferSourceRights {
+        id: String,
+        new_source: String,
+    },
+    /// Moves the deadline further out. Only the party the escrow pays out to on expiry
+    /// can do this: the source for `refund_to_source`, the recipient for
+    /// `release_to_recipient`. The new expiration has to be of the same kind and later,
+    /// or `Never`
+    ExtendExpiry {
+        id: String,
+        expires: Expiration,
+    },
+    /// Proves the source is still around, moving the expiry of a dead man's switch one
+    /// ping interval out from now. Only the source can do this, before the escrow expired
+    Ping {
+        id: String,
+    },
+    /// Registers a contract implementing `EscrowHookMsg` to be notified whenever the
+    /// escrow is funded, approved, refunded, resolved or expires. A failing subscriber does
+    /// not hold up the escrow. Only the source, the recipient or an arbiter can do this
+    Subscribe {
+        id: String,
+        subscriber: String,
+    },
+    /// Stops the notifications, by the subscriber itself or whoever may subscribe
+    Unsubscribe {
+        id: String,
+        subscriber: String,
+    },
+    /// Returns the contribution of the sender to a crowdfund that missed its goal. For
+    /// escrows with `pull_refunds`, withdraws what was refunded to the sender so far instead
+    ClaimRefund {
+        id: String,
+    },
+    /// Releases a hash locked escrow to the recipient, `preimage` is the hex encoded
+    /// preimage of the `hash_lock`. Only the recipient can do this, before expiry.
+    /// Any other conditions of the escrow have to be met as well
+    ClaimWithPreimage {
+        id: String,
+        preimage: String,
+    },
+    /// Releases the escrow to the recipient once all its conditions are met. Only the
+    /// recipient can do this, before expiry. `preimage` is needed for `Preimage` conditions
+    Fulfill {
+        id: String,
+        preimage: Option<String>,
+    },
+    /// Sends the vested, unclaimed part of a vesting escrow to the recipient. For escrows
+    /// with `pull_payouts`, withdraws what was released to the sender so far instead.
+    /// Only the recipient can do this
+    Claim {
+        id: String,
+    },
+    /// Source or recipient contest an open escrow before it expires. Until an arbiter
+    /// approves, refunds or resolves it, nothing is released or refunded automatically
+    RaiseDispute {
+        id: String,
+        reason: String,
+    },
+    /// The party losing (part of) the balance by an arbiter decision on a disputed escrow
+    /// escalates it to the appellate arbiter, while the appeal window is open
+    Appeal {
+        id: String,
+    },
+    /// Final decision of the appellate arbiter on an appealed escrow, paid out right away
+    DecideAppeal {
+        id: String,
+        recipient_bps: u16,
+    },
+    /// Carries out an arbiter decision that was not appealed in time. Anyone can do this
+    FinalizeDecision {
+        id: String,
+    },
+    /// Consent of the source or the recipient to release the escrow. Once both called it,
+    /// the tokens go to the recipient without the arbiter (and without arbiter fee)
+    MutualApprove {
+        id: String,
+    },
+    /// Records the arbiter's verdict. Once `threshold` arbiters agree, the escrow is released
+    /// or refunded. Votes can be changed until then
+    CastVote {
+        id: String,
+        verdict: Verdict,
+    },
     // This accepts a properly-encoded ReceiveMsg from a cw20 contract
     Receive(Cw20ReceiveMsg),
+    /// Deposit for fee-on-transfer cw20 tokens, where less than the amount announced by
+    /// `Receive` may arrive. Pulls `amount` from the sender via `TransferFrom` (so an
+    /// allowance is needed) and credits the escrow with what the contract actually received
+    DepositCw20 {
+        token: String,
+        amount: Uint128,
+        msg: ReceiveMsg,
+    },
+    /// Creates an escrow funded with `amount` of the cw20 `token`, pulled from the sender
+    /// via `TransferFrom` under an allowance they granted the contract before. For
+    /// integrators that cannot `Send` with a hook message, see `DepositCw20`
+    CreateFromAllowance {
+        msg: CreateMsg,
+        token: String,
+        amount: Uint128,
+    },
+    /// This accepts a properly-encoded ReceiveMsg from a cw721 contract, to escrow the NFT
+    ReceiveNft(Cw721ReceiveMsg),
+    /// Adds the sender to the arbiter registry or updates their profile. Native tokens
+    /// sent along are added to their bond
+    RegisterArbiter {
+        fee_bps: u16,
+        categories: Vec<String>,
+    },
+    /// Removes the sender from the arbiter registry and returns their bond. Fails while
+    /// they still handle active escrows
+    UnregisterArbiter {},
+    /// Ends a periodic schedule after the current period: what the started periods paid
+    /// stays claimable by the recipient, the rest is refunded. Only arbiters can do this
+    StopSchedule {
+        id: String,
+    },
+    /// Registers a template escrows can be created from, or replaces one the sender
+    /// registered before. Anyone can do this
+    RegisterTemplate {
+        id: String,
+        template: TemplateMsg,
+    },
+    /// Removes a template, escrows created from it are not affected. Only its owner
+    /// and the admin can do this
+    RemoveTemplate {
+        id: String,
+    },
+    /// Settles up to `limit` expired escrows, see `SudoMsg::ProcessExpirations`. Anyone can do this, the sender receives the
+    /// keeper reward for every settled escrow, taken out of what that escrow holds
+    Crank {
+        limit: u32,
+    },
+    /// Sends the claimable payouts of the escrow that nobody withdrew within the
+    /// `unclaimed_timeout` to `to`, e.g. a community pool or treasury.
+    /// Only the admin can do this
+    SweepUnclaimed {
+        id: String,
+        to: String,
+    },
+    /// Sends `amount` of a native denom or cw20 contract that was sent to the contract
+    /// directly (not with Create or TopUp) to `to`. Only what exceeds the funds held
+    /// for escrows, unclaimed payouts and arbiter bonds can be moved.
+    /// Only the admin can do this
+    RescueFunds {
+        denom_or_token: String,
+        amount: Uint128,
+        to: String,
+    },
+    /// Stops new escrows and top-ups, with `block_releases` also every payout to a
+    /// recipient. Refunds keep working. Only the admin can do this
+    Pause {
+        block_releases: Option<bool>,
+    },
+    /// Lifts a pause. Only the admin can do this
+    Unpause {},
+    /// Adds and removes cw20 contracts from the global allowlist. Only the admin can do this
+    UpdateTokenAllowlist {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    /// Adds and removes addresses exempt from the creation and protocol fees, like
+    /// partner marketplaces. Only the admin can do this
+    UpdateFeeExempt {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    /// Adds and removes sources exempt from `max_open_per_source`, like trusted
+    /// integrators. Only the admin can do this
+    UpdateOpenLimitExempt {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    /// Updates the contract wide settings. Fields left empty are unchanged.
+    /// Only the admin can do this
+    UpdateConfig(UpdateConfigMsg),
+    /// Lets the remote arbiter on the other end of `channel_id` approve and refund the
+    /// escrows naming `arbiter` as arbiter, or unbinds the channel without an arbiter.
+    /// Only the admin can do this
+    BindArbiterChannel {
+        channel_id: String,
+        arbiter: Option<String>,
+    },
+    /// Carries out a packet of a remote arbiter. Only the contract itself can do this,
+    /// when it receives the packet
+    IbcArbitrate {
+        channel_id: String,
+        packet: ArbitrationPacket,
+    },
+    /// Hands over or gives up ownership of the contract
+    UpdateOwnership(OwnershipAction),
 }
 
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, QueryResponses)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
-    /// Show all open escrows. Return type is ListResponse.
-    // List {},
-    /// Returns a human-readable representation of the arbiter.
+    /// Show all active escrows, or only those in the given status (settled statuses
+    /// are looked up in the archive) and/or handled by the given arbiter.
+    /// Return type is ListResponse.
+    #[returns(ListResponse)]
+    List {
+        start_after: Option<String>,
+        limit: Option<u32>,
+        status: Option<Status>,
+        arbiter: Option<String>,
+    },
+    /// Show all settled escrows. Return type is ListResponse.
+    #[returns(ListResponse)]
+    ListArchived {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Show all active escrows handled by the given arbiter. Return type is ListResponse.
+    #[returns(ListResponse)]
+    ListByArbiter {
+        arbiter: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Show open escrows past their deadline and grace period for keepers to `Crank`, those
+    /// expiring at a height first, each kind in the order they expired. Defaults to the
+    /// current block.
+    /// Pages continue after the `start_after` id in this order. Return type is ListResponse.
+    #[returns(ListResponse)]
+    ListExpired {
+        as_of_height: Option<u64>,
+        as_of_time: Option<Timestamp>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Show all active escrows holding the given native denom or cw20 token.
+    /// Return type is ListResponse.
+    #[returns(ListResponse)]
+    ListByToken {
+        denom_or_cw20: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Returns how many escrows `List` with the same filters would page through.
+    /// Return type is CountResponse.
+    #[returns(CountResponse)]
+    Count {
+        status: Option<Status>,
+        arbiter: Option<String>,
+    },
+    /// Show active escrows with a deadline from `from` up to (not including) `to`, both
+    /// heights or both times, those expiring first first. Pages continue after the
+    /// `start_after` id in this order. Return type is ListResponse.
+    #[returns(ListResponse)]
+    ListExpiringBetween {
+        from: Expiration,
+        to: Expiration,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Returns the details of the named escrow, active or archived, error if not created.
+    /// Return type: DetailsResponse.
+    #[returns(DetailsResponse)]
     Details { id: String },
+    /// Returns the full id of the escrow the `creator` created with `namespaced` and
+    /// `id`, or of the global escrow `id` without a creator. Error if there is none.
+    /// Return type: ResolveIdResponse.
+    #[returns(ResolveIdResponse)]
+    ResolveId {
+        creator: Option<String>,
+        id: String,
+    },
+    /// Returns the votes cast on a threshold escrow. Return type: VotesResponse.
+    #[returns(VotesResponse)]
+    Votes { id: String },
+    /// Returns what every funder deposited. Return type: ContributionsResponse.
+    #[returns(ContributionsResponse)]
+    Contributions { id: String },
+    /// Returns what happened to the escrow so far, oldest first, paged by sequence
+    /// number. Return type: HistoryResponse.
+    #[returns(HistoryResponse)]
+    History {
+        id: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns what is waiting to be withdrawn with `Claim` or `ClaimRefund`, for escrows
+    /// with `pull_payouts` or `pull_refunds`. Return type: ClaimableResponse.
+    #[returns(ClaimableResponse)]
+    Claimable { id: String },
+    /// Returns the contracts notified of changes to the escrow.
+    /// Return type: SubscribersResponse.
+    #[returns(SubscribersResponse)]
+    Subscribers { id: String },
+    /// Returns whether the escrow is expired as of the current block.
+    /// Return type: IsExpiredResponse.
+    #[returns(IsExpiredResponse)]
+    IsExpired { id: String },
+    /// Returns how far the escrow is from its funding target. Return type: FundingResponse.
+    #[returns(FundingResponse)]
+    Funding { id: String },
+    /// Returns the contract wide settings. Return type: ConfigResponse.
+    #[returns(ConfigResponse)]
+    Config {},
+    /// Returns how many active escrows `source` has against `max_open_per_source`.
+    /// Return type: OpenEscrowsResponse.
+    #[returns(OpenEscrowsResponse)]
+    OpenEscrows { source: String },
+    /// Returns whether `address` pays no creation or protocol fees.
+    /// Return type: FeeExemptResponse.
+    #[returns(FeeExemptResponse)]
+    FeeExempt { address: String },
+    /// Returns the owner and any pending transfer. Return type: Ownership.
+    #[returns(Ownership)]
+    Ownership {},
+    /// Returns what all active escrows hold together and how many escrows there are.
+    /// Return type: StatsResponse.
+    #[returns(StatsResponse)]
+    Stats {},
+    /// Returns the registry profile of an arbiter. Return type: ArbiterInfo.
+    #[returns(ArbiterInfo)]
+    Arbiter { address: String },
+    /// Returns the track record of an arbiter. Return type: ArbiterStatsResponse.
+    #[returns(ArbiterStatsResponse)]
+    ArbiterStats { arbiter: String },
+    /// Lists registered arbiters, optionally only those handling `category`.
+    /// Return type: ArbitersResponse.
+    #[returns(ArbitersResponse)]
+    ListArbiters {
+        category: Option<String>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Returns a registered template. Return type: TemplateResponse.
+    #[returns(TemplateResponse)]
+    Template { id: String },
+    /// Lists registered templates, optionally only those of `owner`.
+    /// Return type: TemplatesResponse.
+    #[returns(TemplatesResponse)]
+    ListTemplates {
+        owner: Option<String>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -72,25 +848,274 @@ pub struct ArbiterResponse {
 pub struct DetailsResponse {
     /// id of this escrow
     pub id: String,
-    /// arbiter can decide to approve or refund the escrow
-    pub arbiter: String,
+    /// any of the arbiters can decide to approve or refund the escrow
+    pub arbiters: Vec<String>,
+    /// number of arbiters that must agree
+    pub threshold: u32,
     /// if approved, funds go to the recipient
     pub recipient: String,
     /// if refunded, funds go to the source
     pub source: String,
-    /// When end height set and block height exceeds this value, the escrow is expired.
-    /// Once an escrow is expired, it can be returned to the original funder (via "refund").
-    pub end_height: Option<u64>,
-    /// When end time (in seconds since epoch 00:00:00 UTC on 1 January 1970) is set and
-    /// block time exceeds this value, the escrow is expired.
+    /// From this height or time on, the escrow is expired.
     /// Once an escrow is expired, it can be returned to the original funder (via "refund").
-    pub end_time: Option<u64>,
+    pub expires: Expiration,
+    /// no approve or claim before this height or time
+    pub start: Option<Expiration>,
+    /// only arbiters may act for this long after expiry
+    pub grace_period: Option<Duration>,
+    /// for a dead man's switch, how often the source has to ping
+    pub ping_interval: Option<Duration>,
+    /// message cw20 tokens are sent to the recipient with on release
+    pub release_hook: Option<Binary>,
+    /// if true, native tokens are released with a `PayoutCallbackMsg`
+    pub payout_callback: bool,
+    /// if true, released tokens wait for the recipient to `Claim` them
+    pub pull_payouts: bool,
+    /// if true, refunded tokens wait for the funders to `ClaimRefund` them
+    pub pull_refunds: bool,
+    /// block the escrow was created in, unknown for escrows created before it was recorded
+    pub created_at_height: Option<u64>,
+    pub created_at_time: Option<Timestamp>,
+    /// everything deposited after creation
+    pub topped_up: TokenAmounts,
+    /// whether the escrow is expired as of the queried block
+    pub is_expired: bool,
+    /// native tokens are released or refunded to these addresses on other chains
+    pub remote_recipient: Option<RemoteAddress>,
+    pub remote_refund_address: Option<RemoteAddress>,
+    /// acts for an arbiter on another chain
+    pub arbiter_proxy: Option<String>,
+    /// arbiters, source and recipient are kept distinct
+    pub strict_parties: bool,
+    /// cw721 contract of the claim receipt, payouts go to its owner
+    pub receipt: Option<String>,
+    /// released tokens are shared between these addresses, by basis points
+    pub splits: Vec<(String, u16)>,
+    /// what the source offers in a swap, and what the recipient has to deposit for it
+    pub swap_offered: Option<TokenAmounts>,
+    pub swap_for: Option<TokenAmounts>,
     /// Balance in native tokens
     pub native_balance: Vec<Coin>,
     /// Balance in cw20 tokens
     pub cw20_balance: Vec<Cw20Coin>,
+    /// NFTs held as (contract, token id)
+    pub nft_balance: Vec<(String, TokenId)>,
     /// Whitelisted cw20 tokens
     pub cw20_whitelist: Vec<String>,
+    /// Share of the balance (in basis points) paid to the arbiter on approve or refund
+    pub arbiter_fee_bps: u16,
+    /// if set, refunds go here instead of to the source
+    pub refund_address: Option<String>,
+    /// what happens to the balance once expired
+    pub on_expiry: OnExpiry,
+    /// where the escrow is in its lifecycle
+    pub status: Status,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub metadata: Option<Binary>,
+    /// sha256 of the agreement, fixed at creation
+    pub terms_hash: Option<String>,
+    /// whether source and recipient called MutualApprove
+    pub source_approved: bool,
+    pub recipient_approved: bool,
+    /// false while waiting for the recipient to accept
+    pub accepted: bool,
+    /// amounts required before the escrow becomes active
+    pub funding_target: Option<TokenAmounts>,
+    /// whether arbiters may change the recipient
+    pub arbiter_can_set_recipient: bool,
+    /// vesting schedule, if any
+    pub schedule: Option<Schedule>,
+    /// what the recipient claimed so far
+    pub claimed: TokenAmounts,
+    /// who besides the source may top up, anyone if not set
+    pub allowed_funders: Option<Vec<String>>,
+    /// whether this is a crowdfund campaign
+    pub crowdfund: bool,
+    /// what has to hold before the recipient can release
+    pub conditions: Vec<Condition>,
+    /// the dispute raised by source or recipient, if any
+    pub dispute: Option<Dispute>,
+    /// arbiter decision waiting for the appeal window, if any
+    pub decision: Option<Decision>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ArbiterInfo {
+    pub address: String,
+    /// fee asked for, in basis points
+    pub fee_bps: u16,
+    pub categories: Vec<String>,
+    pub bond: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ArbitersResponse {
+    pub arbiters: Vec<ArbiterInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct TemplateResponse {
+    pub id: String,
+    pub owner: String,
+    pub arbiters: Vec<String>,
+    pub threshold: u32,
+    pub arbiter_fee_bps: u16,
+    /// escrows expire this long after creation
+    pub duration: Option<Duration>,
+    /// tokens the escrows may hold, any if empty
+    pub tokens: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct TemplatesResponse {
+    pub templates: Vec<TemplateResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ArbiterStatsResponse {
+    pub arbiter: String,
+    /// escrows settled or decided by the arbiter
+    pub escrows_handled: u64,
+    pub disputes_resolved: u64,
+    /// decisions changed on appeal
+    pub decisions_overturned: u64,
+    /// seconds from raising a dispute to the decision, none without resolved disputes
+    pub average_resolution_time: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ConfigResponse {
+    /// None once renounced, see the Ownership query for a pending transfer
+    pub owner: Option<String>,
+    /// cw20 and cw721 contracts accepted for deposits
+    pub token_allowlist: Vec<String>,
+    pub max_tokens: u32,
+    pub fee_bps: u16,
+    pub fee_collector: String,
+    pub appellate_arbiter: Option<String>,
+    pub appeal_window: u64,
+    pub paused: bool,
+    pub releases_paused: bool,
+    /// paid per escrow settled with Crank
+    pub keeper_reward: Vec<Coin>,
+    /// seconds after which unclaimed payouts can be swept with SweepUnclaimed
+    pub unclaimed_timeout: Option<u64>,
+    /// Polytone voices or ICA controllers trusted to instantiate arbiter proxies
+    pub arbiter_relayers: Vec<String>,
+    /// longest an escrow may run until it expires
+    pub max_duration: Option<Duration>,
+    /// most active escrows a source can have at once
+    pub max_open_per_source: Option<u32>,
+    /// paid on top of the funds of escrows created with `Create`
+    pub creation_fee: Option<Coin>,
+    /// escrows must have distinct arbiters, source and recipient
+    pub strict_parties: bool,
+    /// mints the claim receipts of escrows
+    pub receipt_nft: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct FeeExemptResponse {
+    pub exempt: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct OpenEscrowsResponse {
+    /// active escrows of the source
+    pub open: u32,
+    /// whether the source is exempt from `max_open_per_source`
+    pub exempt: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct CountResponse {
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct StatsResponse {
+    /// total value locked, without NFTs
+    pub total_locked: TokenAmounts,
+    pub counts: EscrowCounts,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct VoteInfo {
+    pub arbiter: String,
+    pub verdict: Verdict,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct VotesResponse {
+    pub votes: Vec<VoteInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ContributionInfo {
+    pub funder: String,
+    pub amounts: TokenAmounts,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ContributionsResponse {
+    pub contributions: Vec<ContributionInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct SubscribersResponse {
+    pub subscribers: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct HistoryEntry {
+    pub seq: u64,
+    /// create, accept, top_up, set_recipient, transfer_source, extend_expiry, dispute,
+    /// approve, claim, stop_schedule, swap, withdraw, refund, claim_refund,
+    /// withdraw_claimable, cancel, resolve or expire
+    pub action: String,
+    pub actor: Option<String>,
+    pub amount: TokenAmounts,
+    pub height: u64,
+    pub time: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct HistoryResponse {
+    pub history: Vec<HistoryEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ClaimableInfo {
+    pub owner: String,
+    pub amounts: TokenAmounts,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ClaimableResponse {
+    pub claimable: Vec<ClaimableInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct IsExpiredResponse {
+    pub id: String,
+    pub expired: bool,
+    /// from when on the escrow counts as expired
+    pub expires_at: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct FundingResponse {
+    pub id: String,
+    pub status: Status,
+    /// amounts required before the escrow becomes active, if any
+    pub target: Option<TokenAmounts>,
+    /// what the escrow currently holds
+    pub balance: TokenAmounts,
+    /// what is still missing to reach the target
+    pub missing: TokenAmounts,
+    /// whether the current balance covers the target
+    pub funded: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -99,3 +1124,22 @@ pub struct ListResponse{
     pub escrows: Vec<String>,
 }
 
+/// Set as response data on Create, so calling contracts can read it from the reply
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct CreateResponse {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ResolveIdResponse {
+    /// what the other messages and queries take
+    pub id: String,
+}
+
+/// Set as response data on approve and refund (also partial). Amounts are before any fees.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PayoutResponse {
+    pub id: String,
+    pub native: Vec<Coin>,
+    pub cw20: Vec<Cw20Coin>,
+}
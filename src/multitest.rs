@@ -0,0 +1,206 @@
+//! Integration tests: a cw3 multisig acting as the arbiter, which approves through a
+//! passed proposal as its members cannot act on the escrow directly, and the helpers in
+//! `testing` with cw20 tokens.
+
+use cosmwasm_std::{
+    coins, to_binary, Addr, Binary, CosmosMsg, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdError,
+    StdResult, Uint128, WasmMsg,
+};
+use cw20::{Cw20Coin, Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw3::Vote;
+use cw3_fixed_multisig::msg::{ExecuteMsg as MultisigExecuteMsg, InstantiateMsg as MultisigInstantiateMsg, Voter};
+use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor};
+use cw_utils::{Duration, Threshold};
+
+use crate::error::ContractError;
+use crate::msg::{CreateMsg, DetailsResponse, ExecuteMsg, InstantiateMsg, QueryMsg};
+use crate::state::Status;
+use crate::testing::{escrow_contract, EscrowContract};
+
+fn multisig_contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(
+        cw3_fixed_multisig::contract::execute,
+        cw3_fixed_multisig::contract::instantiate,
+        cw3_fixed_multisig::contract::query,
+    ))
+}
+
+#[test]
+fn cw3_multisig_as_arbiter() {
+    let sender = Addr::unchecked("sender");
+    let mut app: App = AppBuilder::new().build(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &sender, coins(100, "tokens"))
+            .unwrap();
+    });
+
+    let multisig_id = app.store_code(multisig_contract());
+    let voters = ["alice", "bob", "carol"]
+        .iter()
+        .map(|addr| Voter {
+            addr: addr.to_string(),
+            weight: 1,
+        })
+        .collect();
+    let multisig = app
+        .instantiate_contract(
+            multisig_id,
+            Addr::unchecked("admin"),
+            &MultisigInstantiateMsg {
+                voters,
+                threshold: Threshold::AbsoluteCount { weight: 2 },
+                max_voting_period: Duration::Time(3600),
+            },
+            &[],
+            "multisig",
+            None,
+        )
+        .unwrap();
+
+    let escrow_id = app.store_code(escrow_contract());
+    let escrow = app
+        .instantiate_contract(
+            escrow_id,
+            Addr::unchecked("admin"),
+            &InstantiateMsg {
+                admin: None,
+                token_allowlist: None,
+                max_tokens: None,
+                fee_bps: None,
+                fee_collector: None,
+                appellate_arbiter: None,
+                appeal_window: None,
+                creation_fee: None,
+                strict_parties: None,
+                receipt_nft: None,
+            },
+            &[],
+            "escrow",
+            None,
+        )
+        .unwrap();
+
+    let create = ExecuteMsg::Create(CreateMsg {
+        id: "deal".to_string(),
+        arbiter: multisig.to_string().into(),
+        recipient: "recipient".to_string(),
+        ..CreateMsg::default()
+    });
+    app.execute_contract(sender, escrow.clone(), &create, &coins(100, "tokens"))
+        .unwrap();
+
+    // a member of the multisig is not the arbiter
+    let approve = ExecuteMsg::Approve {
+        id: "deal".to_string(),
+    };
+    let err = app
+        .execute_contract(Addr::unchecked("alice"), escrow.clone(), &approve, &[])
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::NotArbiter { sender } if sender == "alice"
+    ));
+
+    // the members propose and pass the approval instead
+    let proposal = MultisigExecuteMsg::Propose {
+        title: "Release deal".to_string(),
+        description: "Goods were delivered".to_string(),
+        msgs: vec![CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: escrow.to_string(),
+            msg: to_binary(&approve).unwrap(),
+            funds: vec![],
+        })],
+        latest: None,
+    };
+    app.execute_contract(Addr::unchecked("alice"), multisig.clone(), &proposal, &[])
+        .unwrap();
+    let vote = MultisigExecuteMsg::Vote {
+        proposal_id: 1,
+        vote: Vote::Yes,
+    };
+    app.execute_contract(Addr::unchecked("bob"), multisig.clone(), &vote, &[])
+        .unwrap();
+    let execute = MultisigExecuteMsg::Execute { proposal_id: 1 };
+    app.execute_contract(Addr::unchecked("carol"), multisig, &execute, &[])
+        .unwrap();
+
+    let balance = app.wrap().query_balance("recipient", "tokens").unwrap();
+    assert_eq!(balance.amount.u128(), 100);
+    let details: DetailsResponse = app
+        .wrap()
+        .query_wasm_smart(
+            escrow,
+            &QueryMsg::Details {
+                id: "deal".to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(details.status, Status::Approved);
+}
+
+// a cw20 token without balances: `Send` always delivers, everything else succeeds
+fn token_contract() -> Box<dyn Contract<Empty>> {
+    fn execute(_deps: DepsMut, _env: Env, info: MessageInfo, msg: Cw20ExecuteMsg) -> StdResult<Response> {
+        match msg {
+            Cw20ExecuteMsg::Send { contract, amount, msg } => {
+                let receive = Cw20ReceiveMsg {
+                    sender: info.sender.into_string(),
+                    amount,
+                    msg,
+                };
+                Ok(Response::new().add_message(receive.into_cosmos_msg(contract)?))
+            }
+            _ => Ok(Response::new()),
+        }
+    }
+    fn instantiate(_deps: DepsMut, _env: Env, _info: MessageInfo, _msg: Empty) -> StdResult<Response> {
+        Ok(Response::new())
+    }
+    fn query(_deps: Deps, _env: Env, _msg: Empty) -> StdResult<Binary> {
+        Err(StdError::generic_err("no queries"))
+    }
+    Box::new(ContractWrapper::new(execute, instantiate, query))
+}
+
+#[test]
+fn testing_helpers_with_cw20() {
+    let mut app = App::default();
+    let admin = Addr::unchecked("admin");
+    let buyer = Addr::unchecked("buyer");
+    let token_id = app.store_code(token_contract());
+    let token = app
+        .instantiate_contract(token_id, admin.clone(), &Empty {}, &[], "token", None)
+        .unwrap();
+    let msg = InstantiateMsg {
+        token_allowlist: Some(vec![token.to_string()]),
+        ..InstantiateMsg::default()
+    };
+    let escrow = EscrowContract::store_and_instantiate(&mut app, &admin, &msg).unwrap();
+
+    let create = CreateMsg {
+        id: "deal".to_string(),
+        arbiter: "arbiter".to_string().into(),
+        recipient: "recipient".to_string(),
+        ..CreateMsg::default()
+    };
+    escrow
+        .create_cw20(&mut app, &buyer, create, &token, Uint128::new(100))
+        .unwrap();
+    escrow
+        .top_up_cw20(&mut app, &buyer, "deal", &token, Uint128::new(50))
+        .unwrap();
+    let details = escrow.details(&app, "deal").unwrap();
+    assert_eq!(
+        details.cw20_balance,
+        vec![Cw20Coin {
+            address: token.to_string(),
+            amount: Uint128::new(150),
+        }]
+    );
+
+    let err = escrow.approve(&mut app, &buyer, "deal").unwrap_err();
+    assert!(matches!(err.downcast::<ContractError>().unwrap(), ContractError::NotArbiter { .. }));
+    escrow.approve(&mut app, &Addr::unchecked("arbiter"), "deal").unwrap();
+    assert_eq!(escrow.details(&app, "deal").unwrap().status, Status::Approved);
+}
@@ -1,80 +1,1692 @@
-use cosmwasm_std::{ Env, Storage, Coin, StdResult};
-use cosmwasm_storage::{bucket_read, bucket, prefixed};
+use cosmwasm_std::{ Addr, Binary, BlockInfo, Env, Empty, Storage, Coin, Order, StdError, StdResult, Timestamp, Uint128};
+use cw_storage_plus::{Bound, IndexList, IndexedMap, Index, Item, Map, MultiIndex, Prefix, Prefixer, PrimaryKey};
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use std::fmt;
+use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::conditions::Condition;
 use crate::error::ContractError;
-use cw20::{ Balance, Cw20CoinVerified };
+use crate::msg::ReceiveMsg;
+use cw20::{ Balance, Cw20CoinVerified, Expiration };
+use cw_utils::Duration;
 
-const PREFIX_ESCROW: &[u8] = b"liability";
+// escrows keep the namespace of the former `bucket`, so the primary keys stay readable
+const PREFIX_ESCROW: &str = "liability";
+const PREFIX_ESCROW_STATUS: &str = "liability__status";
+const PREFIX_ESCROW_EXPIRES_HEIGHT: &str = "liability__expires_height";
+const PREFIX_ESCROW_EXPIRES_TIME: &str = "liability__expires_time";
+// settled escrows are moved out of the way, so iterating active ones stays cheap
+const PREFIX_ARCHIVE: &str = "archive";
+const PREFIX_ARCHIVE_STATUS: &str = "archive__status";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    /// Maximum number of different native and cw20 tokens a single escrow can hold
+    pub max_tokens: u32,
+    /// Protocol fee in basis points, taken from the balance on approve. New escrows
+    /// snapshot this value, so changing it does not affect existing deals.
+    pub fee_bps: u16,
+    /// Receives the protocol fees
+    pub fee_collector: Addr,
+    /// Decides appeals against decisions on disputed escrows, those are final without it
+    #[serde(default)]
+    pub appellate_arbiter: Option<Addr>,
+    /// Seconds the losing party has to appeal
+    #[serde(default)]
+    pub appeal_window: u64,
+    /// Circuit breaker: no new escrows or top-ups, refunds still work
+    #[serde(default)]
+    pub paused: bool,
+    /// While paused, nothing is released to recipients either
+    #[serde(default)]
+    pub releases_paused: bool,
+    /// Paid to `Crank` callers by every escrow they settle, out of its balance
+    #[serde(default)]
+    pub keeper_reward: Vec<Coin>,
+    /// Seconds after which claimable payouts nobody withdrew can be swept by the owner,
+    /// never if not set
+    #[serde(default)]
+    pub unclaimed_timeout: Option<u64>,
+    /// Contracts relaying messages from other chains, like Polytone voices or ICA
+    /// controllers. Proxies they instantiated can act as `arbiter_proxy` of an escrow
+    #[serde(default)]
+    pub arbiter_relayers: Vec<Addr>,
+    /// Escrows have to expire within this from creation, and expiries can only be
+    /// extended up to this from now. No limit if not set
+    #[serde(default)]
+    pub max_duration: Option<Duration>,
+    /// How many active escrows a single source can have at once, no limit if not set.
+    /// Sources in `OPEN_LIMIT_EXEMPT` are not limited
+    #[serde(default)]
+    pub max_open_per_source: Option<u32>,
+    /// Taken from the native funds sent with `Create` and paid to the fee collector,
+    /// the rest funds the escrow
+    #[serde(default)]
+    pub creation_fee: Option<Coin>,
+    /// Rejects escrows where the arbiter, source and recipient are not all different
+    #[serde(default)]
+    pub strict_parties: bool,
+    /// cw721 contract the claim receipts of escrows are minted on, the escrow contract
+    /// has to be its minter
+    #[serde(default)]
+    pub receipt_nft: Option<Addr>,
+}
+
+pub const CONFIG: Item<Config> = Item::new("config");
+
+/// The owner can update the contract wide settings, like the token allowlist. It is
+/// handed over in two steps: the owner proposes, the new owner accepts (cw-ownable style)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct Ownership {
+    /// None once renounced
+    pub owner: Option<Addr>,
+    pub pending_owner: Option<Addr>,
+    /// The pending owner has to accept before this
+    pub pending_expiry: Option<Expiration>,
+}
+
+pub const OWNERSHIP: Item<Ownership> = Item::new("ownership");
+
+
+// the owner used to be stored as `admin` in the config
+#[derive(Serialize, Deserialize)]
+struct LegacyConfig {
+    admin: Option<Addr>,
+}
+
+/// Moves the admin of configs stored before `Ownership` existed, returns whether it did
+pub fn migrate_ownership(storage: &mut dyn Storage) -> StdResult<bool> {
+    if OWNERSHIP.may_load(storage)?.is_some() {
+        return Ok(false);
+    }
+    let legacy: Item<LegacyConfig> = Item::new("config");
+    let owner = match legacy.may_load(storage)?.and_then(|config| config.admin) {
+        Some(owner) => owner,
+        None => return Ok(false),
+    };
+    let ownership = Ownership {
+        owner: Some(owner),
+        ..Ownership::default()
+    };
+    OWNERSHIP.save(storage, &ownership)?;
+    Ok(true)
+}
+
+/// cw20 and cw721 contracts that are accepted for deposits at all. Each escrow can
+/// narrow the cw20 tokens down further with its own `cw20_whitelist`.
+pub const TOKEN_ALLOWLIST: Map<&Addr, Empty> = Map::new("token_allowlist");
+
+pub fn is_token_allowed(storage: &dyn Storage, token: &Addr) -> bool {
+    TOKEN_ALLOWLIST.has(storage, token)
+}
+
+pub fn allowed_tokens(storage: &dyn Storage) -> StdResult<Vec<Addr>> {
+    TOKEN_ALLOWLIST.keys(storage, None, None, Order::Ascending).collect()
+}
+
+/// A cw20 deposit waiting for its `TransferFrom` to complete, see `ExecuteMsg::DepositCw20`
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PendingDeposit {
+    pub token: Addr,
+    pub sender: Addr,
+    /// contract balance of the token before the transfer
+    pub balance_before: Uint128,
+    pub msg: ReceiveMsg,
+}
+
+pub const PENDING_DEPOSIT: Item<PendingDeposit> = Item::new("pending_deposit");
+
+/// Tokens moved by the payouts of the current execution, in reply id order, so a failing
+/// transfer can be reported precisely
+pub const PAYOUT_TOKENS: Item<Vec<String>> = Item::new("payout_tokens");
+
+/// What happens to the balance once the escrow expired without being settled
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OnExpiry {
+    #[default]
+    RefundToSource,
+    ReleaseToRecipient,
+}
+
+impl fmt::Display for OnExpiry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OnExpiry::RefundToSource => f.write_str("refund_to_source"),
+            OnExpiry::ReleaseToRecipient => f.write_str("release_to_recipient"),
+        }
+    }
+}
+
+/// Lifecycle of an escrow. Settled escrows are kept around (with an empty balance),
+/// so they remain queryable.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// Not active yet: the recipient has to accept
+    Pending,
+    /// Not active yet: accepted, but top-ups have not reached the funding target
+    Funding,
+    #[default]
+    Open,
+    Disputed,
+    /// An arbiter decided the dispute, the payout waits for the appeal window to close
+    Decided,
+    /// Waiting for the appellate arbiter
+    Appealed,
+    Approved,
+    Refunded,
+    /// Split between recipient and source by the arbiter
+    Resolved,
+    /// Settled according to `on_expiry` after the deadline passed
+    Expired,
+    /// Withdrawn by the source before the escrow became active
+    Cancelled,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Funding => "funding",
+            Status::Open => "open",
+            Status::Disputed => "disputed",
+            Status::Decided => "decided",
+            Status::Appealed => "appealed",
+            Status::Approved => "approved",
+            Status::Refunded => "refunded",
+            Status::Resolved => "resolved",
+            Status::Expired => "expired",
+            Status::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_settled(&self) -> bool {
+        !matches!(
+            self,
+            Status::Pending | Status::Funding | Status::Open | Status::Disputed | Status::Decided | Status::Appealed
+        )
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An account on another chain, reached with ICS-20 transfers over `chain_channel`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RemoteAddress {
+    /// The transfer channel on this chain, e.g. "channel-0"
+    pub chain_channel: String,
+    pub address: String,
+    /// Sent along with the transfers, e.g. an ibc-hooks `{"wasm": {..}}` call to deposit
+    /// the tokens into a vault on arrival
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
+/// Longest memo ibc-go relays
+pub const MAX_MEMO_LENGTH: usize = 32768;
+
+impl RemoteAddress {
+    pub fn validate(&self) -> Result<(), ContractError> {
+        let channel = self.chain_channel.strip_prefix("channel-").unwrap_or_default();
+        if channel.is_empty() || !channel.chars().all(|c| c.is_ascii_digit()) || self.address.is_empty() {
+            return Err(ContractError::InvalidRemoteAddress {});
+        }
+        if matches!(&self.memo, Some(memo) if memo.len() > MAX_MEMO_LENGTH) {
+            return Err(ContractError::MemoTooLong { max: MAX_MEMO_LENGTH });
+        }
+        Ok(())
+    }
+}
+
+/// How the balance of a vesting escrow becomes claimable by the recipient.
+/// Times are in seconds since epoch. With a `cliff`, nothing is claimable before it,
+/// at the cliff everything vested so far becomes claimable at once
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Schedule {
+    /// Nothing before `start_time`, then linearly until everything is vested at `end_time`
+    Linear {
+        start_time: u64,
+        end_time: u64,
+        #[serde(default)]
+        cliff: Option<u64>,
+    },
+    /// Points of (time, cumulative basis points vested), interpolated linearly in between.
+    /// Nothing is vested before the first point, the last point has to reach 10000
+    Piecewise {
+        points: Vec<(u64, u16)>,
+        #[serde(default)]
+        cliff: Option<u64>,
+    },
+    /// Subscription or retainer: the balance is paid in `periods` equal parts, one
+    /// becoming claimable every `interval` seconds from `start_time` on, the first at
+    /// `start_time`. Arbiters can cancel the periods not started yet with `StopSchedule`
+    Periodic {
+        start_time: u64,
+        interval: u64,
+        periods: u32,
+    },
+}
+
+impl Schedule {
+    pub fn validate(&self) -> Result<(), ContractError> {
+        let invalid = |reason: &str| Err(ContractError::InvalidSchedule { reason: reason.to_string() });
+        let end = match self {
+            Schedule::Linear { start_time, end_time, .. } => {
+                if start_time >= end_time {
+                    return invalid("end_time must be after start_time");
+                }
+                *end_time
+            }
+            Schedule::Piecewise { points, .. } => {
+                let last = match points.last() {
+                    Some(last) => last,
+                    None => return invalid("no points"),
+                };
+                if points.windows(2).any(|w| w[0].0 >= w[1].0 || w[0].1 > w[1].1) {
+                    return invalid("points must be ordered by time and never decrease");
+                }
+                if u128::from(last.1) != BPS_DENOMINATOR {
+                    return invalid("last point must vest 10000 bps");
+                }
+                last.0
+            }
+            Schedule::Periodic { interval, periods, .. } => {
+                if *interval == 0 || *periods == 0 {
+                    return invalid("interval and periods must not be zero");
+                }
+                return Ok(());
+            }
+        };
+        match self.cliff() {
+            Some(cliff) if cliff > end => invalid("cliff must not be after the end"),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn cliff(&self) -> Option<u64> {
+        match self {
+            Schedule::Linear { cliff, .. } | Schedule::Piecewise { cliff, .. } => *cliff,
+            Schedule::Periodic { .. } => None,
+        }
+    }
+
+    /// For a periodic schedule, how many periods started by `now`
+    pub fn periods_started(&self, now: u64) -> Option<u32> {
+        match self {
+            Schedule::Periodic { start_time, interval, periods } => Some(if now < *start_time {
+                0
+            } else {
+                ((now - start_time) / interval + 1).min(u64::from(*periods)) as u32
+            }),
+            _ => None,
+        }
+    }
+
+    /// The part of `total` that is vested at `now`
+    pub fn vested(&self, total: Uint128, now: u64) -> Uint128 {
+        if matches!(self.cliff(), Some(cliff) if now < cliff) {
+            return Uint128::zero();
+        }
+        match self {
+            Schedule::Linear { start_time, end_time, .. } => {
+                if now <= *start_time {
+                    Uint128::zero()
+                } else if now >= *end_time {
+                    total
+                } else {
+                    total.multiply_ratio(now - start_time, end_time - start_time)
+                }
+            }
+            Schedule::Piecewise { points, .. } => {
+                match points.iter().position(|(time, _)| *time > now) {
+                    Some(0) => Uint128::zero(),
+                    None => {
+                        let bps = points.last().map(|(_, bps)| *bps).unwrap_or_default();
+                        total.multiply_ratio(bps, BPS_DENOMINATOR)
+                    }
+                    Some(i) => {
+                        let (t0, b0) = points[i - 1];
+                        let (t1, b1) = points[i];
+                        let span = u128::from(t1 - t0);
+                        let bps = u128::from(b0) * span + u128::from(b1 - b0) * u128::from(now - t0);
+                        total.multiply_ratio(bps, BPS_DENOMINATOR * span)
+                    }
+                }
+            }
+            Schedule::Periodic { periods, .. } => {
+                let started = self.periods_started(now).unwrap_or_default();
+                // stopped before the first period, nothing is left to vest
+                if started >= *periods {
+                    total
+                } else {
+                    total.multiply_ratio(started, *periods)
+                }
+            }
+        }
+    }
+}
+
+/// Terms of a two-sided swap: the source deposits `offered` on create, the recipient
+/// `wanted` with top-ups. Each side is tracked as their contribution
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Swap {
+    pub offered: GenericBalance,
+    pub wanted: GenericBalance,
+}
+
+/// Raised by the source or the recipient with `RaiseDispute`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Dispute {
+    pub raised_by: Addr,
+    pub reason: String,
+    pub raised_at: Timestamp,
+    pub raised_at_height: u64,
+    /// When an arbiter settled the escrow
+    #[serde(default)]
+    pub resolved_at: Option<Timestamp>,
+}
+
+/// Arbiter decision on a disputed escrow, held until the appeal window closed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Decision {
+    pub arbiter: Addr,
+    /// What the escrow settles as: approved, refunded or resolved
+    pub outcome: Status,
+    /// Share of the balance going to the recipient, the rest is refunded
+    pub recipient_bps: u16,
+    pub decided_at: Timestamp,
+    pub appeal_until: Timestamp,
+    #[serde(default)]
+    pub appealed_by: Option<Addr>,
+}
+
+impl Decision {
+    /// Whoever does not get the whole balance can appeal
+    pub fn may_appeal(&self, escrow: &Escrow, sender: &Addr) -> bool {
+        let bps = u128::from(self.recipient_bps);
+        (*sender == escrow.source && bps > 0) || (*sender == escrow.recipient && bps < BPS_DENOMINATOR)
+    }
+}
+
+/// Profile of an arbiter in the registry, so creators can find one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ArbiterProfile {
+    /// What the arbiter asks for, in basis points. Escrows still set their own `arbiter_fee_bps`
+    pub fee_bps: u16,
+    /// Kinds of deals handled, e.g. "nft" or "freelance"
+    pub categories: Vec<String>,
+    /// Native tokens locked while registered
+    pub bond: GenericBalance,
+}
+
+pub const ARBITER_REGISTRY: Map<&Addr, ArbiterProfile> = Map::new("arbiter_registry");
+
+/// Registered arbiters, optionally only those handling `category`
+pub fn registered_arbiters(
+    storage: &dyn Storage,
+    category: Option<&str>,
+    start_after: Option<&Addr>,
+    limit: usize,
+) -> StdResult<Vec<(Addr, ArbiterProfile)>> {
+    let start = start_after.map(|addr| Bound::exclusive(addr.as_str()));
+
+    ARBITER_REGISTRY
+        .range(storage, start, None, Order::Ascending)
+        .filter(|elem| match (elem, category) {
+            (Ok((_, profile)), Some(category)) => profile.categories.iter().any(|c| c == category),
+            _ => true,
+        })
+        .take(limit)
+        .collect()
+}
+
+/// Track record of an arbiter, so frontends can rank them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct ArbiterStats {
+    /// Escrows settled (or, if disputed, decided) by this arbiter
+    pub escrows_handled: u64,
+    pub disputes_resolved: u64,
+    /// Decisions the appellate arbiter changed
+    pub decisions_overturned: u64,
+    /// Sum of the seconds from raising a dispute to the decision
+    pub total_resolution_time: u64,
+}
+
+pub const ARBITER_STATS: Map<&Addr, ArbiterStats> = Map::new("arbiter_stats");
+
+/// Preset terms escrows are created with by `CreateFromTemplate`, e.g. the standard deal
+/// of a marketplace
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Template {
+    /// Registered the template, can replace or remove it (as can the contract owner)
+    pub owner: Addr,
+    pub arbiters: Vec<Addr>,
+    pub threshold: u32,
+    pub arbiter_fee_bps: u16,
+    /// Escrows expire this long after they were created, never if not set
+    pub duration: Option<Duration>,
+    /// Native denoms, cw20 and cw721 contracts the escrows may hold, any if empty
+    pub tokens: Vec<String>,
+}
+
+pub const TEMPLATES: Map<&str, Template> = Map::new("templates");
+
+/// Registered templates, optionally only those of `owner`
+pub fn registered_templates(
+    storage: &dyn Storage,
+    owner: Option<&Addr>,
+    start_after: Option<&str>,
+    limit: usize,
+) -> StdResult<Vec<(String, Template)>> {
+    let start = start_after.map(Bound::exclusive);
+
+    TEMPLATES
+        .range(storage, start, None, Order::Ascending)
+        .filter(|elem| match (elem, owner) {
+            (Ok((_, template)), Some(owner)) => template.owner == *owner,
+            _ => true,
+        })
+        .take(limit)
+        .collect()
+}
+
+/// Counts a decision of `arbiter`, `resolution_time` is set for disputes
+pub fn record_decision(storage: &mut dyn Storage, arbiter: &Addr, resolution_time: Option<u64>) -> StdResult<()> {
+    ARBITER_STATS.update(storage, arbiter, |stats| -> StdResult<_> {
+        let mut stats = stats.unwrap_or_default();
+        stats.escrows_handled += 1;
+        if let Some(time) = resolution_time {
+            stats.disputes_resolved += 1;
+            stats.total_resolution_time += time;
+        }
+        Ok(stats)
+    })?;
+    Ok(())
+}
+
+pub fn record_overturned(storage: &mut dyn Storage, arbiter: &Addr) -> StdResult<()> {
+    ARBITER_STATS.update(storage, arbiter, |stats| -> StdResult<_> {
+        let mut stats = stats.unwrap_or_default();
+        stats.decisions_overturned += 1;
+        Ok(stats)
+    })?;
+    Ok(())
+}
+
+/// What an arbiter votes for on a threshold escrow
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Release,
+    Refund,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Release => f.write_str("release"),
+            Verdict::Refund => f.write_str("refund"),
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Escrow {
-    pub arbiter: String,
-    pub recipient: String,
-    pub source: String,
-    pub end_height: Option<u64>,
-    pub end_time: Option<u64>,
+    /// Any one of them may approve or refund. Legacy escrows stored a single `arbiter`.
+    #[serde(alias = "arbiter", deserialize_with = "one_or_many")]
+    pub arbiters: Vec<Addr>,
+    /// Number of matching arbiter votes needed to release or refund. With 1, any arbiter
+    /// can decide alone
+    #[serde(default = "default_threshold")]
+    pub threshold: u32,
+    pub recipient: Addr,
+    pub source: Addr,
+    /// Legacy escrows stored `end_height` and `end_time` instead, until they are migrated
+    #[serde(default)]
+    pub expires: Expiration,
+    /// Nothing can be released before this point, the escrow locks its funds from creation
+    #[serde(default)]
+    pub start: Option<Expiration>,
+    /// After expiry, only arbiters may act for this long before anyone can settle
+    #[serde(default)]
+    pub grace_period: Option<Duration>,
+    /// Dead man's switch: each `Ping` by the source moves `expires` this far out
+    #[serde(default)]
+    pub ping_interval: Option<Duration>,
+    /// If set, cw20 tokens are released with `Send` carrying this message instead of `Transfer`
+    #[serde(default)]
+    pub release_hook: Option<Binary>,
+    /// The recipient is a contract taking native payouts with `PayoutCallbackMsg`
+    #[serde(default)]
+    pub payout_callback: bool,
+    /// Released tokens are held in `CLAIMABLE` for the recipient to withdraw
+    #[serde(default)]
+    pub pull_payouts: bool,
+    /// Refunded tokens are held in `CLAIMABLE` for each funder to withdraw
+    #[serde(default)]
+    pub pull_refunds: bool,
+    /// Block the escrow was created in, not known for escrows created before it was recorded
+    #[serde(default)]
+    pub created_at_height: Option<u64>,
+    #[serde(default)]
+    pub created_at_time: Option<Timestamp>,
+    /// Native tokens released to the recipient go to this address on another chain
+    #[serde(default)]
+    pub remote_recipient: Option<RemoteAddress>,
+    /// Native tokens refunded to the source go to this address on another chain
+    #[serde(default)]
+    pub remote_refund_address: Option<RemoteAddress>,
+    /// Acts as an arbiter on behalf of an arbiter on another chain
+    #[serde(default)]
+    pub arbiter_proxy: Option<Addr>,
+    /// The arbiters, source and recipient have to stay different addresses
+    #[serde(default)]
+    pub strict_parties: bool,
+    /// cw721 contract holding the claim receipt of the escrow, the token id is the escrow
+    /// id. Payouts go to whoever owns the receipt rather than to the recipient
+    #[serde(default)]
+    pub receipt: Option<Addr>,
+    /// Payouts are shared between these addresses by basis points, everything goes to
+    /// the recipient if empty
+    #[serde(default)]
+    pub splits: Vec<(Addr, u16)>,
+    /// Delivered both ways with `CompleteSwap` only, never approved
+    #[serde(default)]
+    pub swap: Option<Swap>,
+    /// Stored separately in `ESCROW_BALANCES`, filled in by `escrows_read`. Legacy
+    /// entries still hold it inline until they are migrated
+    #[serde(default, skip_serializing)]
     pub balance: GenericBalance,
-    pub cw20_whitelist: Vec<String>
+    pub cw20_whitelist: Vec<String>,
+    /// Protocol fee in basis points at creation time, deducted on approve
+    #[serde(default)]
+    pub fee_bps: u16,
+    /// Arbiter compensation in basis points, paid when the arbiter approves or refunds
+    #[serde(default)]
+    pub arbiter_fee_bps: u16,
+    /// If set, refunds go here instead of to the source
+    #[serde(default)]
+    pub refund_address: Option<Addr>,
+    #[serde(default)]
+    pub on_expiry: OnExpiry,
+    #[serde(default)]
+    pub status: Status,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub metadata: Option<Binary>,
+    /// Set at creation and never changed afterwards
+    #[serde(default)]
+    pub terms_hash: Option<String>,
+    /// Consent of the parties to release without the arbiter, see `MutualApprove`
+    #[serde(default)]
+    pub source_approved: bool,
+    #[serde(default)]
+    pub recipient_approved: bool,
+    /// False until the recipient accepted, for escrows created with `requires_acceptance`
+    #[serde(default = "default_accepted")]
+    pub accepted: bool,
+    /// Tokens the escrow has to hold before it becomes active
+    #[serde(default)]
+    pub funding_target: Option<GenericBalance>,
+    /// Whether arbiters may change the recipient as well
+    #[serde(default)]
+    pub arbiter_can_set_recipient: bool,
+    /// Vesting escrows release to the recipient over time via `Claim`
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    /// What the recipient claimed so far, per token
+    #[serde(default)]
+    pub claimed: GenericBalance,
+    /// Restricts top-ups to these addresses and the source
+    #[serde(default)]
+    pub allowed_funders: Option<Vec<Addr>>,
+    /// Crowdfund campaign, refunded by each contributor claiming if the goal was missed
+    #[serde(default)]
+    pub crowdfund: bool,
+    /// Once all are met, the recipient can release the escrow with `Fulfill`
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    /// Set once a party raised a dispute, kept after settlement
+    #[serde(default)]
+    pub dispute: Option<Dispute>,
+    /// Decision on the dispute, while it can still be appealed
+    #[serde(default)]
+    pub decision: Option<Decision>,
+}
+
+fn default_threshold() -> u32 {
+    1
+}
+
+fn default_accepted() -> bool {
+    true
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Addr>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Addr),
+        Many(Vec<Addr>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(addr) => vec![addr],
+        OneOrMany::Many(addrs) => addrs,
+    })
 }
 
 impl Escrow {
+    pub fn is_arbiter(&self, addr: &Addr) -> bool {
+        self.arbiters.contains(addr) || self.arbiter_proxy.as_ref() == Some(addr)
+    }
+
+    /// In strict mode, no party may hold two roles: an escrow between an address and
+    /// itself, or decided by one of its own parties, is usually a client bug
+    pub fn check_parties(&self) -> Result<(), ContractError> {
+        if !self.strict_parties {
+            return Ok(());
+        }
+        let overlap = |address: &Addr, roles: &str| ContractError::OverlappingParties {
+            address: address.to_string(),
+            roles: roles.to_string(),
+        };
+        if self.source == self.recipient {
+            return Err(overlap(&self.source, "source and recipient"));
+        }
+        if let Some(arbiter) = self.arbiters.iter().find(|a| **a == self.source) {
+            return Err(overlap(arbiter, "arbiter and source"));
+        }
+        if let Some(arbiter) = self.arbiters.iter().find(|a| **a == self.recipient) {
+            return Err(overlap(arbiter, "arbiter and recipient"));
+        }
+        Ok(())
+    }
+
+    /// Where refunded tokens are sent to
+    pub fn refund_to(&self) -> &Addr {
+        self.refund_address.as_ref().unwrap_or(&self.source)
+    }
+
     pub fn is_expired(&self, env: &Env) -> bool {
-        if let Some(end_height) = self.end_height {
-            if env.block.height > end_height {
-                return true;
-            }
+        self.expires.is_expired(&env.block)
+    }
+
+    /// When the grace period after expiry ends, validated to be in the same unit on create
+    pub fn grace_ends(&self) -> Expiration {
+        match self.grace_period {
+            Some(grace_period) => (self.expires + grace_period).unwrap_or(self.expires),
+            None => self.expires,
         }
+    }
 
-        if let Some(end_time) = self.end_time {
-            if env.block.time.nanos() > end_time * 1000 {
-                return true;
-            }
+    pub fn in_grace_period(&self, env: &Env) -> bool {
+        self.is_expired(env) && !self.grace_ends().is_expired(&env.block)
+    }
+
+    pub fn has_started(&self, env: &Env) -> bool {
+        self.start.map_or(true, |start| start.is_expired(&env.block))
+    }
+
+    pub fn is_funded(&self) -> bool {
+        match &self.funding_target {
+            Some(target) => self.balance.covers(target),
+            None => true,
         }
+    }
+
+    /// Whether the escrow is waiting for acceptance or funding
+    pub fn is_inactive(&self) -> bool {
+        matches!(self.status, Status::Pending | Status::Funding)
+    }
+
+    /// Once accepted, an inactive escrow waits in `Funding` until it is fully funded
+    /// and then becomes open. Returns whether it became open.
+    pub fn activate_if_ready(&mut self) -> bool {
+        if !self.is_inactive() || !self.accepted {
+            return false;
+        }
+        if self.is_funded() {
+            self.status = Status::Open;
+            return true;
+        }
+        self.status = Status::Funding;
         false
     }
+
+    /// Vested tokens the recipient has not claimed yet. Vesting applies to everything
+    /// ever deposited, i.e. the current balance plus what was claimed already
+    pub fn claimable(&self, now: u64) -> GenericBalance {
+        let schedule = match &self.schedule {
+            Some(schedule) => schedule,
+            None => return GenericBalance::default(),
+        };
+        let claimable = |held: Uint128, claimed: Uint128| {
+            schedule.vested(held + claimed, now).saturating_sub(claimed).min(held)
+        };
+
+        let mut claim = GenericBalance::default();
+        for coin in self.balance.native.iter() {
+            let claimed = self.claimed.native.iter().find(|c| c.denom == coin.denom);
+            let amount = claimable(coin.amount, claimed.map(|c| c.amount).unwrap_or_default());
+            if !amount.is_zero() {
+                claim.native.push(Coin {
+                    denom: coin.denom.clone(),
+                    amount,
+                });
+            }
+        }
+        for token in self.balance.cw20.iter() {
+            let claimed = self.claimed.cw20.iter().find(|c| c.address == token.address);
+            let amount = claimable(token.amount, claimed.map(|c| c.amount).unwrap_or_default());
+            if !amount.is_zero() {
+                claim.cw20.push(Cw20CoinVerified {
+                    address: token.address.clone(),
+                    amount,
+                });
+            }
+        }
+        // NFTs cannot vest partially, they are claimable once the schedule completed
+        if schedule.vested(Uint128::new(1), now) == Uint128::new(1) {
+            claim.nfts = self.balance.nfts.clone();
+        }
+        claim
+    }
+
+    /// Marks the escrow as settled. All tokens have been paid out at this point.
+    pub fn settle(&mut self, status: Status) {
+        self.status = status;
+        self.balance = GenericBalance::default();
+    }
+
+    /// Records when an arbiter settled a disputed escrow
+    pub fn close_dispute(&mut self, env: &Env) {
+        if let Some(dispute) = self.dispute.as_mut() {
+            dispute.resolved_at = Some(env.block.time);
+        }
+    }
+
+    /// A crowdfund that was still short of its goal at the deadline
+    pub fn is_failed_crowdfund(&self, env: &Env) -> bool {
+        self.crowdfund && self.status == Status::Funding && self.is_expired(env)
+    }
+
+    /// Whether `sender` may add funds to the escrow
+    pub fn may_fund(&self, sender: &Addr) -> bool {
+        match &self.allowed_funders {
+            Some(funders) => *sender == self.source || funders.contains(sender),
+            None => true,
+        }
+    }
+
+    /// The hash of the first `Preimage` condition
+    pub fn hash_lock(&self) -> Option<&String> {
+        self.conditions.iter().find_map(|condition| match condition {
+            Condition::Preimage(hash) => Some(hash),
+            _ => None,
+        })
+    }
+
+    /// Arbiters can still release late deliveries during the grace period
+    pub fn is_release_blocked(&self, env: &Env) -> bool {
+        self.on_expiry == OnExpiry::RefundToSource && self.grace_ends().is_expired(&env.block)
+    }
+}
+
+pub struct EscrowIndexes<'a> {
+    pub status: MultiIndex<'a, String, Escrow, String>,
+    /// Deadlines, so expired escrows are found without going through all of them.
+    /// Escrows without that kind of deadline are indexed at u64::MAX
+    pub expires_height: MultiIndex<'a, u64, Escrow, String>,
+    /// in nanoseconds
+    pub expires_time: MultiIndex<'a, u64, Escrow, String>,
+}
+
+impl<'a> IndexList<Escrow> for EscrowIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Escrow>> + '_> {
+        let v: Vec<&dyn Index<Escrow>> = vec![&self.status, &self.expires_height, &self.expires_time];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Settled escrows are only looked up by status
+pub struct ArchiveIndexes<'a> {
+    pub status: MultiIndex<'a, String, Escrow, String>,
+}
+
+impl<'a> IndexList<Escrow> for ArchiveIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Escrow>> + '_> {
+        let v: Vec<&dyn Index<Escrow>> = vec![&self.status];
+        Box::new(v.into_iter())
+    }
+}
+
+fn status_index<'a>(pk_namespace: &'a str, status: &'a str) -> MultiIndex<'a, String, Escrow, String> {
+    MultiIndex::new(|e: &Escrow| e.status.to_string(), pk_namespace, status)
+}
+
+/// (arbiter, id) of active escrows. A `MultiIndex` holds one key per escrow,
+/// which does not work with several arbiters.
+const ARBITER_ESCROWS: Map<(&Addr, &str), Empty> = Map::new("arbiter_escrows");
+
+/// (arbiter, status, id) of settled escrows. Their status never changes again, so those
+/// of an arbiter in a settled status are found without going through the archive
+pub(crate) const ARCHIVED_BY_ARBITER: Map<(&Addr, &str, &str), Empty> = Map::new("archived_by_arbiter");
+
+/// Whether there are settled escrows but no `ARCHIVED_BY_ARBITER` entries, for archives
+/// from before it was kept
+pub fn archive_unindexed(storage: &dyn Storage) -> bool {
+    archive().keys_raw(storage, None, None, Order::Ascending).next().is_some()
+        && ARCHIVED_BY_ARBITER.keys_raw(storage, None, None, Order::Ascending).next().is_none()
+}
+
+fn index_archived(storage: &mut dyn Storage, escrow: &Escrow, id: &str) -> StdResult<()> {
+    for arbiter in escrow.arbiters.iter() {
+        ARCHIVED_BY_ARBITER.save(storage, (arbiter, escrow.status.as_str(), id), &Empty {})?;
+    }
+    Ok(())
 }
 
-pub fn escrows_read(storage: &dyn Storage, id: &String) -> StdResult<Escrow> {
-    bucket_read(storage, PREFIX_ESCROW).load(id.as_bytes())
+fn index_arbiters(storage: &mut dyn Storage, escrow: &Escrow, id: &str) -> StdResult<()> {
+    for arbiter in escrow.arbiters.iter() {
+        ARBITER_ESCROWS.save(storage, (arbiter, id), &Empty {})?;
+    }
+    Ok(())
+}
+
+/// Votes of threshold escrows by (id, arbiter). Cleared once the escrow is archived.
+pub const VOTES: Map<(&str, &Addr), Verdict> = Map::new("votes");
+
+/// What each funder deposited into an escrow by (id, funder), the source included.
+/// Refunds are split in proportion to it. Kept after the escrow is archived
+pub const CONTRIBUTIONS: Map<(&str, &Addr), GenericBalance> = Map::new("contributions");
+
+pub fn add_contribution(
+    storage: &mut dyn Storage,
+    id: &str,
+    funder: &Addr,
+    amount: &GenericBalance,
+) -> StdResult<()> {
+    let mut contribution = CONTRIBUTIONS.may_load(storage, (id, funder))?.unwrap_or_default();
+    contribution.add_all(amount)?;
+    CONTRIBUTIONS.save(storage, (id, funder), &contribution)
+}
+
+/// Everything deposited into an escrow after its creation. Kept apart from the escrow, so
+/// top-ups only touch the balances of the deposited tokens
+pub const TOPPED_UP: Map<&str, GenericBalance> = Map::new("topped_up");
+
+pub fn add_topped_up(storage: &mut dyn Storage, id: &str, amount: &GenericBalance) -> StdResult<()> {
+    let mut topped_up = TOPPED_UP.may_load(storage, id)?.unwrap_or_default();
+    topped_up.add_all(amount)?;
+    TOPPED_UP.save(storage, id, &topped_up)
+}
+
+/// Payouts of escrows with `pull_payouts` or `pull_refunds` by (id, owner), until the
+/// owner withdraws them with `Claim` or `ClaimRefund`
+pub const CLAIMABLE: Map<(&str, &Addr), GenericBalance> = Map::new("claimable");
+/// When the claimable balance of (id, owner) was first credited, the unclaimed timeout
+/// counts from there
+pub const CLAIMABLE_SINCE: Map<(&str, &Addr), Timestamp> = Map::new("claimable_since");
+
+pub fn add_claimable(
+    storage: &mut dyn Storage,
+    id: &str,
+    owner: &Addr,
+    amount: &GenericBalance,
+    now: Timestamp,
+) -> StdResult<()> {
+    if amount.is_empty() {
+        return Ok(());
+    }
+    let mut claimable = CLAIMABLE.may_load(storage, (id, owner))?.unwrap_or_default();
+    claimable.add_all(amount)?;
+    if !CLAIMABLE_SINCE.has(storage, (id, owner)) {
+        CLAIMABLE_SINCE.save(storage, (id, owner), &now)?;
+    }
+    CLAIMABLE.save(storage, (id, owner), &claimable)
+}
+
+pub fn remove_claimable(storage: &mut dyn Storage, id: &str, owner: &Addr) {
+    CLAIMABLE.remove(storage, (id, owner));
+    CLAIMABLE_SINCE.remove(storage, (id, owner));
+}
+
+/// Everything waiting to be withdrawn from the escrow, in owner order
+pub fn escrow_claimable(storage: &dyn Storage, id: &str) -> StdResult<Vec<(Addr, GenericBalance)>> {
+    CLAIMABLE
+        .prefix(id)
+        .range(storage, None, None, Order::Ascending)
+        .collect()
+}
+
+/// All contributions to the escrow, in funder order
+pub fn escrow_contributions(storage: &dyn Storage, id: &str) -> StdResult<Vec<(Addr, GenericBalance)>> {
+    CONTRIBUTIONS
+        .prefix(id)
+        .range(storage, None, None, Order::Ascending)
+        .collect()
+}
+
+/// All votes cast on the escrow, in arbiter order
+pub fn escrow_votes(storage: &dyn Storage, id: &str) -> StdResult<Vec<(Addr, Verdict)>> {
+    VOTES
+        .prefix(id)
+        .range(storage, None, None, Order::Ascending)
+        .collect()
+}
+
+/// Local arbiter address a remote arbiter acts as, by the channel its packets come in on
+pub const ARBITER_CHANNELS: Map<&str, Addr> = Map::new("arbiter_channels");
+
+/// Contracts notified of changes to an escrow by (id, subscriber)
+pub const SUBSCRIBERS: Map<(&str, &Addr), Empty> = Map::new("subscribers");
+
+pub fn escrow_subscribers(storage: &dyn Storage, id: &str) -> StdResult<Vec<Addr>> {
+    SUBSCRIBERS
+        .prefix(id)
+        .keys(storage, None, None, Order::Ascending)
+        .collect()
+}
+
+/// An entry in the history of an escrow
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Action {
+    /// create, accept, top_up, set_recipient, transfer_source, extend_expiry, dispute,
+    /// approve, claim, stop_schedule, swap, withdraw, refund, claim_refund,
+    /// withdraw_claimable, cancel, resolve or expire
+    pub action: String,
+    /// Not set for payouts no one in particular triggered, like expiry or fulfilled conditions
+    pub actor: Option<Addr>,
+    pub amount: GenericBalance,
+    pub height: u64,
+    pub time: Timestamp,
+}
+
+/// Append-only log of what happened to an escrow by (id, sequence number), kept after
+/// the escrow is archived
+pub const HISTORY: Map<(&str, u64), Action> = Map::new("history");
+
+pub fn record_action(
+    storage: &mut dyn Storage,
+    env: &Env,
+    id: &str,
+    action: &str,
+    actor: Option<&Addr>,
+    amount: &GenericBalance,
+) -> StdResult<()> {
+    let last = HISTORY.prefix(id).keys(storage, None, None, Order::Descending).next().transpose()?;
+    let action = Action {
+        action: action.to_string(),
+        actor: actor.cloned(),
+        amount: amount.clone(),
+        height: env.block.height,
+        time: env.block.time,
+    };
+    HISTORY.save(storage, (id, last.map_or(0, |seq| seq + 1)), &action)
+}
+
+/// The history of an escrow, oldest first
+pub fn escrow_history(
+    storage: &dyn Storage,
+    id: &str,
+    start_after: Option<u64>,
+    limit: usize,
+) -> StdResult<Vec<(u64, Action)>> {
+    HISTORY
+        .prefix(id)
+        .range(storage, start_after.map(Bound::exclusive_int), None, Order::Ascending)
+        .take(limit)
+        .collect()
+}
+
+/// Escrows that are not settled yet
+pub fn escrows<'a>() -> IndexedMap<'a, &'a str, Escrow, EscrowIndexes<'a>> {
+    let indexes = EscrowIndexes {
+        status: status_index(PREFIX_ESCROW, PREFIX_ESCROW_STATUS),
+        expires_height: MultiIndex::new(
+            |e: &Escrow| Deadline::Height.key(&e.expires),
+            PREFIX_ESCROW,
+            PREFIX_ESCROW_EXPIRES_HEIGHT,
+        ),
+        expires_time: MultiIndex::new(
+            |e: &Escrow| Deadline::Time.key(&e.expires),
+            PREFIX_ESCROW,
+            PREFIX_ESCROW_EXPIRES_TIME,
+        ),
+    };
+    IndexedMap::new(PREFIX_ESCROW, indexes)
+}
+
+/// Settled escrows, kept for auditing
+pub fn archive<'a>() -> IndexedMap<'a, &'a str, Escrow, ArchiveIndexes<'a>> {
+    let indexes = ArchiveIndexes {
+        status: status_index(PREFIX_ARCHIVE, PREFIX_ARCHIVE_STATUS),
+    };
+    IndexedMap::new(PREFIX_ARCHIVE, indexes)
+}
+
+/// Balances of active escrows by (id, token), so single tokens can be read and updated
+/// without going through the whole balance. Tokens are keyed as `native:<denom>`,
+/// `cw20:<address>` and `nft:<contract>:<token id>`
+pub(crate) const ESCROW_BALANCES: Map<(&str, &str), Uint128> = Map::new("escrow_balances");
+
+fn balance_entries(balance: &GenericBalance) -> Vec<(String, Uint128)> {
+    let native = balance.native.iter().map(|c| (format!("native:{}", c.denom), c.amount));
+    let cw20 = balance.cw20.iter().map(|c| (format!("cw20:{}", c.address), c.amount));
+    let nfts = balance
+        .nfts
+        .iter()
+        .map(|(contract, id)| (format!("nft:{}:{}", contract, id), Uint128::new(1)));
+    native.chain(cw20).chain(nfts).collect()
+}
+
+// adds a stored entry back to a balance
+fn push_balance_entry(balance: &mut GenericBalance, key: &str, amount: Uint128) -> StdResult<()> {
+    let invalid = || StdError::generic_err(format!("Invalid balance key {}", key));
+    match key.split_once(':').ok_or_else(invalid)? {
+        ("native", denom) => balance.native.push(Coin {
+            denom: denom.to_string(),
+            amount,
+        }),
+        ("cw20", address) => balance.cw20.push(Cw20CoinVerified {
+            address: Addr::unchecked(address),
+            amount,
+        }),
+        ("nft", nft) => {
+            let (contract, token_id) = nft.split_once(':').ok_or_else(invalid)?;
+            balance.nfts.push((Addr::unchecked(contract), token_id.to_string()))
+        }
+        _ => return Err(invalid()),
+    }
+    Ok(())
+}
+
+/// Sum of the balances of all active escrows by token, keyed like `ESCROW_BALANCES`.
+/// NFTs are left out
+const TOTAL_LOCKED: Map<&str, Uint128> = Map::new("total_locked");
+
+/// Active escrows by (token, id), the reverse of `ESCROW_BALANCES` without NFTs
+const TOKEN_ESCROWS: Map<(&str, &str), Empty> = Map::new("token_escrows");
+
+// counts `amount` of token `key` deposited into escrow `id`
+fn lock(storage: &mut dyn Storage, id: &str, key: &str, amount: Uint128) -> StdResult<()> {
+    if key.starts_with("nft:") {
+        return Ok(());
+    }
+    TOKEN_ESCROWS.save(storage, (key, id), &Empty {})?;
+    let total = TOTAL_LOCKED.may_load(storage, key)?.unwrap_or_default();
+    TOTAL_LOCKED.save(storage, key, &total.checked_add(amount)?)
+}
+
+// counts the whole `amount` of token `key` held by escrow `id` leaving it
+fn unlock(storage: &mut dyn Storage, id: &str, key: &str, amount: Uint128) -> StdResult<()> {
+    if key.starts_with("nft:") {
+        return Ok(());
+    }
+    TOKEN_ESCROWS.remove(storage, (key, id));
+    let total = TOTAL_LOCKED.may_load(storage, key)?.unwrap_or_default().checked_sub(amount)?;
+    if total.is_zero() {
+        TOTAL_LOCKED.remove(storage, key);
+    } else {
+        TOTAL_LOCKED.save(storage, key, &total)?;
+    }
+    Ok(())
+}
+
+/// What all active escrows hold together
+pub fn total_locked(storage: &dyn Storage) -> StdResult<GenericBalance> {
+    let mut balance = GenericBalance::default();
+    for entry in TOTAL_LOCKED.range(storage, None, None, Order::Ascending) {
+        let (key, amount) = entry?;
+        push_balance_entry(&mut balance, &key, amount)?;
+    }
+    Ok(balance)
+}
+
+/// Everything the contract owes: active escrows, payouts waiting to be claimed, arbiter
+/// bonds. Anything it holds on top of that was sent to it directly
+pub fn tracked_funds(storage: &dyn Storage) -> StdResult<GenericBalance> {
+    let mut tracked = total_locked(storage)?;
+    for entry in CLAIMABLE.range(storage, None, None, Order::Ascending) {
+        tracked.add_all(&entry?.1)?;
+    }
+    for entry in ARBITER_REGISTRY.range(storage, None, None, Order::Ascending) {
+        tracked.add_all(&entry?.1.bond)?;
+    }
+    Ok(tracked)
+}
+
+/// Running counts of escrows, for dashboards
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct EscrowCounts {
+    /// Not settled yet, including pending and disputed escrows
+    pub active: u64,
+    pub approved: u64,
+    pub refunded: u64,
+    pub resolved: u64,
+    pub expired: u64,
+    pub cancelled: u64,
+}
+
+impl EscrowCounts {
+    fn settled(&mut self, status: Status) -> Option<&mut u64> {
+        match status {
+            Status::Approved => Some(&mut self.approved),
+            Status::Refunded => Some(&mut self.refunded),
+            Status::Resolved => Some(&mut self.resolved),
+            Status::Expired => Some(&mut self.expired),
+            Status::Cancelled => Some(&mut self.cancelled),
+            _ => None,
+        }
+    }
+}
+
+pub const ESCROW_COUNTS: Item<EscrowCounts> = Item::new("escrow_counts");
+
+/// Active escrows by source, for `max_open_per_source`. Escrows from before it was
+/// kept are only counted if a migration rebuilt the stats
+pub const OPEN_BY_SOURCE: Map<&Addr, u32> = Map::new("open_by_source");
+
+/// Partners (e.g. marketplaces) paying neither the creation fee nor the protocol fee on
+/// escrows they create or receive
+pub const FEE_EXEMPT: Map<&Addr, Empty> = Map::new("fee_exempt");
+
+/// Trusted integrators not limited by `max_open_per_source`
+pub const OPEN_LIMIT_EXEMPT: Map<&Addr, Empty> = Map::new("open_limit_exempt");
+
+pub fn open_escrows_of(storage: &dyn Storage, source: &Addr) -> StdResult<u32> {
+    Ok(OPEN_BY_SOURCE.may_load(storage, source)?.unwrap_or_default())
+}
+
+/// Makes `new_source` the source of an unsettled escrow, taking over what the old
+/// source contributed and their place in the open escrow counts
+pub fn transfer_source(storage: &mut dyn Storage, id: &str, escrow: &mut Escrow, new_source: Addr) -> StdResult<()> {
+    if let Some(contribution) = CONTRIBUTIONS.may_load(storage, (id, &escrow.source))? {
+        CONTRIBUTIONS.remove(storage, (id, &escrow.source));
+        add_contribution(storage, id, &new_source, &contribution)?;
+    }
+    count_open(storage, &escrow.source, false)?;
+    count_open(storage, &new_source, true)?;
+    escrow.source = new_source;
+    Ok(())
+}
+
+fn count_open(storage: &mut dyn Storage, source: &Addr, opened: bool) -> StdResult<()> {
+    let open = open_escrows_of(storage, source)?;
+    match (opened, open) {
+        (true, open) => OPEN_BY_SOURCE.save(storage, source, &(open + 1)),
+        (false, 0 | 1) => {
+            OPEN_BY_SOURCE.remove(storage, source);
+            Ok(())
+        }
+        (false, open) => OPEN_BY_SOURCE.save(storage, source, &(open - 1)),
+    }
+}
+
+fn update_counts(storage: &mut dyn Storage, action: impl FnOnce(&mut EscrowCounts)) -> StdResult<()> {
+    let mut counts = ESCROW_COUNTS.may_load(storage)?.unwrap_or_default();
+    action(&mut counts);
+    ESCROW_COUNTS.save(storage, &counts)
+}
+
+/// The whole balance of an active escrow, every kind of token ordered by its key
+pub fn escrow_balance_read(storage: &dyn Storage, id: &str) -> StdResult<GenericBalance> {
+    let mut balance = GenericBalance::default();
+    for entry in ESCROW_BALANCES.prefix(id).range(storage, None, None, Order::Ascending) {
+        let (key, amount) = entry?;
+        push_balance_entry(&mut balance, &key, amount)?;
+    }
+    Ok(balance)
+}
+
+fn escrow_balance_save(storage: &mut dyn Storage, id: &str, balance: &GenericBalance) -> StdResult<()> {
+    escrow_balance_remove(storage, id)?;
+    for (key, amount) in balance_entries(balance) {
+        ESCROW_BALANCES.save(storage, (id, &key), &amount)?;
+        lock(storage, id, &key, amount)?;
+    }
+    Ok(())
+}
+
+fn escrow_balance_remove(storage: &mut dyn Storage, id: &str) -> StdResult<()> {
+    let entries: Vec<(String, Uint128)> = ESCROW_BALANCES
+        .prefix(id)
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for (key, amount) in entries {
+        ESCROW_BALANCES.remove(storage, (id, &key));
+        unlock(storage, id, &key, amount)?;
+    }
+    Ok(())
+}
+
+/// Adds a deposit to the stored balance, touching only the deposited tokens.
+/// Returns their new totals and whether any of them was not held before.
+pub fn escrow_balance_add(
+    storage: &mut dyn Storage,
+    id: &str,
+    deposit: &GenericBalance,
+) -> StdResult<(GenericBalance, bool)> {
+    let mut totals = GenericBalance::default();
+    let mut new_token = false;
+    for (key, amount) in balance_entries(deposit) {
+        let total = ESCROW_BALANCES.update(storage, (id, &key), |held| -> StdResult<_> {
+            match held {
+                Some(held) => Ok(held.checked_add(amount)?),
+                None => {
+                    new_token = true;
+                    Ok(amount)
+                }
+            }
+        })?;
+        lock(storage, id, &key, amount)?;
+        push_balance_entry(&mut totals, &key, total)?;
+    }
+    Ok((totals, new_token))
+}
+
+/// Key of an escrow created with `namespaced`. Ids cannot contain a '/', so these
+/// never collide with global ids
+pub fn namespaced_id(creator: &Addr, id: &str) -> String {
+    format!("{}/{}", creator, id)
+}
+
+/// Loads an active escrow together with its balance
+pub fn escrows_read(storage: &dyn Storage, id: &str) -> StdResult<Escrow> {
+    let mut escrow = escrows_read_without_balance(storage, id)?;
+    // entries that were not migrated yet still hold their balance inline
+    if escrow.balance.is_empty() {
+        escrow.balance = escrow_balance_read(storage, id)?;
+    }
+    Ok(escrow)
+}
+
+/// Loads an active escrow, leaving its balance empty
+pub fn escrows_read_without_balance(storage: &dyn Storage, id: &str) -> StdResult<Escrow> {
+    escrows().load(storage, id)
 }
 
 pub fn escrows_save(
     storage: &mut dyn Storage, 
     escrow: &Escrow,
-    id: &String
+    id: &str
 ) -> StdResult<()> {
-    bucket(storage, PREFIX_ESCROW).save(id.as_bytes(), escrow)
+    escrows().save(storage, id, escrow)?;
+    escrow_balance_save(storage, id, &escrow.balance)
 }
 
 pub fn escrows_update(
     storage: &mut dyn Storage,
     escrow: Escrow,
-    id: &String
+    id: &str
 ) ->  Result<Escrow, ContractError> {
-    bucket(storage, PREFIX_ESCROW).update(id.as_bytes(), | existing | match existing {
+    // ids of settled escrows cannot be reused either
+    if archive().may_load(storage, id)?.is_some() {
+        return Err(ContractError::IdAlreadyExists { id: id.to_string() });
+    }
+    let escrow = escrows().update(storage, id, | existing | match existing {
         None => Ok(escrow),
-        Some(_) => Err(ContractError::IdAlreadyExists{}),
-    })
+        Some(_) => Err(ContractError::IdAlreadyExists { id: id.to_string() }),
+    })?;
+    escrow_balance_save(storage, id, &escrow.balance)?;
+    index_arbiters(storage, &escrow, id)?;
+    update_counts(storage, |counts| counts.active += 1)?;
+    count_open(storage, &escrow.source, true)?;
+    Ok(escrow)
 }
 
 pub fn escrows_remove(
     storage: &mut dyn Storage,
-    id: &String,
+    id: &str,
 ) -> StdResult<()> {
-    prefixed(storage, PREFIX_ESCROW).remove(id.as_bytes());
-    Ok(())
+    escrows().remove(storage, id)?;
+    escrow_balance_remove(storage, id)?;
+    update_counts(storage, |counts| counts.active = counts.active.saturating_sub(1))
 }
 
+/// Moves a settled escrow from the active map into the archive
+pub fn archive_escrow(
+    storage: &mut dyn Storage,
+    escrow: &Escrow,
+    id: &str,
+) -> StdResult<()> {
+    escrows_remove(storage, id)?;
+    for arbiter in escrow.arbiters.iter() {
+        ARBITER_ESCROWS.remove(storage, (arbiter, id));
+        VOTES.remove(storage, (id, arbiter));
+    }
+    count_open(storage, &escrow.source, false)?;
+    update_counts(storage, |counts| {
+        if let Some(count) = counts.settled(escrow.status) {
+            *count += 1;
+        }
+    })?;
+    index_archived(storage, escrow, id)?;
+    archive().save(storage, id, escrow)
+}
+
+pub fn archived_escrows_may_load(storage: &dyn Storage, id: &str) -> StdResult<Option<Escrow>> {
+    archive().may_load(storage, id)
+}
+
+/// Where a migration done in steps stands, see `MigrateMsg::limit`. Only stored
+/// while one is in progress
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct MigrationProgress {
+    /// Whether `ESCROW_COUNTS`, `TOTAL_LOCKED` and `TOKEN_ESCROWS` are built along the way,
+    /// for deployments from before they were kept
+    pub rebuild_stats: bool,
+    /// Whether `ARCHIVED_BY_ARBITER` is built along the way, for archives from before it
+    /// was kept
+    #[serde(default)]
+    pub index_archive: bool,
+    /// Last archived escrow counted, archived escrows go first
+    pub archive_cursor: Option<String>,
+    pub archive_done: bool,
+    /// Last active escrow migrated
+    pub escrow_cursor: Option<String>,
+}
+
+pub const MIGRATION: Item<MigrationProgress> = Item::new("migration");
+
+/// The last expired escrow settling went through, the next call continues after it
+pub const EXPIRY_CURSOR: Item<(Expiration, String)> = Item::new("expiry_cursor");
+
+/// The deadlines escrows stored before `Escrow::expires`, read from the same entries
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+struct LegacyDeadlines {
+    #[serde(default)]
+    end_height: Option<u64>,
+    /// compared against block time in nanos / 1000
+    #[serde(default)]
+    end_time: Option<u64>,
+}
+
+const LEGACY_DEADLINES: Map<&str, LegacyDeadlines> = Map::new(PREFIX_ESCROW);
+
+impl LegacyDeadlines {
+    /// The `Expiration` expiring exactly when the legacy deadlines did. With both set,
+    /// the one that passed. None while neither did, such escrows expired at whichever
+    /// came first, which no single `Expiration` can say
+    fn into_expiration(self, block: &BlockInfo) -> Option<Expiration> {
+        let at_height = self.end_height.map(|height| Expiration::AtHeight(height + 1));
+        let at_time = self
+            .end_time
+            .map(|time| Expiration::AtTime(Timestamp::from_nanos(time * 1000 + 1)));
+        match (at_height, at_time) {
+            (Some(height), _) if height.is_expired(block) => Some(height),
+            (Some(_), Some(time)) => time.is_expired(block).then_some(time),
+            (Some(expires), None) | (None, Some(expires)) => Some(expires),
+            (None, None) => Some(Expiration::Never {}),
+        }
+    }
+}
+
+/// Legacy escrows with both deadlines set and neither passed as of `block`, they cannot be
+/// migrated without changing when they expire
+pub fn ambiguous_legacy_deadlines(storage: &dyn Storage, block: &BlockInfo) -> StdResult<Vec<String>> {
+    let mut ids = vec![];
+    for entry in LEGACY_DEADLINES.range(storage, None, None, Order::Ascending) {
+        let (id, deadlines) = entry?;
+        if deadlines.into_expiration(block).is_none() {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+impl MigrationProgress {
+    pub fn start(storage: &mut dyn Storage) -> StdResult<Self> {
+        let rebuild_stats = ESCROW_COUNTS.may_load(storage)?.is_none();
+        if rebuild_stats {
+            ESCROW_COUNTS.save(storage, &EscrowCounts::default())?;
+        }
+        let index_archive = archive_unindexed(storage);
+        Ok(MigrationProgress {
+            rebuild_stats,
+            index_archive,
+            // nothing to count or index otherwise
+            archive_done: !(rebuild_stats || index_archive),
+            ..MigrationProgress::default()
+        })
+    }
+}
+
+/// Counts up to `limit` archived escrows into `ESCROW_COUNTS` and `ARCHIVED_BY_ARBITER`,
+/// those that are rebuilt. Returns how many
+pub fn count_archived_escrows(
+    storage: &mut dyn Storage,
+    progress: &mut MigrationProgress,
+    limit: usize,
+) -> StdResult<usize> {
+    let start = progress.archive_cursor.clone().map(Bound::exclusive);
+    let page: Vec<(String, Escrow)> = archive()
+        .range(storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<_>>()?;
+
+    for (id, escrow) in page.iter() {
+        if progress.rebuild_stats {
+            update_counts(storage, |counts| {
+                if let Some(count) = counts.settled(escrow.status) {
+                    *count += 1;
+                }
+            })?;
+        }
+        if progress.index_archive {
+            index_archived(storage, escrow, id)?;
+        }
+    }
+    progress.archive_done = page.len() < limit;
+    if let Some((id, _)) = page.last() {
+        progress.archive_cursor = Some(id.clone());
+    }
+    Ok(page.len())
+}
+
+/// Re-saves up to `limit` active escrows so that the secondary indexes (and the arbiter
+/// map) get populated, returns how many and how many of them were archived.
+/// Entries written by the legacy `bucket` storage live under the same `liability`
+/// namespace and key layout, but were never indexed. Balances still stored inline
+/// move to `ESCROW_BALANCES`, escrows settled before the archive existed move there
+/// and legacy deadlines become `expires`.
+pub fn migrate_escrows(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    progress: &mut MigrationProgress,
+    limit: usize,
+) -> StdResult<(usize, usize)> {
+    let start = progress.escrow_cursor.clone().map(Bound::exclusive);
+    let mut page: Vec<(String, Escrow)> = escrows()
+        .range(storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<_>>()?;
+
+    let mut archived = 0;
+    for (id, escrow) in page.iter_mut() {
+        if matches!(escrow.expires, Expiration::Never {}) {
+            escrow.expires = LEGACY_DEADLINES
+                .load(storage, id)?
+                .into_expiration(block)
+                .ok_or_else(|| StdError::generic_err(format!("Escrow {} has two deadlines", id)))?;
+        }
+        escrows().save(storage, id, escrow)?;
+        if progress.rebuild_stats {
+            update_counts(storage, |counts| counts.active += 1)?;
+            count_open(storage, &escrow.source, true)?;
+            let held: Vec<(String, Uint128)> = ESCROW_BALANCES
+                .prefix(id)
+                .range(storage, None, None, Order::Ascending)
+                .collect::<StdResult<_>>()?;
+            for (key, amount) in held {
+                lock(storage, id, &key, amount)?;
+            }
+        }
+        if !escrow.balance.is_empty() {
+            escrow_balance_save(storage, id, &escrow.balance)?;
+        }
+        index_arbiters(storage, escrow, id)?;
+        if escrow.status.is_settled() {
+            archive_escrow(storage, escrow, id)?;
+            archived += 1;
+        }
+    }
+    if let Some((id, _)) = page.last() {
+        progress.escrow_cursor = Some(id.clone());
+    }
+    Ok((page.len(), archived))
+}
+
+/// Basis points are parts per 10,000
+pub const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Token id of a cw721 NFT
+pub type TokenId = String;
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug, Default)]
 pub struct GenericBalance {
     pub native: Vec<Coin>,
     pub cw20: Vec<Cw20CoinVerified>,
+    /// cw721 NFTs as (contract, token id)
+    #[serde(default)]
+    pub nfts: Vec<(Addr, TokenId)>,
+}
+
+/// Tokens received in a single deposit. Native coins sent with zero amount are dropped
+impl From<Balance> for GenericBalance {
+    fn from(balance: Balance) -> Self {
+        match balance {
+            Balance::Native(balance) => GenericBalance {
+                native: balance.0.into_iter().filter(|c| !c.amount.is_zero()).collect(),
+                ..GenericBalance::default()
+            },
+            Balance::Cw20(token) => GenericBalance {
+                cw20: vec![token],
+                ..GenericBalance::default()
+            },
+        }
+    }
+}
+
+/// Comma separated, native coins as `100uatom`, cw20 tokens as `100<contract>` and
+/// NFTs as `<contract>/<token id>`
+impl fmt::Display for GenericBalance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let native = self.native.iter().map(|c| c.to_string());
+        let cw20 = self.cw20.iter().map(|c| format!("{}{}", c.amount, c.address));
+        let nfts = self.nfts.iter().map(|(contract, id)| format!("{}/{}", contract, id));
+        f.write_str(&native.chain(cw20).chain(nfts).collect::<Vec<_>>().join(","))
+    }
 }
 
 impl GenericBalance {
-    pub fn add_tokens(&mut self, add: Balance) {
+    /// Removes `bps` basis points of every token from this balance and returns them.
+    /// Amounts are rounded down and tokens that end up at zero are dropped from both sides.
+    /// NFTs cannot be split and always stay in this balance.
+    pub fn split_bps(&mut self, bps: u16) -> GenericBalance {
+        let mut taken = GenericBalance::default();
+        for coin in self.native.iter_mut() {
+            let amount = coin.amount.multiply_ratio(bps, BPS_DENOMINATOR);
+            if !amount.is_zero() {
+                coin.amount -= amount;
+                taken.native.push(Coin {
+                    denom: coin.denom.clone(),
+                    amount,
+                });
+            }
+        }
+        for token in self.cw20.iter_mut() {
+            let amount = token.amount.multiply_ratio(bps, BPS_DENOMINATOR);
+            if !amount.is_zero() {
+                token.amount -= amount;
+                taken.cw20.push(Cw20CoinVerified {
+                    address: token.address.clone(),
+                    amount,
+                });
+            }
+        }
+        self.native.retain(|c| !c.amount.is_zero());
+        self.cw20.retain(|c| !c.amount.is_zero());
+        taken
+    }
+
+    /// Whether this balance holds at least the amounts of every token in `other`
+    pub fn covers(&self, other: &GenericBalance) -> bool {
+        other.native.iter().all(|want| {
+            self.native
+                .iter()
+                .any(|have| have.denom == want.denom && have.amount >= want.amount)
+        }) && other.cw20.iter().all(|want| {
+            self.cw20
+                .iter()
+                .any(|have| have.address == want.address && have.amount >= want.amount)
+        }) && other.nfts.iter().all(|want| self.nfts.contains(want))
+    }
+
+    /// What this balance lacks to cover `target`
+    pub fn shortfall(&self, target: &GenericBalance) -> GenericBalance {
+        let mut missing = GenericBalance::default();
+        for want in target.native.iter() {
+            let have = self.native.iter().find(|c| c.denom == want.denom);
+            let amount = want.amount.saturating_sub(have.map(|c| c.amount).unwrap_or_default());
+            if !amount.is_zero() {
+                missing.native.push(Coin {
+                    denom: want.denom.clone(),
+                    amount,
+                });
+            }
+        }
+        for want in target.cw20.iter() {
+            let have = self.cw20.iter().find(|c| c.address == want.address);
+            let amount = want.amount.saturating_sub(have.map(|c| c.amount).unwrap_or_default());
+            if !amount.is_zero() {
+                missing.cw20.push(Cw20CoinVerified {
+                    address: want.address.clone(),
+                    amount,
+                });
+            }
+        }
+        missing.nfts = target.nfts.iter().filter(|nft| !self.nfts.contains(nft)).cloned().collect();
+        missing
+    }
+
+    /// All that is held of the named native denoms, cw20 contracts and cw721 contracts
+    pub fn of_tokens(&self, tokens: &[String]) -> Result<GenericBalance, ContractError> {
+        let mut selected = GenericBalance::default();
+        for token in tokens {
+            let native = self.native.iter().filter(|coin| coin.denom == *token);
+            let cw20 = self.cw20.iter().filter(|coin| coin.address == *token);
+            let nfts = self.nfts.iter().filter(|(contract, _)| *contract == *token);
+            let found = selected.num_tokens();
+            selected.native.extend(native.cloned());
+            selected.cw20.extend(cw20.cloned());
+            selected.nfts.extend(nfts.cloned());
+            if selected.num_tokens() == found {
+                return Err(ContractError::TokenNotHeld { token: token.clone() });
+            }
+        }
+        Ok(selected)
+    }
+
+    /// Number of different native denoms, cw20 tokens and NFTs held
+    pub fn num_tokens(&self) -> usize {
+        self.native.len() + self.cw20.len() + self.nfts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.native.is_empty() && self.cw20.is_empty() && self.nfts.is_empty()
+    }
+
+    pub fn add_tokens(&mut self, add: Balance) -> StdResult<()> {
         match add {
             Balance::Native(balance) => {
                 for token in balance.0 {
@@ -86,7 +1698,9 @@ impl GenericBalance {
                         }
                     });
                     match index {
-                        Some(idx) => self.native[idx].amount += token.amount,
+                        Some(idx) => {
+                            self.native[idx].amount = self.native[idx].amount.checked_add(token.amount)?
+                        }
                         None => self.native.push(token),
                     }
                 }
@@ -100,25 +1714,329 @@ impl GenericBalance {
                     }
                 });
                 match index {
-                    Some(idx) => self.cw20[idx].amount += token.amount,
+                    Some(idx) => self.cw20[idx].amount = self.cw20[idx].amount.checked_add(token.amount)?,
                     None => self.cw20.push(token),
                 }
             }
         };
+        Ok(())
     }
+
+    /// Adds all tokens of `other` to this balance
+    pub fn add_all(&mut self, other: &GenericBalance) -> StdResult<()> {
+        for token in other.native.iter() {
+            match self.native.iter_mut().find(|exist| exist.denom == token.denom) {
+                Some(exist) => exist.amount = exist.amount.checked_add(token.amount)?,
+                None => self.native.push(token.clone()),
+            }
+        }
+        for token in other.cw20.iter() {
+            match self.cw20.iter_mut().find(|exist| exist.address == token.address) {
+                Some(exist) => exist.amount = exist.amount.checked_add(token.amount)?,
+                None => self.cw20.push(token.clone()),
+            }
+        }
+        for nft in other.nfts.iter() {
+            if !self.nfts.contains(nft) {
+                self.nfts.push(nft.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the given tokens from this balance. Tokens that reach zero are dropped.
+    /// Fails if any of them is not held in a sufficient amount.
+    pub fn sub_tokens(&mut self, sub: &GenericBalance) -> Result<(), ContractError> {
+        for token in sub.native.iter() {
+            let exist = self.native.iter_mut().find(|exist| exist.denom == token.denom);
+            match exist {
+                Some(exist) if exist.amount >= token.amount => exist.amount -= token.amount,
+                exist => {
+                    return Err(ContractError::InsufficientBalance {
+                        token: token.denom.clone(),
+                        available: exist.map(|exist| exist.amount).unwrap_or_default(),
+                        requested: token.amount,
+                    })
+                }
+            }
+        }
+        for token in sub.cw20.iter() {
+            let exist = self.cw20.iter_mut().find(|exist| exist.address == token.address);
+            match exist {
+                Some(exist) if exist.amount >= token.amount => exist.amount -= token.amount,
+                exist => {
+                    return Err(ContractError::InsufficientBalance {
+                        token: token.address.to_string(),
+                        available: exist.map(|exist| exist.amount).unwrap_or_default(),
+                        requested: token.amount,
+                    })
+                }
+            }
+        }
+        for nft in sub.nfts.iter() {
+            match self.nfts.iter().position(|exist| exist == nft) {
+                Some(index) => {
+                    self.nfts.remove(index);
+                }
+                None => {
+                    return Err(ContractError::InsufficientBalance {
+                        token: format!("{}/{}", nft.0, nft.1),
+                        available: Uint128::zero(),
+                        requested: Uint128::one(),
+                    })
+                }
+            }
+        }
+        self.native.retain(|c| !c.amount.is_zero());
+        self.cw20.retain(|c| !c.amount.is_zero());
+        Ok(())
+    }
+}
+
+
+/// Returns the ids of stored escrows in ascending order, starting right after
+/// `start_after` (if set) and returning at most `limit` entries.
+pub fn all_escrow_ids(
+    storage: &dyn Storage,
+    start_after: Option<String>,
+    limit: usize,
+) -> StdResult<Vec<String>> {
+    let start = start_after.map(Bound::exclusive);
+
+    escrows()
+        .range(storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|elem| elem.map(|(id, _)| id))
+        .collect()
 }
 
+/// Same as `all_escrow_ids`, but only returns escrows handled by the given arbiter.
+pub fn escrow_ids_by_arbiter(
+    storage: &dyn Storage,
+    arbiter: &Addr,
+    start_after: Option<String>,
+    limit: usize,
+) -> StdResult<Vec<String>> {
+    let start = start_after.map(Bound::exclusive);
+
+    ARBITER_ESCROWS
+        .prefix(arbiter)
+        .range(storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|elem| elem.map(|(id, _)| id))
+        .collect()
+}
 
-// pub fn all_escrow_ids(
-//     storage: &dyn Storage,
-// )  -> Result<Vec<String>, ContractError> {
-//     let escrow_bucket: ReadonlyBucket<String> = bucket_read(storage, PREFIX_ESCROW);
+/// Active escrows expired as of `block`, those expiring at a height first, each kind
+/// of deadline in ascending order. Continues after the escrow `start_after` expiring at
+/// `expires`, which does not have to be active anymore
+pub fn expired_escrow_ids<'a>(
+    storage: &'a dyn Storage,
+    block: &BlockInfo,
+    start_after: Option<(&Expiration, &str)>,
+) -> impl Iterator<Item = StdResult<String>> + 'a {
+    let by_height = match start_after {
+        // past every escrow expiring at a height
+        Some((Expiration::AtTime(_) | Expiration::Never {}, _)) => Box::new(std::iter::empty()),
+        _ => {
+            let after = start_after.map(|(expires, id)| (Deadline::Height.key(expires), id));
+            escrows_by_deadline(storage, Deadline::Height, None, after, block.height + 1)
+        }
+    };
+    let after = start_after
+        .filter(|(expires, _)| !matches!(expires, Expiration::AtHeight(_)))
+        .map(|(expires, id)| (Deadline::Time.key(expires), id));
+    let by_time = escrows_by_deadline(storage, Deadline::Time, None, after, block.time.nanos() + 1);
+    by_height.chain(by_time).map(|elem| elem.map(|(id, _)| id))
+}
+
+/// Which of the deadline indexes to go through
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Deadline {
+    Height,
+    Time,
+}
 
-//     escrow_bucket    
-//         .range(None, None, Order::Ascending)
-//         .map(| elem| {
-//             let (k, _) = elem?;
-//             Ok(String::from_utf8(k).unwrap())
-//         })
-//         .collect()
-// }
\ No newline at end of file
+impl Deadline {
+    /// Where an escrow expiring at `expires` is in this index, escrows without this kind
+    /// of deadline are at u64::MAX
+    pub fn key(self, expires: &Expiration) -> u64 {
+        match (self, expires) {
+            (Deadline::Height, Expiration::AtHeight(height)) => *height,
+            (Deadline::Time, Expiration::AtTime(time)) => time.nanos(),
+            _ => u64::MAX,
+        }
+    }
+}
+
+/// Active escrows expiring at a height, or a time in nanoseconds, from `from` (inclusive)
+/// to `to` (exclusive), in ascending order of it. With `start_after`, only those after
+/// the escrow with that deadline and id
+pub fn escrows_by_deadline<'a>(
+    storage: &'a dyn Storage,
+    deadline: Deadline,
+    from: Option<u64>,
+    start_after: Option<(u64, &str)>,
+    to: u64,
+) -> Box<dyn Iterator<Item = StdResult<(String, Escrow)>> + 'a> {
+    // index keys are the length prefixed deadline followed by the id, so these bounds take
+    // in every escrow at or after `from`, or after the cursor, and exclude every escrow at
+    // or after `to`
+    let min = match (from, start_after) {
+        (Some(from), Some((key, _))) if key < from => Some(Bound::inclusive(from.joined_prefix())),
+        (_, Some((key, id))) => Some(Bound::exclusive(key.joined_extra_key(id.as_bytes()))),
+        (from, None) => from.map(|from| Bound::inclusive(from.joined_prefix())),
+    };
+    let max = Some(Bound::exclusive(to.joined_prefix()));
+    match deadline {
+        Deadline::Height => escrows().idx.expires_height.range(storage, min, max, Order::Ascending),
+        Deadline::Time => escrows().idx.expires_time.range(storage, min, max, Order::Ascending),
+    }
+}
+
+/// Same as `all_escrow_ids`, but only returns escrows in the given status.
+pub fn escrow_ids_by_status(
+    storage: &dyn Storage,
+    status: Status,
+    start_after: Option<String>,
+    limit: usize,
+) -> StdResult<Vec<String>> {
+    let start = start_after.map(Bound::exclusive);
+
+    escrows()
+        .idx
+        .status
+        .prefix(status.to_string())
+        .range(storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|elem| elem.map(|(id, _)| id))
+        .collect()
+}
+
+/// Same as `all_escrow_ids`, but only returns escrows holding the given native denom or
+/// cw20 token. A cw20 address wins over a denom of the same name
+pub fn escrow_ids_by_token(
+    storage: &dyn Storage,
+    denom_or_cw20: &str,
+    start_after: Option<String>,
+    limit: usize,
+) -> StdResult<Vec<String>> {
+    let cw20 = format!("cw20:{}", denom_or_cw20);
+    let held_as_cw20 = TOKEN_ESCROWS
+        .prefix(&cw20)
+        .keys_raw(storage, None, None, Order::Ascending)
+        .next()
+        .is_some();
+    let key = if held_as_cw20 {
+        cw20
+    } else {
+        format!("native:{}", denom_or_cw20)
+    };
+    let start = start_after.map(Bound::exclusive);
+
+    TOKEN_ESCROWS
+        .prefix(&key)
+        .keys(storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect()
+}
+
+// active escrows, or archived ones for a settled status
+fn escrows_in_status(status: Status) -> Prefix<String, Escrow> {
+    if status.is_settled() {
+        archive().idx.status.prefix(status.to_string())
+    } else {
+        escrows().idx.status.prefix(status.to_string())
+    }
+}
+
+/// Ids of the escrows handled by `arbiter` in the given status. Settled ones come from
+/// their own index, for the others only the active escrows of the arbiter are checked
+fn arbiter_escrows_in_status<'a>(
+    storage: &'a dyn Storage,
+    status: Status,
+    arbiter: &Addr,
+    start_after: Option<String>,
+) -> Box<dyn Iterator<Item = StdResult<String>> + 'a> {
+    let start = start_after.map(Bound::exclusive);
+    if status.is_settled() {
+        let ids = ARCHIVED_BY_ARBITER
+            .prefix((arbiter, status.as_str()))
+            .range(storage, start, None, Order::Ascending)
+            .map(|elem| elem.map(|(id, _)| id));
+        return Box::new(ids);
+    }
+    let ids = ARBITER_ESCROWS
+        .prefix(arbiter)
+        .range(storage, start, None, Order::Ascending)
+        .filter_map(move |elem| {
+            let id = match elem {
+                Ok((id, _)) => id,
+                Err(err) => return Some(Err(err)),
+            };
+            match escrows_read_without_balance(storage, &id) {
+                Ok(escrow) if escrow.status == status => Some(Ok(id)),
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            }
+        });
+    Box::new(ids)
+}
+
+/// Escrows in the given status (archived ones for a settled status) that are handled
+/// by `arbiter`, paged like `all_escrow_ids`
+pub fn escrow_ids_by_status_and_arbiter(
+    storage: &dyn Storage,
+    status: Status,
+    arbiter: &Addr,
+    start_after: Option<String>,
+    limit: usize,
+) -> StdResult<Vec<String>> {
+    arbiter_escrows_in_status(storage, status, arbiter, start_after)
+        .take(limit)
+        .collect()
+}
+
+/// Number of escrows `List` and `ListByArbiter` go through with these filters, that is
+/// active escrows unless `status` is a settled one
+pub fn count_escrows(storage: &dyn Storage, status: Option<Status>, arbiter: Option<&Addr>) -> StdResult<u64> {
+    let count = match (status, arbiter) {
+        (None, None) => return Ok(ESCROW_COUNTS.may_load(storage)?.unwrap_or_default().active),
+        (None, Some(arbiter)) => ARBITER_ESCROWS
+            .prefix(arbiter)
+            .keys_raw(storage, None, None, Order::Ascending)
+            .count(),
+        (Some(status), None) => escrows_in_status(status)
+            .keys_raw(storage, None, None, Order::Ascending)
+            .count(),
+        (Some(status), Some(arbiter)) => {
+            let mut count = 0;
+            for id in arbiter_escrows_in_status(storage, status, arbiter, None) {
+                id?;
+                count += 1;
+            }
+            count
+        }
+    };
+    Ok(count as u64)
+}
+
+/// Same as `all_escrow_ids` for the archive, optionally only in the given status.
+pub fn archived_escrow_ids(
+    storage: &dyn Storage,
+    status: Option<Status>,
+    start_after: Option<String>,
+    limit: usize,
+) -> StdResult<Vec<String>> {
+    let start = start_after.map(Bound::exclusive);
+
+    let ids = match status {
+        Some(status) => archive()
+            .idx
+            .status
+            .prefix(status.to_string())
+            .range(storage, start, None, Order::Ascending),
+        None => archive().range(storage, start, None, Order::Ascending),
+    };
+    ids.take(limit).map(|elem| elem.map(|(id, _)| id)).collect()
+}
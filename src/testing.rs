@@ -0,0 +1,116 @@
+//! cw-multi-test helpers for contracts integrating with the escrow. Enable the `testing`
+//! feature to use them in your own integration tests:
+//!
+//! ```ignore
+//! let escrow = EscrowContract::store_and_instantiate(&mut app, &admin, &InstantiateMsg::default())?;
+//! escrow.create(&mut app, &buyer, create_msg, &coins(100, "ujuno"))?;
+//! escrow.approve(&mut app, &arbiter, "deal")?;
+//! assert_eq!(escrow.details(&app, "deal")?.status, Status::Approved);
+//! ```
+use anyhow::Result as AnyResult;
+use cosmwasm_std::{to_binary, Addr, Coin, Empty, StdResult, Uint128};
+use cw20::Cw20ExecuteMsg;
+use cw_multi_test::{App, AppResponse, Contract, ContractWrapper, Executor};
+
+use crate::msg::{CreateMsg, DetailsResponse, ExecuteMsg, InstantiateMsg, QueryMsg, ReceiveMsg};
+
+/// The escrow with all its entry points, to store in an `App`
+pub fn escrow_contract() -> Box<dyn Contract<Empty>> {
+    Box::new(
+        ContractWrapper::new(
+            crate::contract::execute,
+            crate::contract::instantiate,
+            crate::contract::query,
+        )
+        .with_reply(crate::contract::reply)
+        .with_sudo(crate::contract::sudo)
+        .with_migrate(crate::contract::migrate),
+    )
+}
+
+/// An instantiated escrow contract in an `App`
+#[derive(Clone, Debug, PartialEq)]
+pub struct EscrowContract(pub Addr);
+
+impl EscrowContract {
+    pub fn addr(&self) -> &Addr {
+        &self.0
+    }
+
+    /// Stores the escrow code and instantiates it as `sender`
+    pub fn store_and_instantiate(app: &mut App, sender: &Addr, msg: &InstantiateMsg) -> AnyResult<Self> {
+        let code_id = app.store_code(escrow_contract());
+        Self::instantiate(app, code_id, sender, msg)
+    }
+
+    pub fn instantiate(app: &mut App, code_id: u64, sender: &Addr, msg: &InstantiateMsg) -> AnyResult<Self> {
+        let addr = app.instantiate_contract(code_id, sender.clone(), msg, &[], "escrow", Some(sender.to_string()))?;
+        Ok(EscrowContract(addr))
+    }
+
+    pub fn execute(&self, app: &mut App, sender: &Addr, msg: &ExecuteMsg, funds: &[Coin]) -> AnyResult<AppResponse> {
+        app.execute_contract(sender.clone(), self.0.clone(), msg, funds)
+    }
+
+    /// Creates an escrow funded with native tokens
+    pub fn create(&self, app: &mut App, sender: &Addr, msg: CreateMsg, funds: &[Coin]) -> AnyResult<AppResponse> {
+        self.execute(app, sender, &ExecuteMsg::Create(msg), funds)
+    }
+
+    /// Creates an escrow funded with `amount` of the cw20 `token`, sent by `sender`
+    pub fn create_cw20(
+        &self,
+        app: &mut App,
+        sender: &Addr,
+        msg: CreateMsg,
+        token: &Addr,
+        amount: Uint128,
+    ) -> AnyResult<AppResponse> {
+        self.send_cw20(app, sender, token, amount, &ReceiveMsg::Create(msg))
+    }
+
+    pub fn top_up(&self, app: &mut App, sender: &Addr, id: &str, funds: &[Coin]) -> AnyResult<AppResponse> {
+        self.execute(app, sender, &ExecuteMsg::TopUp { id: id.to_string() }, funds)
+    }
+
+    /// Adds `amount` of the cw20 `token`, sent by `sender`, to the escrow
+    pub fn top_up_cw20(
+        &self,
+        app: &mut App,
+        sender: &Addr,
+        id: &str,
+        token: &Addr,
+        amount: Uint128,
+    ) -> AnyResult<AppResponse> {
+        self.send_cw20(app, sender, token, amount, &ReceiveMsg::TopUp { id: id.to_string() })
+    }
+
+    pub fn approve(&self, app: &mut App, sender: &Addr, id: &str) -> AnyResult<AppResponse> {
+        self.execute(app, sender, &ExecuteMsg::Approve { id: id.to_string() }, &[])
+    }
+
+    pub fn refund(&self, app: &mut App, sender: &Addr, id: &str) -> AnyResult<AppResponse> {
+        self.execute(app, sender, &ExecuteMsg::Refund { id: id.to_string() }, &[])
+    }
+
+    pub fn details(&self, app: &App, id: &str) -> StdResult<DetailsResponse> {
+        app.wrap()
+            .query_wasm_smart(&self.0, &QueryMsg::Details { id: id.to_string() })
+    }
+
+    fn send_cw20(
+        &self,
+        app: &mut App,
+        sender: &Addr,
+        token: &Addr,
+        amount: Uint128,
+        msg: &ReceiveMsg,
+    ) -> AnyResult<AppResponse> {
+        let send = Cw20ExecuteMsg::Send {
+            contract: self.0.to_string(),
+            amount,
+            msg: to_binary(msg)?,
+        };
+        app.execute_contract(sender.clone(), token.clone(), &send, &[])
+    }
+}